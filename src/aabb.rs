@@ -0,0 +1,89 @@
+use crate::ray::Ray;
+use crate::vec3::{Float, Vec3};
+
+/// 轴对齐包围盒
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn min(&self) -> &Vec3 {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Vec3 {
+        &self.max
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// 包含两个包围盒的最小包围盒
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            max: Vec3::new(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        }
+    }
+
+    /// 最长的轴，0、1、2分别对应x、y、z
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// slab方法：射线在三个方向上进入和离开包围盒的区间取交集
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.hit_interval(ray, t_min, t_max).is_some()
+    }
+
+    /// 射线在`[t_min, t_max]`内穿过包围盒的区间
+    pub fn hit_interval(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inv_d = 1.0 / axis_of(ray.direction(), axis);
+            let mut t0 = (axis_of(&self.min, axis) - axis_of(ray.origin(), axis)) * inv_d;
+            let mut t1 = (axis_of(&self.max, axis) - axis_of(ray.origin(), axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+/// 按编号取向量的分量
+pub fn axis_of(v: &Vec3, axis: usize) -> Float {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
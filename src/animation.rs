@@ -0,0 +1,298 @@
+//! 关键帧动画：摄像机参数和物体变换都可以用关键帧描述，
+//! 配合射线的时间实现运动模糊
+
+use crate::aabb::Aabb;
+use crate::camera::{Camera, PerspectiveCamera};
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec3::{Float, Vec3};
+use std::ops::{Add, Mul, Sub};
+
+/// 从当前关键帧到下一个关键帧的插值方式
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    /// 保持当前值直到下一个关键帧
+    Constant,
+    Linear,
+    /// 三次贝塞尔曲线，控制点由相邻关键帧自动确定（Catmull-Rom），曲线经过每个关键帧且一阶导数连续
+    Bezier,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe<T> {
+    pub time: Float,
+    pub value: T,
+    pub interpolation: Interpolation,
+}
+
+/// 可以插值的值
+pub trait Animatable:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Float, Output = Self>
+{
+}
+
+impl<T> Animatable for T where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Float, Output = T> {}
+
+/// 一个参数随时间变化的关键帧序列，第一帧之前和最后一帧之后保持端点的值
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    /// 没有关键帧的轨道，取值前至少要添加一个关键帧
+    pub fn new() -> Self {
+        Track { keys: Vec::new() }
+    }
+
+    /// 不随时间变化的参数
+    pub fn constant(value: T) -> Self {
+        Self::new().key(0.0, value, Interpolation::Constant)
+    }
+
+    /// 添加关键帧，关键帧按时间排序，同一时刻的关键帧会被替换
+    pub fn key(mut self, time: Float, value: T, interpolation: Interpolation) -> Self {
+        let key = Keyframe {
+            time,
+            value,
+            interpolation,
+        };
+        match self
+            .keys
+            .binary_search_by(|k| k.time.partial_cmp(&time).unwrap())
+        {
+            Ok(i) => self.keys[i] = key,
+            Err(i) => self.keys.insert(i, key),
+        }
+        self
+    }
+
+    pub fn keys(&self) -> &[Keyframe<T>] {
+        &self.keys
+    }
+
+    /// 关键帧覆盖的时间范围
+    pub fn time_range(&self) -> (Float, Float) {
+        (self.keys[0].time, self.keys[self.keys.len() - 1].time)
+    }
+
+    pub fn sample(&self, time: Float) -> T {
+        assert!(!self.keys.is_empty(), "track has no keyframes");
+        let last = self.keys.len() - 1;
+        if time <= self.keys[0].time {
+            return self.keys[0].value;
+        }
+        if time >= self.keys[last].time {
+            return self.keys[last].value;
+        }
+        // 第一个时间大于`time`的关键帧
+        let i = self.keys.partition_point(|k| k.time <= time);
+        let (k0, k1) = (&self.keys[i - 1], &self.keys[i]);
+        let s = (time - k0.time) / (k1.time - k0.time);
+        match k0.interpolation {
+            Interpolation::Constant => k0.value,
+            Interpolation::Linear => k0.value + (k1.value - k0.value) * s,
+            Interpolation::Bezier => {
+                let prev = if i >= 2 {
+                    self.keys[i - 2].value
+                } else {
+                    k0.value
+                };
+                let next = if i < last {
+                    self.keys[i + 1].value
+                } else {
+                    k1.value
+                };
+                let p0 = k0.value;
+                let p1 = k0.value + (k1.value - prev) * (1.0 / 6.0);
+                let p2 = k1.value - (next - k0.value) * (1.0 / 6.0);
+                let p3 = k1.value;
+                let u = 1.0 - s;
+                p0 * (u * u * u)
+                    + p1 * (3.0 * u * u * s)
+                    + p2 * (3.0 * u * s * s)
+                    + p3 * (s * s * s)
+            }
+        }
+    }
+}
+
+/// 关键帧动画的透视摄像机，每条射线按它的时间取摄像机的参数，
+/// 因而快门打开期间摄像机的运动也会形成运动模糊
+pub struct AnimatedCamera {
+    pub lookfrom: Track<Vec3>,
+    pub lookat: Track<Vec3>,
+    /// 垂直视角，单位为度
+    pub vfov: Track<Float>,
+    pub focus_dist: Track<Float>,
+    pub vup: Vec3,
+    pub aspect_ratio: Float,
+    pub aperture: Float,
+}
+
+impl AnimatedCamera {
+    /// 某一时刻的摄像机
+    pub fn at(&self, time: Float) -> PerspectiveCamera {
+        PerspectiveCamera::new(
+            &self.lookfrom.sample(time),
+            &self.lookat.sample(time),
+            &self.vup,
+            self.vfov.sample(time),
+            self.aspect_ratio,
+            self.aperture,
+            self.focus_dist.sample(time),
+        )
+    }
+}
+
+impl Camera for AnimatedCamera {
+    /// 时刻0的射线
+    fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        self.get_ray_at(s, t, 0.0)
+    }
+
+    fn get_ray_at(&self, s: Float, t: Float, time: Float) -> Option<Ray> {
+        let ray = self.at(time).get_ray(s, t)?;
+        Some(Ray::with_time(*ray.origin(), *ray.direction(), time))
+    }
+}
+
+/// 用平移、欧拉角旋转（度）和缩放三条轨道描述的物体变换
+#[derive(Debug, Clone)]
+pub struct TransformAnimation {
+    pub translation: Track<Vec3>,
+    pub rotation: Track<Vec3>,
+    pub scale: Track<Vec3>,
+}
+
+impl Default for TransformAnimation {
+    fn default() -> Self {
+        TransformAnimation {
+            translation: Track::constant(Vec3::zero()),
+            rotation: Track::constant(Vec3::zero()),
+            scale: Track::constant(Vec3::new(1.0, 1.0, 1.0)),
+        }
+    }
+}
+
+impl TransformAnimation {
+    pub fn at(&self, time: Float) -> Transform {
+        Transform::from_trs(
+            self.translation.sample(time),
+            self.rotation.sample(time),
+            self.scale.sample(time),
+        )
+    }
+
+    fn time_range(&self) -> (Float, Float) {
+        [&self.translation, &self.rotation, &self.scale]
+            .iter()
+            .map(|track| track.time_range())
+            .fold((Float::MAX, Float::MIN), |(a, b), (c, d)| {
+                (a.min(c), b.max(d))
+            })
+    }
+}
+
+/// 随时间运动的物体，按射线的时间取变换，因而快门打开期间的运动会形成运动模糊
+pub struct Animated {
+    object: Box<dyn Hittable>,
+    animation: TransformAnimation,
+}
+
+impl Animated {
+    pub fn new(object: Box<dyn Hittable>, animation: TransformAnimation) -> Self {
+        Animated { object, animation }
+    }
+}
+
+/// 计算包围盒时在动画时间范围内取样的次数
+const BOUNDING_BOX_SAMPLES: usize = 64;
+
+impl Hittable for Animated {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let transform = self.animation.at(ray.time());
+        let local = transform.inverse_ray(ray);
+        self.object
+            .hit(&local, t_min, t_max)
+            .map(|h| transform.hit_record(h))
+    }
+
+    /// 在整个动画中取样求包围盒的并集，旋转的中间状态可能稍微超出，因此再略微放大
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = self.object.bounding_box()?;
+        let (start, end) = self.animation.time_range();
+        let mut result = self.animation.at(start).bounding_box(&bbox);
+        for i in 1..=BOUNDING_BOX_SAMPLES {
+            let time = start + (end - start) * i as Float / BOUNDING_BOX_SAMPLES as Float;
+            result = result.surrounding(&self.animation.at(time).bounding_box(&bbox));
+        }
+        let margin = 0.01 * (result.max() - result.min());
+        Some(Aabb::new(result.min() - margin, result.max() + margin))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_linear() {
+        let track =
+            Track::new()
+                .key(1.0, 10.0, Interpolation::Linear)
+                .key(0.0, 0.0, Interpolation::Linear);
+        assert_eq!(track.sample(-1.0), 0.0);
+        assert_eq!(track.sample(0.25), 2.5);
+        assert_eq!(track.sample(2.0), 10.0);
+    }
+
+    #[test]
+    fn test_bezier_passes_through_keys() {
+        let track = Track::new()
+            .key(0.0, 0.0, Interpolation::Bezier)
+            .key(1.0, 1.0, Interpolation::Bezier)
+            .key(2.0, 0.0, Interpolation::Bezier);
+        assert!((track.sample(1.0) - 1.0).abs() < 1e-6);
+        // 两段在关键帧处平滑衔接，峰值两侧对称
+        assert!((track.sample(0.9) - track.sample(1.1)).abs() < 1e-6);
+        assert!(track.sample(0.5) > 0.5);
+    }
+
+    #[test]
+    fn test_constant() {
+        let track = Track::new()
+            .key(0.0, Vec3::zero(), Interpolation::Constant)
+            .key(1.0, Vec3::new(1.0, 1.0, 1.0), Interpolation::Linear);
+        assert_eq!(track.sample(0.99), Vec3::zero());
+        assert_eq!(track.sample(1.0), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_camera_moves_with_ray_time() {
+        let camera = AnimatedCamera {
+            lookfrom: Track::new()
+                .key(0.0, Vec3::zero(), Interpolation::Linear)
+                .key(1.0, Vec3::new(2.0, 0.0, 0.0), Interpolation::Linear),
+            lookat: Track::constant(Vec3::new(0.0, 0.0, -1.0)),
+            vfov: Track::constant(40.0),
+            focus_dist: Track::constant(1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            aspect_ratio: 1.0,
+            aperture: 0.0,
+        };
+        // 同一次渲染里的射线按各自的时间取摄像机的位置
+        let ray = camera.get_ray_at(0.5, 0.5, 0.25).unwrap();
+        assert!((*ray.origin() - Vec3::new(0.5, 0.0, 0.0)).length() < 1e-5);
+        assert_eq!(ray.time(), 0.25);
+        let ray = camera.get_ray_at(0.5, 0.5, 0.75).unwrap();
+        assert!((*ray.origin() - Vec3::new(1.5, 0.0, 0.0)).length() < 1e-5);
+    }
+}
@@ -0,0 +1,376 @@
+//! 渲染通道（AOV, arbitrary output variables）
+//!
+//! 除最终颜色外，记录每条摄像机射线第一次碰撞的信息，
+//! 供降噪和后期合成使用，可以输出为一个多层的EXR文件。
+
+use crate::material::Material;
+use crate::vec3::{Float, Vec3};
+use exr::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// 一次采样的各个通道
+#[derive(Debug, Copy, Clone)]
+pub struct AovSample {
+    pub albedo: Vec3,
+    pub normal: Vec3,
+    /// 摄像机到交点的距离，未击中物体时为无穷大
+    pub depth: Float,
+    pub position: Vec3,
+    /// 材质的标识（`Rc`的地址），未击中物体时为0
+    pub material_key: usize,
+    /// 物体的编号，从1开始，未击中物体时为0
+    pub object_id: usize,
+    /// 直接光照：直接看到的光源和背景，以及只经过一次反射的光
+    pub direct: Vec3,
+    /// 间接光照：经过两次及以上反射的光
+    pub indirect: Vec3,
+}
+
+impl AovSample {
+    pub fn miss(background: Vec3) -> Self {
+        AovSample {
+            albedo: background,
+            normal: Vec3::zero(),
+            depth: Float::INFINITY,
+            position: Vec3::zero(),
+            material_key: 0,
+            object_id: 0,
+            direct: background,
+            indirect: Vec3::zero(),
+        }
+    }
+}
+
+/// 材质标识到连续编号的映射，编号按第一次出现的顺序从1开始分配
+#[derive(Default)]
+struct MaterialIds {
+    ids: HashMap<usize, usize>,
+}
+
+impl MaterialIds {
+    fn id(&mut self, key: usize) -> usize {
+        if key == 0 {
+            return 0;
+        }
+        let next = self.ids.len() + 1;
+        *self.ids.entry(key).or_insert(next)
+    }
+}
+
+/// 材质的标识，即`Rc`指向的地址
+pub fn material_key(material: &Rc<dyn Material>) -> usize {
+    Rc::as_ptr(material) as *const () as usize
+}
+
+/// 整幅图像的各个通道，颜色类通道取所有采样的平均，
+/// 编号类通道取每个像素的第一个采样
+pub struct AovBuffers {
+    width: usize,
+    height: usize,
+    albedo: Vec<Vec3>,
+    normal: Vec<Vec3>,
+    depth: Vec<Float>,
+    position: Vec<Vec3>,
+    direct: Vec<Vec3>,
+    indirect: Vec<Vec3>,
+    material_id: Vec<Option<usize>>,
+    object_id: Vec<Option<usize>>,
+    /// 每个像素的采样数
+    count: Vec<u32>,
+    /// 每个像素击中物体的采样数，用于平均深度和位置
+    hits: Vec<u32>,
+    material_ids: MaterialIds,
+}
+
+impl AovBuffers {
+    pub fn new(width: usize, height: usize) -> Self {
+        let size = width * height;
+        AovBuffers {
+            width,
+            height,
+            albedo: vec![Vec3::zero(); size],
+            normal: vec![Vec3::zero(); size],
+            depth: vec![0.0; size],
+            position: vec![Vec3::zero(); size],
+            direct: vec![Vec3::zero(); size],
+            indirect: vec![Vec3::zero(); size],
+            material_id: vec![None; size],
+            object_id: vec![None; size],
+            count: vec![0; size],
+            hits: vec![0; size],
+            material_ids: MaterialIds::default(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// 累加一次采样，深度和位置只对击中物体的采样取平均
+    pub fn add(&mut self, x: usize, y: usize, sample: &AovSample) {
+        let i = y * self.width + x;
+        self.albedo[i] += sample.albedo;
+        self.normal[i] += sample.normal;
+        self.direct[i] += sample.direct;
+        self.indirect[i] += sample.indirect;
+        self.count[i] += 1;
+        if sample.depth.is_finite() {
+            self.depth[i] += sample.depth;
+            self.position[i] += sample.position;
+            self.hits[i] += 1;
+        }
+        if self.object_id[i].is_none() {
+            self.object_id[i] = Some(sample.object_id);
+            self.material_id[i] = Some(self.material_ids.id(sample.material_key));
+        }
+    }
+
+    fn average(&self, buffer: &[Vec3], x: usize, y: usize) -> Vec3 {
+        let i = y * self.width + x;
+        buffer[i] / self.count[i].max(1) as Float
+    }
+
+    pub fn albedo(&self, x: usize, y: usize) -> Vec3 {
+        self.average(&self.albedo, x, y)
+    }
+
+    pub fn normal(&self, x: usize, y: usize) -> Vec3 {
+        let n = self.normal[y * self.width + x];
+        if n.squared_length() > 0.0 {
+            n.unit_vector()
+        } else {
+            n
+        }
+    }
+
+    pub fn depth(&self, x: usize, y: usize) -> Float {
+        let i = y * self.width + x;
+        if self.hits[i] == 0 {
+            Float::INFINITY
+        } else {
+            self.depth[i] / self.hits[i] as Float
+        }
+    }
+
+    pub fn position(&self, x: usize, y: usize) -> Vec3 {
+        let i = y * self.width + x;
+        self.position[i] / self.hits[i].max(1) as Float
+    }
+
+    pub fn direct(&self, x: usize, y: usize) -> Vec3 {
+        self.average(&self.direct, x, y)
+    }
+
+    pub fn indirect(&self, x: usize, y: usize) -> Vec3 {
+        self.average(&self.indirect, x, y)
+    }
+
+    pub fn material_id(&self, x: usize, y: usize) -> usize {
+        self.material_id[y * self.width + x].unwrap_or(0)
+    }
+
+    pub fn object_id(&self, x: usize, y: usize) -> usize {
+        self.object_id[y * self.width + x].unwrap_or(0)
+    }
+
+    pub fn sample_count(&self, x: usize, y: usize) -> u32 {
+        self.count[y * self.width + x]
+    }
+
+    /// 把最终颜色和所有通道写入一个多层的EXR文件
+    ///
+    /// 最终颜色为`R`、`G`、`B`，其余通道以`通道名.分量`命名，
+    /// 例如`albedo.R`、`normal.X`、`depth.Z`，大部分合成软件会据此分层。
+    /// 未击中物体的像素深度写为`f32::MAX`
+    pub fn save_exr<P: AsRef<Path>>(&self, colors: &[Vec3], path: P) -> exr::error::UnitResult {
+        let size = (self.width, self.height);
+        let pixels = self.width * self.height;
+        let scalar = |name: &str, value: &dyn Fn(usize, usize) -> Float| {
+            let mut samples = Vec::with_capacity(pixels);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    samples.push(value(x, y));
+                }
+            }
+            AnyChannel::new(name, FlatSamples::F32(samples))
+        };
+        let vector = |name: &str, components: [&str; 3], value: &dyn Fn(usize, usize) -> Vec3| {
+            vec![
+                scalar(&format!("{}{}", name, components[0]), &|x, y| {
+                    value(x, y).x()
+                }),
+                scalar(&format!("{}{}", name, components[1]), &|x, y| {
+                    value(x, y).y()
+                }),
+                scalar(&format!("{}{}", name, components[2]), &|x, y| {
+                    value(x, y).z()
+                }),
+            ]
+        };
+        let rgb = ["R", "G", "B"];
+        let rgb_layer = [".R", ".G", ".B"];
+        let xyz_layer = [".X", ".Y", ".Z"];
+
+        let mut channels = Vec::new();
+        channels.extend(vector("", rgb, &|x, y| colors[y * self.width + x]));
+        channels.extend(vector("albedo", rgb_layer, &|x, y| self.albedo(x, y)));
+        channels.extend(vector("normal", xyz_layer, &|x, y| self.normal(x, y)));
+        channels.extend(vector("position", xyz_layer, &|x, y| self.position(x, y)));
+        channels.extend(vector("direct", rgb_layer, &|x, y| self.direct(x, y)));
+        channels.extend(vector("indirect", rgb_layer, &|x, y| self.indirect(x, y)));
+        channels.push(scalar("depth.Z", &|x, y| {
+            let depth = self.depth(x, y);
+            if depth.is_finite() {
+                depth
+            } else {
+                Float::MAX
+            }
+        }));
+        channels.push(scalar("material_id.Y", &|x, y| {
+            self.material_id(x, y) as Float
+        }));
+        channels.push(scalar("object_id.Y", &|x, y| self.object_id(x, y) as Float));
+        channels.push(scalar("samples.Y", &|x, y| {
+            self.sample_count(x, y) as Float
+        }));
+
+        let layer = Layer::new(
+            size,
+            LayerAttributes::named("render"),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(SmallVec::from_vec(channels)),
+        );
+        Image::from_layer(layer).write().to_file(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn hit(material: &Rc<dyn Material>, object_id: usize, depth: Float) -> AovSample {
+        AovSample {
+            albedo: Vec3::new(0.2, 0.4, 0.6),
+            normal: Vec3::new(0.0, 2.0, 0.0),
+            depth,
+            position: Vec3::new(depth, 0.0, 0.0),
+            material_key: material_key(material),
+            object_id,
+            direct: Vec3::new(1.0, 1.0, 1.0),
+            indirect: Vec3::new(0.0, 0.5, 0.0),
+        }
+    }
+
+    fn lambertian() -> Rc<dyn Material> {
+        Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn test_averages_samples_and_numbers_materials() {
+        let (first, second) = (lambertian(), lambertian());
+        let mut buffers = AovBuffers::new(3, 1);
+        // 像素0两次击中、一次落空，深度和位置只对击中的采样取平均
+        buffers.add(0, 0, &hit(&second, 4, 1.0));
+        buffers.add(0, 0, &hit(&second, 4, 3.0));
+        buffers.add(0, 0, &AovSample::miss(Vec3::new(0.8, 0.8, 0.8)));
+        buffers.add(1, 0, &hit(&first, 2, 5.0));
+        buffers.add(2, 0, &hit(&second, 4, 5.0));
+
+        assert_eq!(buffers.sample_count(0, 0), 3);
+        let albedo = (2.0 * Vec3::new(0.2, 0.4, 0.6) + Vec3::new(0.8, 0.8, 0.8)) / 3.0;
+        assert!((buffers.albedo(0, 0) - albedo).length() < 1e-5);
+        let direct = (2.0 * Vec3::new(1.0, 1.0, 1.0) + Vec3::new(0.8, 0.8, 0.8)) / 3.0;
+        assert!((buffers.direct(0, 0) - direct).length() < 1e-5);
+        assert!((buffers.indirect(0, 0) - Vec3::new(0.0, 1.0 / 3.0, 0.0)).length() < 1e-5);
+        assert_eq!(buffers.depth(0, 0), 2.0);
+        assert_eq!(buffers.position(0, 0), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(buffers.normal(0, 0), Vec3::new(0.0, 1.0, 0.0));
+
+        // 材质按第一次出现的顺序从1开始编号，同一个`Rc`在不同像素编号相同
+        assert_eq!(buffers.material_id(0, 0), 1);
+        assert_eq!(buffers.material_id(1, 0), 2);
+        assert_eq!(buffers.material_id(2, 0), 1);
+        assert_eq!(buffers.object_id(0, 0), 4);
+        assert_eq!(buffers.object_id(1, 0), 2);
+    }
+
+    #[test]
+    fn test_exr_channels() {
+        let material = lambertian();
+        let mut buffers = AovBuffers::new(2, 1);
+        buffers.add(0, 0, &hit(&material, 3, 2.0));
+        buffers.add(0, 0, &hit(&material, 3, 4.0));
+        buffers.add(1, 0, &AovSample::miss(Vec3::new(0.5, 0.5, 0.5)));
+        let colors = [Vec3::new(0.1, 0.2, 0.3), Vec3::new(0.5, 0.5, 0.5)];
+        let path = std::env::temp_dir().join(format!("aov-test-{}.exr", std::process::id()));
+        buffers.save_exr(&colors, &path).unwrap();
+        let image = read_first_flat_layer_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let layer = &image.layer_data;
+        assert_eq!(layer.size, Vec2(2, 1));
+        let channel = |name: &str| -> Vec<f32> {
+            let channel = layer
+                .channel_data
+                .list
+                .iter()
+                .find(|c| c.name == *name)
+                .unwrap_or_else(|| panic!("missing channel {}", name));
+            match &channel.sample_data {
+                FlatSamples::F32(samples) => samples.clone(),
+                _ => panic!("channel {} is not f32", name),
+            }
+        };
+        let mut names: Vec<String> = layer
+            .channel_data
+            .list
+            .iter()
+            .map(|c| c.name.to_string())
+            .collect();
+        names.sort();
+        let expected = [
+            "B",
+            "G",
+            "R",
+            "albedo.B",
+            "albedo.G",
+            "albedo.R",
+            "depth.Z",
+            "direct.B",
+            "direct.G",
+            "direct.R",
+            "indirect.B",
+            "indirect.G",
+            "indirect.R",
+            "material_id.Y",
+            "normal.X",
+            "normal.Y",
+            "normal.Z",
+            "object_id.Y",
+            "position.X",
+            "position.Y",
+            "position.Z",
+            "samples.Y",
+        ];
+        assert_eq!(names, expected);
+
+        assert_eq!(channel("R"), vec![0.1, 0.5]);
+        assert_eq!(channel("B"), vec![0.3, 0.5]);
+        assert_eq!(channel("albedo.G"), vec![0.4, 0.5]);
+        assert_eq!(channel("normal.Y"), vec![1.0, 0.0]);
+        assert_eq!(channel("position.X"), vec![3.0, 0.0]);
+        assert_eq!(channel("indirect.G"), vec![0.5, 0.0]);
+        // 未击中物体的像素深度写为最大值
+        assert_eq!(channel("depth.Z"), vec![3.0, Float::MAX]);
+        assert_eq!(channel("material_id.Y"), vec![1.0, 0.0]);
+        assert_eq!(channel("object_id.Y"), vec![3.0, 0.0]);
+        assert_eq!(channel("samples.Y"), vec![2.0, 1.0]);
+    }
+}
@@ -0,0 +1,635 @@
+//! 双向路径追踪（Veach 1997）：分别从摄像机和光源出发生成两条子路径，
+//! 把两条子路径上的顶点两两连接，再用多重重要性抽样按平衡启发式合并各种连接方式
+//!
+//! 光源的抽样见`light`模块。光源一侧的路径可以穿过镜面，
+//! 所以玻璃球下面的焦散和从小开口照进来的光比单向的路径追踪收敛得快。
+//! 材质按双向路径追踪的需要分为三类：
+//!
+//! * `Material::is_specular`的镜面，路径可以穿过，但不能在这里连接；
+//! * 实现了`Material::bsdf`的表面，可以作为连接的端点；
+//! * 其余只能用`scatter`抽样的材质，光源一侧的路径在这里停下，
+//!   摄像机一侧的路径照常穿过，合并时只考虑实际能生成这条路径的连接方式
+//!
+//! 为了不用把光投影回胶片，这里不包括把光源子路径直接连接到摄像机的方式
+
+use crate::aov::{material_key, AovSample};
+use crate::hittable::HitRecord;
+use crate::light::{self, Emission};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::vec3::{Float, Vec3};
+use rand::Rng;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 顶点的种类
+enum Kind {
+    Camera,
+    /// 物体表面上的交点，也可能是摄像机路径击中的光源
+    Surface(HitRecord),
+    /// 在面光源上抽样的起点
+    Light(HitRecord),
+    /// 天空上的端点，`Vec3`为从场景看向天空的方向
+    Background(Vec3),
+}
+
+/// 子路径上的顶点
+struct Vertex {
+    kind: Kind,
+    point: Vec3,
+    /// 指向路径上前一个顶点的单位向量
+    wo: Vec3,
+    /// 从子路径的起点到这个顶点为止的贡献
+    beta: Vec3,
+    delta: bool,
+    /// 材质能用`bsdf`求值，可以作为连接的端点
+    connectable: bool,
+    /// 沿子路径生成的方向和相反的方向抽样到这个顶点的概率密度（对面积）
+    pdf_fwd: Float,
+    pdf_rev: Float,
+}
+
+impl Vertex {
+    fn camera(point: Vec3) -> Self {
+        Vertex {
+            kind: Kind::Camera,
+            point,
+            wo: Vec3::zero(),
+            beta: Vec3::new(1.0, 1.0, 1.0),
+            delta: false,
+            connectable: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn hit(&self) -> Option<&HitRecord> {
+        match &self.kind {
+            Kind::Surface(hit_record) | Kind::Light(hit_record) => Some(hit_record),
+            _ => None,
+        }
+    }
+
+    /// 从这个顶点指向`next`的单位向量
+    fn direction_to(&self, next: &Vertex) -> Vec3 {
+        match next.kind {
+            Kind::Background(direction) => direction,
+            _ => (next.point - self.point).unit_vector(),
+        }
+    }
+
+    /// 光从`next`方向射来、沿`wo`射出的BSDF值
+    fn f(&self, next: &Vertex) -> Vec3 {
+        match &self.kind {
+            Kind::Surface(hit_record) => hit_record
+                .material
+                .bsdf(hit_record, &self.wo, &self.direction_to(next))
+                .map_or(Vec3::zero(), |(f, _)| f),
+            _ => Vec3::zero(),
+        }
+    }
+
+    /// 着色法向和`w`夹角的余弦的绝对值，不在表面上时为1
+    fn abs_cos(&self, w: &Vec3) -> Float {
+        self.hit().map_or(1.0, |h| h.normal.dot(w).abs())
+    }
+
+    /// 几何法向和`w`夹角的余弦的绝对值，不在表面上时为1
+    fn abs_geometric_cos(&self, w: &Vec3) -> Float {
+        self.hit().map_or(1.0, |h| h.geometric_normal.dot(w).abs())
+    }
+
+    /// 把从这个顶点出发的立体角概率密度转换为`next`处的面积概率密度
+    fn convert_density(&self, pdf: Float, next: &Vertex) -> Float {
+        if let Kind::Background(_) = next.kind {
+            return pdf;
+        }
+        let w = next.point - self.point;
+        let distance_squared = w.squared_length();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        pdf * next.abs_geometric_cos(&(w / distance_squared.sqrt())) / distance_squared
+    }
+}
+
+/// 抽样概率为0时当作1，镜面顶点的正反概率密度可以互相抵消
+fn remap(pdf: Float) -> Float {
+    if pdf != 0.0 {
+        pdf
+    } else {
+        1.0
+    }
+}
+
+fn is_black(c: &Vec3) -> bool {
+    c.x() <= 0.0 && c.y() <= 0.0 && c.z() <= 0.0
+}
+
+/// 双向路径追踪器，`max_depth`为路径上最多的反射次数
+pub struct Bidirectional<'a> {
+    scene: &'a Scene,
+    max_depth: i32,
+    /// 选中某个光源的概率
+    light_pdf: Float,
+}
+
+impl<'a> Bidirectional<'a> {
+    pub fn new(scene: &'a Scene, max_depth: i32) -> Self {
+        Bidirectional {
+            scene,
+            max_depth,
+            light_pdf: light::selection_pdf(scene),
+        }
+    }
+
+    /// 估计摄像机射线带回的光，并记录第一次碰撞的各个通道
+    pub fn ray_color(&self, ray: &Ray, aov: &mut AovSample) -> Vec3 {
+        let max_depth = self.max_depth.max(0) as usize;
+        let mut camera = vec![Vertex::camera(*ray.origin())];
+        let direction = ray.direction().unit_vector();
+        let ray = Ray::with_time(*ray.origin(), direction, ray.time());
+        self.random_walk(
+            &ray,
+            Vec3::new(1.0, 1.0, 1.0),
+            1.0,
+            max_depth + 1,
+            false,
+            &mut camera,
+        );
+        let mut light = Vec::new();
+        self.light_subpath(ray.time(), max_depth, &mut light);
+
+        let (mut direct, mut indirect) = (Vec3::zero(), Vec3::zero());
+        for t in 2..=camera.len() {
+            // 对光源抽样的连接方式（s = 1）不用光源子路径，光源一侧的光线被挡住时也要计算
+            for s in 0..=light.len().max(1) {
+                let depth = s + t - 2;
+                if depth > max_depth {
+                    continue;
+                }
+                let contribution = self.connect(&light, &camera, s, t, ray.time());
+                if depth <= 1 {
+                    direct += contribution;
+                } else {
+                    indirect += contribution;
+                }
+            }
+        }
+
+        *aov = match camera.get(1).map(|v| &v.kind) {
+            Some(Kind::Surface(hit_record)) => AovSample {
+                albedo: hit_record.material.albedo(hit_record),
+                normal: hit_record.normal,
+                depth: hit_record.t,
+                position: hit_record.point,
+                material_key: material_key(&hit_record.material),
+                object_id: hit_record.object_id,
+                direct,
+                indirect,
+            },
+            _ => AovSample::miss(direct + indirect),
+        };
+        direct + indirect
+    }
+
+    /// 从`ray`出发随机游走，把最多`max_vertices`个顶点加到`path`后面，
+    /// `pdf`为抽样`ray`方向的立体角概率密度
+    fn random_walk(
+        &self,
+        ray: &Ray,
+        mut beta: Vec3,
+        pdf: Float,
+        max_vertices: usize,
+        from_light: bool,
+        path: &mut Vec<Vertex>,
+    ) {
+        let mut rng = sampler::rng();
+        let mut ray = Ray::with_time(*ray.origin(), *ray.direction(), ray.time());
+        let mut pdf_fwd = pdf;
+        let mut bounces = 0;
+        while bounces < max_vertices {
+            let prev = path.len() - 1;
+            let hit_record = match self.scene.hit(&ray, 0.001, Float::MAX) {
+                Some(h) => h,
+                None => {
+                    // 光源出发的路径射向天空时没有贡献
+                    if !from_light {
+                        let direction = ray.direction().unit_vector();
+                        path.push(Vertex {
+                            kind: Kind::Background(direction),
+                            point: path[prev].point + direction,
+                            wo: -direction,
+                            beta,
+                            delta: false,
+                            connectable: false,
+                            pdf_fwd,
+                            pdf_rev: 0.0,
+                        });
+                    }
+                    break;
+                }
+            };
+
+            let wo = -ray.direction().unit_vector();
+            let material = Rc::clone(&hit_record.material);
+            let delta = material.is_specular(&hit_record);
+            let connectable = !delta && material.bsdf(&hit_record, &wo, &wo).is_some();
+            if from_light && !delta && !connectable {
+                break;
+            }
+            let mut vertex = Vertex {
+                point: hit_record.point,
+                kind: Kind::Surface(hit_record),
+                wo,
+                beta,
+                delta,
+                connectable,
+                pdf_fwd: 0.0,
+                pdf_rev: 0.0,
+            };
+            vertex.pdf_fwd = path[prev].convert_density(pdf_fwd, &vertex);
+            path.push(vertex);
+            bounces += 1;
+            if bounces >= max_vertices {
+                break;
+            }
+
+            let hit_record = path[prev + 1].hit().unwrap();
+            let (attenuation, scattered) = match material.scatter(&ray, hit_record) {
+                Some(s) => s,
+                None => break,
+            };
+            let wi = scattered.direction().unit_vector();
+            let pdf_rev = if connectable {
+                pdf_fwd = material.bsdf(hit_record, &wo, &wi).map_or(0.0, |(_, p)| p);
+                material.bsdf(hit_record, &wi, &wo).map_or(0.0, |(_, p)| p)
+            } else {
+                pdf_fwd = 0.0;
+                0.0
+            };
+            let point = hit_record.point;
+            beta = beta * attenuation;
+            // 俄罗斯轮盘，反射率低的路径更早结束
+            if bounces > 3 {
+                let q = attenuation
+                    .x()
+                    .max(attenuation.y())
+                    .max(attenuation.z())
+                    .min(0.95);
+                if rng.gen_range(0.0, 1.0) >= q {
+                    break;
+                }
+                beta /= q;
+            }
+            path[prev].pdf_rev = path[prev + 1].convert_density(pdf_rev, &path[prev]);
+            ray = Ray::with_time(point, wi, ray.time());
+        }
+    }
+
+    /// 从光源发出一条光线，再随机游走生成光源子路径
+    fn light_subpath(&self, time: Float, max_depth: usize, path: &mut Vec<Vertex>) {
+        let Emission {
+            ray,
+            radiance,
+            hit_record,
+            pdf_light,
+            pdf_position,
+            pdf_direction,
+        } = match light::sample_emission(self.scene, time) {
+            Some(e) => e,
+            None => return,
+        };
+        let direction = ray.direction().unit_vector();
+        let (power, from_background) = match hit_record {
+            Some(hit_record) => {
+                let cos = hit_record.geometric_normal.dot(&direction).abs();
+                path.push(Vertex {
+                    point: hit_record.point,
+                    kind: Kind::Light(hit_record),
+                    wo: Vec3::zero(),
+                    beta: radiance,
+                    delta: false,
+                    connectable: false,
+                    pdf_fwd: pdf_light * pdf_position,
+                    pdf_rev: 0.0,
+                });
+                (cos * radiance, false)
+            }
+            None => {
+                path.push(Vertex {
+                    kind: Kind::Background(-direction),
+                    point: *ray.origin(),
+                    wo: Vec3::zero(),
+                    beta: radiance,
+                    delta: false,
+                    connectable: false,
+                    pdf_fwd: pdf_light * pdf_direction,
+                    pdf_rev: 0.0,
+                });
+                (radiance, true)
+            }
+        };
+        if pdf_direction <= 0.0 || is_black(&power) {
+            return;
+        }
+        let beta = power / (pdf_light * pdf_position * pdf_direction);
+        self.random_walk(&ray, beta, pdf_direction, max_depth, true, path);
+        // 天空的光线的第二个顶点的概率密度来自圆盘上的抽样
+        if from_background {
+            if let Some(vertex) = path.get_mut(1) {
+                vertex.pdf_fwd = pdf_position * vertex.abs_geometric_cos(&direction);
+            }
+        }
+    }
+
+    /// 用光源子路径的前`s`个顶点和摄像机子路径的前`t`个顶点组成一条完整的路径，
+    /// 返回乘过多重重要性抽样权重的贡献
+    fn connect(
+        &self,
+        light: &[Vertex],
+        camera: &[Vertex],
+        s: usize,
+        t: usize,
+        time: Float,
+    ) -> Vec3 {
+        let pt = &camera[t - 1];
+        if let Kind::Background(_) = pt.kind {
+            if s > 0 {
+                return Vec3::zero();
+            }
+        }
+        let mut sampled = None;
+        let contribution = if s == 0 {
+            pt.beta * self.emitted(pt, &camera[t - 2])
+        } else if s == 1 {
+            if !pt.connectable {
+                return Vec3::zero();
+            }
+            match self.sample_light(pt, time) {
+                Some(vertex) => {
+                    let w = pt.direction_to(&vertex);
+                    let c = pt.beta * pt.f(&vertex) * vertex.beta * pt.abs_cos(&w);
+                    sampled = Some(vertex);
+                    c
+                }
+                None => Vec3::zero(),
+            }
+        } else {
+            let qs = &light[s - 1];
+            if !qs.connectable || !pt.connectable {
+                return Vec3::zero();
+            }
+            let c = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta;
+            if is_black(&c) {
+                return Vec3::zero();
+            }
+            let d = pt.point - qs.point;
+            let distance_squared = d.squared_length();
+            if distance_squared < 1e-12 || !self.unoccluded(&qs.point, &pt.point, time) {
+                return Vec3::zero();
+            }
+            let w = d / distance_squared.sqrt();
+            c * (qs.abs_cos(&w) * pt.abs_cos(&w) / distance_squared)
+        };
+        if is_black(&contribution) {
+            return Vec3::zero();
+        }
+        contribution * self.mis_weight(light, camera, sampled.as_ref(), s, t)
+    }
+
+    /// 摄像机路径击中光源或者射向天空时看到的光，`prev`为路径上前一个顶点
+    fn emitted(&self, vertex: &Vertex, prev: &Vertex) -> Vec3 {
+        match &vertex.kind {
+            Kind::Surface(hit_record) => {
+                let ray = Ray::new(prev.point, vertex.point - prev.point);
+                hit_record.material.emitted(&ray, hit_record)
+            }
+            Kind::Background(direction) => self.scene.background(&Ray::new(prev.point, *direction)),
+            _ => Vec3::zero(),
+        }
+    }
+
+    /// 为`pt`抽样一个光源上的点，返回的顶点已经除以了抽样的概率密度
+    fn sample_light(&self, pt: &Vertex, time: Float) -> Option<Vertex> {
+        let sample = light::sample_incident(self.scene, &pt.point)?;
+        if !light::visible(self.scene, &pt.point, &sample, time) {
+            return None;
+        }
+        let (kind, point) = match sample.hit_record {
+            Some(hit_record) => {
+                let point = hit_record.point;
+                (Kind::Light(hit_record), point)
+            }
+            None => (
+                Kind::Background(sample.direction),
+                pt.point + sample.direction,
+            ),
+        };
+        let mut vertex = Vertex {
+            kind,
+            point,
+            wo: Vec3::zero(),
+            beta: sample.radiance / sample.pdf,
+            delta: false,
+            connectable: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        };
+        vertex.pdf_fwd = self.pdf_light_origin(&vertex);
+        Some(vertex)
+    }
+
+    fn unoccluded(&self, from: &Vec3, to: &Vec3, time: Float) -> bool {
+        let d = *to - *from;
+        let distance = d.length();
+        let ray = Ray::with_time(*from, d / distance, time);
+        self.scene.hit(&ray, 0.001, distance - 0.001).is_none()
+    }
+
+    /// 从`prev`到达`vertex`后抽样到`next`的面积概率密度，`vertex`是光源时和`prev`无关
+    fn pdf(&self, vertex: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> Float {
+        match &vertex.kind {
+            Kind::Light(_) | Kind::Background(_) => self.pdf_light(vertex, next),
+            Kind::Surface(hit_record) if vertex.connectable => {
+                let wo = vertex.direction_to(prev.expect("surface vertex needs a predecessor"));
+                let wi = vertex.direction_to(next);
+                let pdf = hit_record
+                    .material
+                    .bsdf(hit_record, &wo, &wi)
+                    .map_or(0.0, |(_, p)| p);
+                vertex.convert_density(pdf, next)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// 从光源`light`发出的光到达`next`的面积概率密度
+    fn pdf_light(&self, light: &Vertex, next: &Vertex) -> Float {
+        let (pdf, w) = match &light.kind {
+            Kind::Background(direction) => (light::background_position_pdf(self.scene), *direction),
+            _ => {
+                let d = next.point - light.point;
+                let distance_squared = d.squared_length();
+                if distance_squared == 0.0 {
+                    return 0.0;
+                }
+                let w = d / distance_squared.sqrt();
+                let cos = light
+                    .hit()
+                    .map_or(0.0, |h| h.geometric_normal.dot(&w).abs());
+                (0.5 * cos / PI / distance_squared, w)
+            }
+        };
+        pdf * next.abs_geometric_cos(&w)
+    }
+
+    /// 从光源上抽样到起点`light`的面积概率密度，天空为立体角概率密度
+    fn pdf_light_origin(&self, light: &Vertex) -> Float {
+        match &light.kind {
+            Kind::Background(direction) => self.light_pdf * self.scene.background_pdf(direction),
+            Kind::Surface(hit_record) | Kind::Light(hit_record) => self
+                .scene
+                .light_index(hit_record.object_id)
+                .map_or(0.0, |i| self.light_pdf / self.scene.light_area(i)),
+            Kind::Camera => 0.0,
+        }
+    }
+
+    /// 平衡启发式的权重：这条路径用`(s, t)`连接时的概率密度除以所有可行的连接方式的概率密度之和，
+    /// 依次把连接点向光源或摄像机一侧移动，用正反两个方向的概率密度之比递推
+    fn mis_weight(
+        &self,
+        light: &[Vertex],
+        camera: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> Float {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let qs = if s == 1 {
+            sampled
+        } else if s > 1 {
+            Some(&light[s - 1])
+        } else {
+            None
+        };
+        let qs_minus = if s > 1 { Some(&light[s - 2]) } else { None };
+        let pt = &camera[t - 1];
+        let pt_minus = &camera[t - 2];
+
+        // 复制一份各顶点的概率密度，再按这种连接方式修改连接点附近的值
+        let densities = |v: &Vertex| (v.pdf_fwd, v.pdf_rev, v.delta, v.connectable);
+        let mut light_side: Vec<_> = (0..s)
+            .map(|i| match (i, qs) {
+                (0, Some(q)) if s == 1 => densities(q),
+                _ => densities(&light[i]),
+            })
+            .collect();
+        let mut camera_side: Vec<_> = camera[..t].iter().map(densities).collect();
+
+        camera_side[t - 1].1 = match qs {
+            Some(q) => self.pdf(q, qs_minus, pt),
+            None => self.pdf_light_origin(pt),
+        };
+        // 没有加进光源列表的发光物体只能被摄像机路径直接击中
+        if s == 0 && camera_side[t - 1].1 == 0.0 {
+            return 1.0;
+        }
+        camera_side[t - 2].1 = match qs {
+            Some(q) => self.pdf(pt, Some(q), pt_minus),
+            None => self.pdf_light(pt, pt_minus),
+        };
+        if let Some(q) = qs {
+            light_side[s - 1].1 = self.pdf(pt, Some(pt_minus), q);
+            light_side[s - 1].2 = false;
+        }
+        if let (Some(q), Some(q_minus)) = (qs, qs_minus) {
+            light_side[s - 2].1 = self.pdf(q, Some(pt), q_minus);
+        }
+        camera_side[t - 1].2 = false;
+        // 击中的光源是光源子路径的起点
+        camera_side[t - 1].3 = true;
+
+        let mut sum = 0.0;
+        let mut ri = 1.0;
+        for i in (2..t).rev() {
+            let (fwd, rev, delta, connectable) = camera_side[i];
+            // 光源子路径穿不过只能抽样的表面，更靠近摄像机的连接方式都不可能
+            if !delta && !connectable {
+                break;
+            }
+            ri *= remap(rev) / remap(fwd);
+            if !delta && camera_side[i - 1].3 {
+                sum += ri;
+            }
+        }
+        let mut ri = 1.0;
+        for i in (0..s).rev() {
+            let (fwd, rev, delta, _) = light_side[i];
+            ri *= remap(rev) / remap(fwd);
+            let delta_prev = i > 0 && light_side[i - 1].2;
+            if !delta && !delta_prev {
+                sum += ri;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::Sphere;
+    use crate::material::Metal;
+    use crate::render::ray_color;
+    use crate::scene::fixtures::{assert_close, closed_room, lit_glass_sphere};
+
+    /// 对每个目标比较两种积分器沿同一条射线的平均值
+    fn assert_matches_path_tracing(scene: &Scene, origin: Vec3, targets: &[Vec3]) {
+        let bidirectional = Bidirectional::new(scene, 10);
+        for target in targets {
+            let ray = Ray::new(origin, *target - origin);
+            let n = 10_000;
+            let (mut expected, mut actual) = (Vec3::zero(), Vec3::zero());
+            let mut aov = AovSample::miss(Vec3::zero());
+            for _ in 0..n {
+                expected += ray_color(&ray, scene, 10, None);
+                actual += bidirectional.ray_color(&ray, &mut aov);
+            }
+            assert_close(expected / n as Float, actual / n as Float, 0.1);
+        }
+    }
+
+    #[test]
+    fn test_matches_path_tracing() {
+        // 玻璃球和粗糙金属球分别是镜面和只能抽样的材质，分别看向地面、玻璃球和金属球
+        let mut builder = lit_glass_sphere();
+        builder.add(Box::new(Sphere::new(
+            Vec3::new(-1.2, 0.5, -0.3),
+            0.5,
+            Rc::new(Metal::new(&Vec3::new(0.8, 0.8, 0.8), 0.3)),
+        )));
+        assert_matches_path_tracing(
+            &builder.build(),
+            Vec3::new(0.0, 1.0, 4.0),
+            &[
+                Vec3::new(0.3, 0.0, 0.9),
+                Vec3::new(0.0, 0.5, 0.0),
+                Vec3::new(-1.0, 0.5, 0.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_enclosed_scene_matches_path_tracing() {
+        assert_matches_path_tracing(
+            &closed_room(),
+            Vec3::new(0.0, 1.5, 1.8),
+            &[Vec3::new(-0.5, 0.0, 0.0), Vec3::new(-2.0, 2.0, -1.0)],
+        );
+    }
+}
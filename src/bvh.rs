@@ -0,0 +1,191 @@
+//! 层次包围盒（BVH），把场景中的物体按包围盒组织成二叉树，
+//! 射线只需要检测与其包围盒相交的子树
+
+use crate::aabb::{axis_of, Aabb};
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::vec3::Float;
+
+enum BvhNode {
+    Leaf {
+        id: usize,
+        object: Box<dyn Hittable>,
+    },
+    Branch {
+        bbox: Aabb,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl BvhNode {
+    /// 沿包围盒中心分布最长的轴，把物体从中位数处分为两半
+    fn build(mut objects: Vec<(usize, Aabb, Box<dyn Hittable>)>) -> (BvhNode, Aabb) {
+        if objects.len() == 1 {
+            let (id, bbox, object) = objects.pop().unwrap();
+            return (BvhNode::Leaf { id, object }, bbox);
+        }
+
+        let centroids = objects
+            .iter()
+            .map(|(_, bbox, _)| Aabb::new(bbox.centroid(), bbox.centroid()))
+            .reduce(|a, b| a.surrounding(&b))
+            .unwrap();
+        let axis = centroids.longest_axis();
+        let mid = objects.len() / 2;
+        objects.select_nth_unstable_by(mid, |(_, a, _), (_, b, _)| {
+            axis_of(&a.centroid(), axis)
+                .partial_cmp(&axis_of(&b.centroid(), axis))
+                .unwrap()
+        });
+        let right = objects.split_off(mid);
+        let (left, left_box) = BvhNode::build(objects);
+        let (right, right_box) = BvhNode::build(right);
+        let bbox = left_box.surrounding(&right_box);
+        (
+            BvhNode::Branch {
+                bbox,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            bbox,
+        )
+    }
+
+    /// `visits`累加检测过的包围盒和物体的个数
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float, visits: &mut usize) -> Option<HitRecord> {
+        *visits += 1;
+        match self {
+            BvhNode::Leaf { id, object } => object.hit(ray, t_min, t_max).map(|mut hit_record| {
+                hit_record.object_id = *id;
+                hit_record
+            }),
+            BvhNode::Branch { bbox, left, right } => {
+                if !bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                let hit_left = left.hit(ray, t_min, t_max, visits);
+                let closest = hit_left.as_ref().map_or(t_max, |h| h.t);
+                let hit_right = right.hit(ray, t_min, closest, visits);
+                hit_right.or(hit_left)
+            }
+        }
+    }
+}
+
+/// 场景的加速结构，建好之后可以被多个摄像机重复使用
+///
+/// 物体按传入的顺序从1开始编号，写入`HitRecord::object_id`；
+/// 没有包围盒的物体（例如无限大的平面）不进入树中，每次都逐个检测
+pub struct Bvh {
+    root: Option<(BvhNode, Aabb)>,
+    unbounded: Vec<(usize, Box<dyn Hittable>)>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Box<dyn Hittable>>) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (i, object) in objects.into_iter().enumerate() {
+            match object.bounding_box() {
+                Some(bbox) => bounded.push((i + 1, bbox, object)),
+                None => unbounded.push((i + 1, object)),
+            }
+        }
+        let root = if bounded.is_empty() {
+            None
+        } else {
+            Some(BvhNode::build(bounded))
+        };
+        Bvh { root, unbounded }
+    }
+
+    /// 有限大的物体的包围盒，忽略无限大的物体
+    pub fn finite_bounding_box(&self) -> Option<Aabb> {
+        self.root.as_ref().map(|(_, bbox)| *bbox)
+    }
+
+    /// 求交的同时返回检测过的包围盒和物体的个数，物体内部的加速结构不计在内
+    pub fn hit_with_cost(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> (Option<HitRecord>, usize) {
+        let mut visits = 0;
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+        if let Some((root, _)) = &self.root {
+            hit_record = root.hit(ray, t_min, t_max, &mut visits);
+            if let Some(h) = &hit_record {
+                closest_so_far = h.t;
+            }
+        }
+        for (id, object) in self.unbounded.iter() {
+            visits += 1;
+            if let Some(mut h) = object.hit(ray, t_min, closest_so_far) {
+                closest_so_far = h.t;
+                h.object_id = *id;
+                hit_record = Some(h);
+            }
+        }
+        (hit_record, visits)
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        self.hit_with_cost(ray, t_min, t_max).0
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.root.as_ref().map(|(_, bbox)| *bbox)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Vec3;
+    use rand::Rng;
+    use std::rc::Rc;
+
+    fn spheres(params: &[(Vec3, Float)]) -> Vec<Box<dyn Hittable>> {
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        params
+            .iter()
+            .map(|(center, radius)| {
+                Box::new(Sphere::new(*center, *radius, material.clone())) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_same_hits_as_list() {
+        let mut rng = rand::thread_rng();
+        let params: Vec<(Vec3, Float)> = (0..200)
+            .map(|_| {
+                let center = 10.0 * Vec3::random() - Vec3::new(5.0, 5.0, 5.0);
+                (center, rng.gen_range(0.1, 0.5))
+            })
+            .collect();
+        let list = spheres(&params);
+        let bvh = Bvh::new(spheres(&params));
+        for _ in 0..1000 {
+            let origin = Vec3::new(0.0, 0.0, 20.0);
+            let target = Vec3::new(rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0), 0.0);
+            let ray = Ray::new(origin, target - origin);
+            let a = list.hit(&ray, 0.001, Float::MAX);
+            let b = bvh.hit(&ray, 0.001, Float::MAX);
+            assert_eq!(a.as_ref().map(|h| h.t), b.as_ref().map(|h| h.t));
+            if let (Some(a), Some(b)) = (a, b) {
+                assert_eq!(a.point, b.point);
+                assert!(b.object_id > 0);
+            }
+        }
+    }
+}
@@ -1,9 +1,149 @@
 use crate::ray::*;
+use crate::sampler;
 use crate::vec3::*;
 use rand::Rng;
+use std::f32::consts::PI;
+use std::path::Path;
 
+/// 各种摄像机的公共接口
+pub trait Camera {
+    /// 生成穿过图像上`(s, t)`处的射线，`s`从左到右、`t`从下到上，范围均为`[0, 1]`
+    ///
+    /// 返回`None`表示该处没有光线，例如鱼眼镜头像圈之外的区域
+    fn get_ray(&self, s: Float, t: Float) -> Option<Ray>;
+
+    /// 生成`time`时刻的射线，会动的摄像机在这里按射线的时间取位置，其余的摄像机不动
+    fn get_ray_at(&self, s: Float, t: Float, time: Float) -> Option<Ray> {
+        let ray = self.get_ray(s, t)?;
+        Some(Ray::with_time(*ray.origin(), *ray.direction(), time))
+    }
+}
+
+/// 由`lookfrom`、`lookat`和`vup`确定的正交基，`w`指向摄像机后方
+fn orthonormal_basis(lookfrom: &Vec3, lookat: &Vec3, vup: &Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (lookfrom - lookat).unit_vector();
+    let u = vup.cross(&w).unit_vector();
+    let v = w.cross(&u);
+    (u, v, w)
+}
+
+/// 对焦平面经过`focus_point`时的对焦距离，即它在视线方向上的投影长度，
+/// 用于对焦在`lookat`之外的位置
+pub fn focus_distance(lookfrom: &Vec3, lookat: &Vec3, focus_point: &Vec3) -> Float {
+    let forward = (lookat - lookfrom).unit_vector();
+    (focus_point - lookfrom).dot(&forward)
+}
+
+/// 真实镜头的物理参数，场景单位为米
+#[derive(Debug, Copy, Clone)]
+pub struct PhysicalLens {
+    /// 焦距，单位毫米
+    pub focal_length: Float,
+    /// 传感器宽度，单位毫米
+    pub sensor_width: Float,
+    /// 光圈值，即焦距与光圈直径之比
+    pub f_stop: Float,
+}
+
+impl PhysicalLens {
+    pub fn new(focal_length: Float, sensor_width: Float, f_stop: Float) -> Self {
+        PhysicalLens {
+            focal_length,
+            sensor_width,
+            f_stop,
+        }
+    }
+
+    /// 全画幅（36mm×24mm）传感器
+    pub fn full_frame(focal_length: Float, f_stop: Float) -> Self {
+        Self::new(focal_length, 36.0, f_stop)
+    }
+
+    /// 给定画面宽高比时的垂直视角，单位为度
+    pub fn vfov(&self, aspect_ratio: Float) -> Float {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        2.0 * (sensor_height / (2.0 * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    /// 光圈直径，换算为场景单位（米）
+    pub fn aperture(&self) -> Float {
+        self.focal_length / self.f_stop / 1000.0
+    }
+}
+
+/// 光圈的形状，决定了焦外光斑（bokeh）的形状
+#[derive(Clone)]
+pub enum Aperture {
+    /// 圆形光圈
+    Circle,
+    /// 由`blades`（至少3）片光圈叶片组成的正多边形，`rotation`为旋转角度，单位为度
+    Polygon { blades: u32, rotation: Float },
+    /// 任意形状的灰度遮罩
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    /// 在光圈内均匀采样一个点，光圈外接于单位圆
+    fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circle => random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                random_in_polygon(*blades, rotation.to_radians())
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+/// 光圈遮罩，灰度图的亮度即为该处的透过率，图像铺满单位圆的外接正方形
+#[derive(Clone)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    transmission: Vec<Float>,
+}
+
+impl ApertureMask {
+    pub fn open<P: AsRef<Path>>(path: P) -> image::ImageResult<Self> {
+        let img = image::open(path)?.into_luma();
+        let (width, height) = img.dimensions();
+        let transmission = img.pixels().map(|p| p[0] as Float / 255.0).collect();
+        Ok(ApertureMask {
+            width: width as usize,
+            height: height as usize,
+            transmission,
+        })
+    }
+
+    /// 按透过率做拒绝采样，遮罩几乎全黑时退化为光圈中心
+    fn sample(&self) -> Vec3 {
+        let mut rng = sampler::rng();
+        for _ in 0..256 {
+            let x: Float = rng.gen_range(-1.0, 1.0);
+            let y: Float = rng.gen_range(-1.0, 1.0);
+            let px = (((x + 1.0) / 2.0 * self.width as Float) as usize).min(self.width - 1);
+            let py = (((1.0 - y) / 2.0 * self.height as Float) as usize).min(self.height - 1);
+            if rng.gen_range(0.0, 1.0) < self.transmission[py * self.width + px] {
+                return Vec3::new(x, y, 0.0);
+            }
+        }
+        Vec3::zero()
+    }
+}
+
+/// 镜筒造成的光学暗角：光圈前方`distance`处还有一个半径为`radius`的圆形遮挡，
+/// 画面边缘的斜射光被部分挡住，焦外光斑变成猫眼形状
+#[derive(Debug, Copy, Clone)]
+pub struct OpticalVignetting {
+    pub distance: Float,
+    pub radius: Float,
+}
+
+/// 薄透镜透视摄像机
 #[allow(dead_code)]
-pub struct Camera {
+pub struct PerspectiveCamera {
     lower_left_corner: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
@@ -12,9 +152,11 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: Float,
+    aperture: Aperture,
+    vignetting: Option<OpticalVignetting>,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         lookfrom: &Vec3,
         lookat: &Vec3,
@@ -28,9 +170,7 @@ impl Camera {
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
-        let w = (lookfrom - lookat).unit_vector();
-        let u = vup.cross(&w).unit_vector();
-        let v = w.cross(&u);
+        let (u, v, w) = orthonormal_basis(lookfrom, lookat, vup);
 
         let origin = *lookfrom;
         let horizontal = focus_dist * viewport_width * u;
@@ -38,7 +178,7 @@ impl Camera {
         let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
 
         let lens_radius = aperture / 2.0;
-        Camera {
+        PerspectiveCamera {
             lower_left_corner,
             horizontal,
             vertical,
@@ -47,22 +187,521 @@ impl Camera {
             v,
             w,
             lens_radius,
+            aperture: Aperture::Circle,
+            vignetting: None,
         }
     }
 
-    pub fn get_ray(&self, s: Float, t: Float) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    /// 用焦距、传感器尺寸和光圈值描述的摄像机
+    pub fn physical(
+        lookfrom: &Vec3,
+        lookat: &Vec3,
+        vup: &Vec3,
+        lens: &PhysicalLens,
+        aspect_ratio: Float,
+        focus_dist: Float,
+    ) -> Self {
+        Self::new(
+            lookfrom,
+            lookat,
+            vup,
+            lens.vfov(aspect_ratio),
+            aspect_ratio,
+            lens.aperture(),
+            focus_dist,
+        )
+    }
+
+    /// 移轴：平移成像平面而不转动摄像机，单位为画面的宽和高，
+    /// 常用于保持建筑的竖线平行
+    pub fn with_lens_shift(mut self, shift_x: Float, shift_y: Float) -> Self {
+        self.lower_left_corner += shift_x * self.horizontal + shift_y * self.vertical;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    pub fn with_optical_vignetting(mut self, vignetting: OpticalVignetting) -> Self {
+        self.vignetting = Some(vignetting);
+        self
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        let rd = self.lens_radius * self.aperture.sample();
         let offset = self.u * rd.x() + self.v * rd.y();
+        let origin = self.origin + offset;
+        let direction =
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset;
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-        )
+        if let Some(vignetting) = self.vignetting {
+            // 射线到达镜筒前端所在平面时，偏离光轴的距离不能超过镜筒半径
+            let along_axis = -direction.dot(&self.w);
+            let p = offset + vignetting.distance / along_axis * direction;
+            let lateral = p + p.dot(&self.w) * -self.w;
+            if lateral.squared_length() > vignetting.radius * vignetting.radius {
+                return None;
+            }
+        }
+
+        Some(Ray::new(origin, direction))
+    }
+}
+
+/// 立体摄像机组，用于VR预览
+///
+/// 两只眼睛沿水平方向各偏移瞳距的一半，视线保持平行，
+/// 再通过移轴使两个视锥在汇聚距离处重合（off-axis），避免转动摄像机带来的垂直视差
+#[derive(Debug, Copy, Clone)]
+pub struct StereoRig {
+    /// 瞳距，场景单位
+    pub interpupillary_distance: Float,
+    /// 零视差平面的距离，比它近的物体出屏，远的入屏
+    pub convergence_distance: Float,
+}
+
+impl StereoRig {
+    pub fn new(interpupillary_distance: Float, convergence_distance: Float) -> Self {
+        StereoRig {
+            interpupillary_distance,
+            convergence_distance,
+        }
+    }
+
+    /// 返回左眼和右眼的摄像机，`make_camera`根据单眼的位置和注视点生成摄像机
+    pub fn eyes<F>(
+        &self,
+        lookfrom: &Vec3,
+        lookat: &Vec3,
+        vup: &Vec3,
+        make_camera: F,
+    ) -> (PerspectiveCamera, PerspectiveCamera)
+    where
+        F: Fn(&Vec3, &Vec3) -> PerspectiveCamera,
+    {
+        let (u, _, _) = orthonormal_basis(lookfrom, lookat, vup);
+        let eye = |side: Float| {
+            let offset = side * self.interpupillary_distance / 2.0 * u;
+            let camera = make_camera(&(lookfrom + offset), &(lookat + offset));
+            // 汇聚距离处的画面宽度
+            let image_distance = (camera.origin - camera.lower_left_corner).dot(&camera.w);
+            let width = camera.horizontal.length() * self.convergence_distance / image_distance;
+            let shift = -side * self.interpupillary_distance / 2.0 / width;
+            camera.with_lens_shift(shift, 0.0)
+        };
+        (eye(-1.0), eye(1.0))
+    }
+}
+
+/// 正交摄像机，所有射线都平行于视线方向
+pub struct OrthographicCamera {
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+}
+
+impl OrthographicCamera {
+    /// `view_height`为画面对应的场景高度
+    pub fn new(
+        lookfrom: &Vec3,
+        lookat: &Vec3,
+        vup: &Vec3,
+        view_height: Float,
+        aspect_ratio: Float,
+    ) -> Self {
+        let (u, v, w) = orthonormal_basis(lookfrom, lookat, vup);
+        let horizontal = aspect_ratio * view_height * u;
+        let vertical = view_height * v;
+        OrthographicCamera {
+            lower_left_corner: lookfrom - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -w,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        Some(Ray::new(
+            self.lower_left_corner + s * self.horizontal + t * self.vertical,
+            self.direction,
+        ))
+    }
+}
+
+/// 等距柱状投影的360°全景摄像机，画面宽高比应为2:1
+///
+/// 水平方向对应经度`[-180°, 180°]`，竖直方向对应纬度`[-90°, 90°]`，画面中心为视线方向
+pub struct EquirectangularCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl EquirectangularCamera {
+    pub fn new(lookfrom: &Vec3, lookat: &Vec3, vup: &Vec3) -> Self {
+        let (u, v, w) = orthonormal_basis(lookfrom, lookat, vup);
+        EquirectangularCamera {
+            origin: *lookfrom,
+            u,
+            v,
+            w,
+        }
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        let longitude = (s - 0.5) * 2.0 * std::f32::consts::PI;
+        let latitude = (t - 0.5) * std::f32::consts::PI;
+        let direction = latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+            + latitude.sin() * self.v;
+        Some(Ray::new(self.origin, direction))
     }
 }
 
+/// 等距投影的鱼眼摄像机，像圈内切于画面的短边
+pub struct FisheyeCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    half_fov: Float,
+    aspect_ratio: Float,
+}
+
+impl FisheyeCamera {
+    /// `fov`为像圈对应的视角，单位为度，可以超过180°
+    pub fn new(
+        lookfrom: &Vec3,
+        lookat: &Vec3,
+        vup: &Vec3,
+        fov: Float,
+        aspect_ratio: Float,
+    ) -> Self {
+        let (u, v, w) = orthonormal_basis(lookfrom, lookat, vup);
+        FisheyeCamera {
+            origin: *lookfrom,
+            u,
+            v,
+            w,
+            half_fov: fov.to_radians() / 2.0,
+            aspect_ratio,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        let x = (2.0 * s - 1.0) * self.aspect_ratio.max(1.0);
+        let y = (2.0 * t - 1.0) / self.aspect_ratio.min(1.0);
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        // 等距投影：到像圈中心的距离和射线与视线的夹角成正比
+        let theta = r * self.half_fov;
+        let phi = y.atan2(x);
+        let direction =
+            theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+        Some(Ray::new(self.origin, direction))
+    }
+}
+
+/// 镜头组中的一个折射面，长度单位均为毫米
+#[derive(Debug, Copy, Clone)]
+pub struct LensElement {
+    /// 曲率半径，球心在靠近传感器一侧时为正，为0时表示光圈（孔径光阑）
+    pub curvature_radius: Float,
+    /// 到下一个面（靠近传感器一侧）的距离
+    pub thickness: Float,
+    /// 该面靠近传感器一侧介质的折射率，空气为1
+    pub ior: Float,
+    /// 通光孔径的直径
+    pub aperture_diameter: Float,
+}
+
+impl LensElement {
+    pub fn new(
+        curvature_radius: Float,
+        thickness: Float,
+        ior: Float,
+        aperture_diameter: Float,
+    ) -> Self {
+        LensElement {
+            curvature_radius,
+            thickness,
+            ior: if ior == 0.0 { 1.0 } else { ior },
+            aperture_diameter,
+        }
+    }
+
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+}
+
+/// 50mm f/2双高斯镜头，来自美国专利2,673,491
+pub fn double_gauss_50mm() -> Vec<LensElement> {
+    vec![
+        LensElement::new(29.475, 3.76, 1.67, 25.2),
+        LensElement::new(84.83, 0.12, 1.0, 25.2),
+        LensElement::new(19.275, 4.025, 1.67, 23.0),
+        LensElement::new(40.77, 3.275, 1.699, 23.0),
+        LensElement::new(12.75, 5.705, 1.0, 18.0),
+        LensElement::new(0.0, 4.5, 1.0, 17.1),
+        LensElement::new(-14.495, 1.18, 1.603, 17.0),
+        LensElement::new(40.77, 6.065, 1.658, 20.0),
+        LensElement::new(-20.385, 0.19, 1.0, 20.0),
+        LensElement::new(437.065, 3.22, 1.717, 20.0),
+        LensElement::new(-39.73, 0.0, 1.0, 20.0),
+    ]
+}
+
+/// 真实镜头组摄像机，从传感器出发依次追踪经过每一个镜片的折射
+///
+/// 镜片按从前（物方）到后（传感器）的顺序给出，最后一片的`thickness`由对焦自动确定。
+/// 镜头坐标系以传感器中心为原点，光轴为z轴，物方为正方向，单位为毫米；场景单位为米。
+/// 被镜片或光圈挡住的射线返回`None`，因此画面会自然地出现暗角
+pub struct RealisticCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    elements: Vec<LensElement>,
+    /// 每个面顶点的z坐标
+    positions: Vec<Float>,
+    sensor_width: Float,
+    sensor_height: Float,
+    /// 从传感器中心看出去能通过整个镜头组的后镜片区域（正方形的半边长）
+    exit_pupil: Float,
+}
+
+impl RealisticCamera {
+    /// `sensor_width`为传感器宽度，单位毫米；`focus_dist`为对焦距离，单位米
+    pub fn new(
+        lookfrom: &Vec3,
+        lookat: &Vec3,
+        vup: &Vec3,
+        elements: Vec<LensElement>,
+        sensor_width: Float,
+        aspect_ratio: Float,
+        focus_dist: Float,
+    ) -> Self {
+        let (u, v, w) = orthonormal_basis(lookfrom, lookat, vup);
+        let mut camera = RealisticCamera {
+            origin: *lookfrom,
+            u,
+            v,
+            w,
+            elements,
+            positions: Vec::new(),
+            sensor_width,
+            sensor_height: sensor_width / aspect_ratio,
+            exit_pupil: 0.0,
+        };
+        camera.focus(focus_dist * 1000.0);
+        camera.exit_pupil = camera.exit_pupil_bound();
+        camera
+    }
+
+    fn rear_element(&self) -> &LensElement {
+        self.elements.last().unwrap()
+    }
+
+    fn update_positions(&mut self) {
+        let mut z = 0.0;
+        self.positions = vec![0.0; self.elements.len()];
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            z += element.thickness;
+            self.positions[i] = z;
+        }
+    }
+
+    /// 从光轴上`distance`处的物点发出一条近轴光线，找到它的像点，把传感器移动到像点处
+    fn focus(&mut self, distance: Float) {
+        let last = self.elements.len() - 1;
+        // 先用一个足够远的后焦距求像点，再据此调整
+        let guess = 100.0;
+        self.elements[last].thickness = guess;
+        self.update_positions();
+        let front = self.positions[0];
+        let height = 0.01 * self.elements[0].aperture_diameter;
+        let start = Vec3::new(0.0, 0.0, front + distance);
+        let ray = Ray::new(start, Vec3::new(height, 0.0, front) - start);
+        if let Some(ray) = self.trace_from_scene(&ray) {
+            // 光线与光轴的交点即为像点
+            let t = -ray.origin().x() / ray.direction().x();
+            let image_z = ray.origin().z() + t * ray.direction().z();
+            self.elements[last].thickness = guess - image_z;
+        }
+        self.update_positions();
+    }
+
+    /// 在后镜片上均匀取点，求从传感器中心出发能穿过镜头组的范围
+    fn exit_pupil_bound(&self) -> Float {
+        let rear_radius = self.rear_element().aperture_diameter / 2.0;
+        let rear_z = self.rear_element().thickness;
+        let n = 64;
+        let mut bound: Float = 0.0;
+        for i in 0..=n {
+            for j in 0..=n {
+                let x = (2.0 * i as Float / n as Float - 1.0) * rear_radius;
+                let y = (2.0 * j as Float / n as Float - 1.0) * rear_radius;
+                let ray = Ray::new(Vec3::zero(), Vec3::new(x, y, rear_z));
+                if self.trace_from_film(&ray).is_some() {
+                    bound = bound.max(x.abs()).max(y.abs());
+                }
+            }
+        }
+        // 略微放大，避免漏掉边缘
+        (bound + 2.0 * rear_radius / n as Float).min(rear_radius)
+    }
+
+    /// 求射线与第`i`个面的交点和朝向射线来向的法向
+    fn intersect(&self, i: usize, ray: &Ray) -> Option<(Vec3, Vec3)> {
+        let element = &self.elements[i];
+        let z = self.positions[i];
+        let (point, normal) = if element.is_stop() {
+            let t = (z - ray.origin().z()) / ray.direction().z();
+            if t < 0.0 {
+                return None;
+            }
+            (ray.point_at_parameter(&t), Vec3::new(0.0, 0.0, 1.0))
+        } else {
+            let radius = element.curvature_radius;
+            let center = Vec3::new(0.0, 0.0, z - radius);
+            let oc = ray.origin() - center;
+            let a = ray.direction().squared_length();
+            let b = oc.dot(ray.direction());
+            let c = oc.squared_length() - radius * radius;
+            let discriminant = b * b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let t0 = (-b - discriminant.sqrt()) / a;
+            let t1 = (-b + discriminant.sqrt()) / a;
+            // 取靠近顶点的那一半球面
+            let p0 = ray.point_at_parameter(&t0);
+            let p1 = ray.point_at_parameter(&t1);
+            let (t, point) = if (p0.z() - z).abs() < (p1.z() - z).abs() {
+                (t0, p0)
+            } else {
+                (t1, p1)
+            };
+            if t < 0.0 {
+                return None;
+            }
+            (point, (point - center) / radius.abs())
+        };
+        let half = element.aperture_diameter / 2.0;
+        if point.x() * point.x() + point.y() * point.y() > half * half {
+            return None;
+        }
+        let normal = if normal.dot(ray.direction()) > 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        Some((point, normal))
+    }
+
+    /// 折射到下一个介质，发生全反射时光线被吸收
+    fn refract(ray: &Ray, point: Vec3, normal: &Vec3, eta_i: Float, eta_t: Float) -> Option<Ray> {
+        let d = ray.direction().unit_vector();
+        let ratio = eta_i / eta_t;
+        let cos_theta = (-d).dot(normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        if ratio * sin_theta > 1.0 {
+            return None;
+        }
+        Some(Ray::new(point, crate::material::refract(&d, normal, ratio)))
+    }
+
+    fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = Ray::new(*ray.origin(), *ray.direction());
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            let (point, normal) = self.intersect(i, &ray)?;
+            if element.is_stop() {
+                continue;
+            }
+            let eta_i = element.ior;
+            let eta_t = if i > 0 { self.elements[i - 1].ior } else { 1.0 };
+            ray = Self::refract(&ray, point, &normal, eta_i, eta_t)?;
+        }
+        Some(ray)
+    }
+
+    fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = Ray::new(*ray.origin(), *ray.direction());
+        for (i, element) in self.elements.iter().enumerate() {
+            let (point, normal) = self.intersect(i, &ray)?;
+            if element.is_stop() {
+                continue;
+            }
+            let eta_i = if i > 0 { self.elements[i - 1].ior } else { 1.0 };
+            let eta_t = element.ior;
+            ray = Self::refract(&ray, point, &normal, eta_i, eta_t)?;
+        }
+        Some(ray)
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        let mut rng = sampler::rng();
+        // 镜头成倒像，传感器上的点与画面坐标上下左右颠倒
+        let film = Vec3::new(
+            -(s - 0.5) * self.sensor_width,
+            -(t - 0.5) * self.sensor_height,
+            0.0,
+        );
+        let rear = Vec3::new(
+            rng.gen_range(-self.exit_pupil, self.exit_pupil),
+            rng.gen_range(-self.exit_pupil, self.exit_pupil),
+            self.rear_element().thickness,
+        );
+        let ray = self.trace_from_film(&Ray::new(film, rear - film))?;
+
+        let to_world = |p: &Vec3| p.x() * self.u + p.y() * self.v - p.z() * self.w;
+        Some(Ray::new(
+            self.origin + to_world(ray.origin()) / 1000.0,
+            to_world(ray.direction()),
+        ))
+    }
+}
+
+/// 在外接于单位圆的正多边形内均匀采样：
+/// 先随机选一个由中心和一条边组成的三角形，再在三角形内均匀采样
+fn random_in_polygon(blades: u32, rotation: Float) -> Vec3 {
+    let mut rng = sampler::rng();
+    let step = 2.0 * PI / blades as Float;
+    let i = rng.gen_range(0, blades) as Float;
+    let a = Vec3::new(
+        (rotation + i * step).cos(),
+        (rotation + i * step).sin(),
+        0.0,
+    );
+    let b = Vec3::new(
+        (rotation + (i + 1.0) * step).cos(),
+        (rotation + (i + 1.0) * step).sin(),
+        0.0,
+    );
+    let r1: Float = rng.gen_range(0.0, 1.0);
+    let r2: Float = rng.gen_range(0.0, 1.0);
+    let sqrt_r1 = r1.sqrt();
+    sqrt_r1 * (1.0 - r2) * a + sqrt_r1 * r2 * b
+}
+
 fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
     loop {
         let p = Vec3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), 0.0);
         if p.squared_length() < 1.0 {
@@ -70,3 +709,113 @@ fn random_in_unit_disk() -> Vec3 {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_polygon_aperture_inside() {
+        let blades = 6;
+        let apothem = (PI / blades as Float).cos();
+        for _ in 0..1000 {
+            let p = random_in_polygon(blades, 0.0);
+            // 正六边形各边的法向
+            for i in 0..blades {
+                let angle = (i as Float + 0.5) * 2.0 * PI / blades as Float;
+                let n = Vec3::new(angle.cos(), angle.sin(), 0.0);
+                assert!(p.dot(&n) <= apothem + 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_realistic_camera_focus() {
+        let lookfrom = Vec3::new(0.0, 0.0, 0.0);
+        let lookat = Vec3::new(0.0, 0.0, -1.0);
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+        let camera = RealisticCamera::new(
+            &lookfrom,
+            &lookat,
+            &vup,
+            double_gauss_50mm(),
+            36.0,
+            1.5,
+            5.0,
+        );
+        // 画面中心发出的光线都应该汇聚到对焦距离处的光轴上
+        let mut count = 0;
+        for _ in 0..100 {
+            if let Some(ray) = camera.get_ray(0.5, 0.5) {
+                let t = (-5.0 - ray.origin().z()) / ray.direction().z();
+                let p = ray.point_at_parameter(&t);
+                assert!(p.x().abs() < 0.01 && p.y().abs() < 0.01);
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} {:?}", a, b);
+    }
+
+    #[test]
+    fn test_projections() {
+        let lookfrom = Vec3::new(1.0, 2.0, 3.0);
+        let lookat = Vec3::new(1.0, 2.0, 0.0);
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+
+        // 正交：射线都沿视线方向，起点铺满画面
+        let camera = OrthographicCamera::new(&lookfrom, &lookat, &vup, 2.0, 2.0);
+        let ray = camera.get_ray(0.0, 1.0).unwrap();
+        assert_close(*ray.origin(), Vec3::new(-1.0, 3.0, 3.0));
+        assert_close(ray.direction().unit_vector(), forward);
+        let ray = camera.get_ray(0.5, 0.5).unwrap();
+        assert_close(*ray.origin(), lookfrom);
+
+        // 等距柱状：中心看向前方，左右边缘看向后方，顶部看向正上方，右边四分之一处看向右方
+        let camera = EquirectangularCamera::new(&lookfrom, &lookat, &vup);
+        let direction = |s, t| camera.get_ray(s, t).unwrap().direction().unit_vector();
+        assert_close(direction(0.5, 0.5), forward);
+        assert_close(direction(0.0, 0.5), -forward);
+        assert_close(direction(0.75, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_close(direction(0.5, 1.0), vup);
+
+        // 鱼眼：像圈边缘对应视角的一半，像圈之外没有光线
+        let camera = FisheyeCamera::new(&lookfrom, &lookat, &vup, 180.0, 2.0);
+        assert_close(
+            camera.get_ray(0.5, 0.5).unwrap().direction().unit_vector(),
+            forward,
+        );
+        assert_close(
+            camera.get_ray(0.75, 0.5).unwrap().direction().unit_vector(),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_close(
+            camera.get_ray(0.5, 1.0).unwrap().direction().unit_vector(),
+            vup,
+        );
+        assert!(camera.get_ray(0.9, 0.5).is_none());
+        assert!(camera.get_ray(0.74, 0.95).is_none());
+    }
+
+    #[test]
+    fn test_physical_lens() {
+        // 全画幅3:2的画面高24mm，50mm镜头的垂直视角为2·atan(12/50)
+        let lens = PhysicalLens::full_frame(50.0, 2.0);
+        let expected = 2.0 * (12.0 as Float / 50.0).atan().to_degrees();
+        assert!((lens.vfov(1.5) - expected).abs() < 1e-3);
+        assert!((expected - 26.99).abs() < 0.01);
+        // f/2时光圈直径为25mm
+        assert!((lens.aperture() - 0.025).abs() < 1e-6);
+        assert!((PhysicalLens::new(24.0, 36.0, 8.0).vfov(1.0) - 73.74).abs() < 0.01);
+
+        // 对焦距离为对焦点在视线方向上的投影
+        let lookfrom = Vec3::new(0.0, 1.0, 0.0);
+        let lookat = Vec3::new(0.0, 1.0, -1.0);
+        let d = focus_distance(&lookfrom, &lookat, &Vec3::new(3.0, -2.0, -4.0));
+        assert!((d - 4.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,222 @@
+//! 构造实体几何（CSG）：用并、交、差组合两个封闭的物体
+//!
+//! 两个物体各自给出射线穿过内部的区间，按布尔运算合并后，第一个边界就是交点
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::vec3::{Float, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    /// 第一个物体减去第二个物体
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// 两个物体的布尔组合，本身也可以再参与组合
+///
+/// 交点的材质来自提供这个表面的物体，差集里被挖去的部分显示第二个物体的材质
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Csg {
+            operation,
+            left,
+            right,
+        }
+    }
+
+    pub fn union(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self::new(CsgOperation::Union, left, right)
+    }
+
+    pub fn intersection(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self::new(CsgOperation::Intersection, left, right)
+    }
+
+    pub fn difference(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self::new(CsgOperation::Difference, left, right)
+    }
+}
+
+/// 合并区间时的事件：某一侧的物体在`t`处进入或离开
+struct Event {
+    t: Float,
+    right: bool,
+    entering: bool,
+    hit_record: HitRecord,
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        self.intervals(ray, t_min, t_max)
+            .into_iter()
+            .find_map(|span| span.enter.or(span.exit))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+        match self.operation {
+            CsgOperation::Union => Some(left?.surrounding(&right?)),
+            CsgOperation::Difference => left,
+            CsgOperation::Intersection => match (left, right) {
+                (Some(a), Some(b)) => {
+                    let min = Vec3::new(
+                        a.min().x().max(b.min().x()),
+                        a.min().y().max(b.min().y()),
+                        a.min().z().max(b.min().z()),
+                    );
+                    let max = Vec3::new(
+                        a.max().x().min(b.max().x()),
+                        a.max().y().min(b.max().y()),
+                        a.max().z().min(b.max().z()),
+                    );
+                    Some(Aabb::new(min, max))
+                }
+                (a, b) => a.or(b),
+            },
+        }
+    }
+
+    /// 把两侧的区间端点按`t`排序后扫描，布尔运算的结果改变时就是组合体的边界
+    fn intervals(&self, ray: &Ray, t_min: Float, t_max: Float) -> Vec<Span> {
+        let mut in_left = false;
+        let mut in_right = false;
+        let mut events = Vec::new();
+        let sides = [
+            (false, self.left.intervals(ray, t_min, t_max)),
+            (true, self.right.intervals(ray, t_min, t_max)),
+        ];
+        for (right, spans) in sides {
+            for span in spans {
+                match span.enter {
+                    Some(hit_record) => events.push(Event {
+                        t: hit_record.t,
+                        right,
+                        entering: true,
+                        hit_record,
+                    }),
+                    // 射线的起点已经在物体内部
+                    None if right => in_right = true,
+                    None => in_left = true,
+                }
+                if let Some(hit_record) = span.exit {
+                    events.push(Event {
+                        t: hit_record.t,
+                        right,
+                        entering: false,
+                        hit_record,
+                    });
+                }
+            }
+        }
+        events.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+
+        let mut inside = self.operation.contains(in_left, in_right);
+        let mut enter = None;
+        let mut spans = Vec::new();
+        for event in events {
+            if event.right {
+                in_right = event.entering;
+            } else {
+                in_left = event.entering;
+            }
+            let now_inside = self.operation.contains(in_left, in_right);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+            // 法向已经朝着射线来的方向，只需要按组合体重新判断是进入还是离开
+            let mut hit_record = event.hit_record;
+            hit_record.front_face = now_inside;
+            if now_inside {
+                enter = Some(hit_record);
+            } else {
+                spans.push(Span {
+                    enter: enter.take(),
+                    exit: Some(hit_record),
+                });
+            }
+        }
+        if inside {
+            spans.push(Span { enter, exit: None });
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+    use std::rc::Rc;
+
+    fn sphere(center: Vec3, radius: Float) -> Box<dyn Hittable> {
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        Box::new(Sphere::new(center, radius, material))
+    }
+
+    #[test]
+    fn test_lens_and_hollow_sphere() {
+        // 两个球的交集是一个双凸透镜，厚度为2
+        let lens = Csg::intersection(
+            sphere(Vec3::new(0.0, 0.0, -2.0), 3.0),
+            sphere(Vec3::new(0.0, 0.0, 2.0), 3.0),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let spans = lens.intervals(&ray, 0.001, Float::MAX);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter_t() - 9.0).abs() < 1e-4);
+        assert!((spans[0].exit_t() - 11.0).abs() < 1e-4);
+        let hit = lens.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!(hit.front_face && hit.normal.z() > 0.99);
+
+        // 空心球：外壳和内壳之间有两段
+        let shell = Csg::difference(sphere(Vec3::zero(), 2.0), sphere(Vec3::zero(), 1.0));
+        let spans = shell.intervals(&ray, 0.001, Float::MAX);
+        assert_eq!(spans.len(), 2);
+        assert!((spans[0].exit_t() - 9.0).abs() < 1e-4);
+        // 从空腔里射出，打到的是内壁，方向是进入壳体
+        let inner = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        let hit = shell.hit(&inner, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-4);
+        assert!(hit.front_face && hit.normal.x() < -0.99);
+        // 从壳体内部射出，先离开壳体
+        let inside = Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = shell.hit(&inside, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-4 && !hit.front_face);
+    }
+
+    #[test]
+    fn test_nested_union() {
+        let union = Csg::union(
+            sphere(Vec3::new(-1.0, 0.0, 0.0), 1.5),
+            sphere(Vec3::new(1.0, 0.0, 0.0), 1.5),
+        );
+        let nested = Csg::difference(Box::new(union), sphere(Vec3::zero(), 0.5));
+        let ray = Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let spans = nested.intervals(&ray, 0.001, Float::MAX);
+        let bounds: Vec<(Float, Float)> = spans.iter().map(|s| (s.enter_t(), s.exit_t())).collect();
+        assert_eq!(bounds.len(), 2);
+        assert!((bounds[0].0 - 7.5).abs() < 1e-4 && (bounds[0].1 - 9.5).abs() < 1e-4);
+        assert!((bounds[1].0 - 10.5).abs() < 1e-4 && (bounds[1].1 - 12.5).abs() < 1e-4);
+    }
+}
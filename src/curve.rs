@@ -0,0 +1,376 @@
+//! 三次贝塞尔曲线，用来表示头发、毛皮和草这类又细又长的物体
+//!
+//! 求交方法来自pbrt：把控制点变换到以射线为`z`轴的坐标系里，不断对半细分曲线，
+//! 丢掉包围盒不含原点的部分，足够平直后按线段求到射线的距离
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Float, Vec3};
+use std::rc::Rc;
+
+/// 曲线的截面形状
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CurveType {
+    /// 始终朝向射线的扁平带子，法向朝着射线来的方向
+    Flat,
+    /// 圆管，法向随着交点在宽度方向上的位置弯曲
+    Tube,
+}
+
+pub struct Curve {
+    control_points: [Vec3; 4],
+    width: [Float; 2],
+    curve_type: CurveType,
+    material: Rc<dyn Material>,
+    /// 求交时细分的层数
+    depth: u32,
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float {
+    (1.0 - t) * a + t * b
+}
+
+fn bezier(cp: &[Vec3; 4], u: Float) -> Vec3 {
+    let s = 1.0 - u;
+    s * s * s * cp[0] + 3.0 * s * s * u * cp[1] + 3.0 * s * u * u * cp[2] + u * u * u * cp[3]
+}
+
+fn bezier_derivative(cp: &[Vec3; 4], u: Float) -> Vec3 {
+    let s = 1.0 - u;
+    3.0 * (s * s * (cp[1] - cp[0]) + 2.0 * s * u * (cp[2] - cp[1]) + u * u * (cp[3] - cp[2]))
+}
+
+/// de Casteljau算法，在中点把曲线分成两段
+fn split(cp: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let mid = |a: Vec3, b: Vec3| 0.5 * (a + b);
+    let (p01, p12, p23) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let p = mid(p012, p123);
+    ([cp[0], p01, p012, p], [p, p123, p23, cp[3]])
+}
+
+fn bounds(cp: &[Vec3; 4]) -> Aabb {
+    cp.iter()
+        .map(|p| Aabb::new(*p, *p))
+        .reduce(|a, b| a.surrounding(&b))
+        .unwrap()
+}
+
+impl Curve {
+    /// `width`是曲线两端的宽度，中间线性变化
+    pub fn new(
+        control_points: [Vec3; 4],
+        width0: Float,
+        width1: Float,
+        material: Rc<dyn Material>,
+    ) -> Self {
+        // 细分到每段和直线的偏差小于宽度的5%为止
+        let mut l0: Float = 0.0;
+        for i in 0..2 {
+            let d = control_points[i] - 2.0 * control_points[i + 1] + control_points[i + 2];
+            l0 = l0.max(d.x().abs()).max(d.y().abs()).max(d.z().abs());
+        }
+        let eps = 0.05 * width0.max(width1);
+        let depth = if l0 > 0.0 && eps > 0.0 {
+            ((std::f32::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2() / 2.0)
+                .round()
+                .clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+        Curve {
+            control_points,
+            width: [width0, width1],
+            curve_type: CurveType::Flat,
+            material,
+            depth,
+        }
+    }
+
+    /// 均匀三次B样条的一段，`points`是影响这一段的四个控制点
+    pub fn from_bspline(
+        points: [Vec3; 4],
+        width0: Float,
+        width1: Float,
+        material: Rc<dyn Material>,
+    ) -> Self {
+        let [p0, p1, p2, p3] = points;
+        let control_points = [
+            (p0 + 4.0 * p1 + p2) / 6.0,
+            (2.0 * p1 + p2) / 3.0,
+            (p1 + 2.0 * p2) / 3.0,
+            (p1 + 4.0 * p2 + p3) / 6.0,
+        ];
+        Self::new(control_points, width0, width1, material)
+    }
+
+    /// 把一串点连成B样条曲线，每段是一个`Curve`，宽度从`width0`线性变化到`width1`
+    ///
+    /// 首尾的点各重复两次，让曲线从第一个点开始、到最后一个点结束
+    pub fn bspline_strand(
+        points: &[Vec3],
+        width0: Float,
+        width1: Float,
+        curve_type: CurveType,
+        material: Rc<dyn Material>,
+    ) -> Vec<Curve> {
+        if points.len() < 2 {
+            return Vec::new();
+        }
+        let mut padded = Vec::with_capacity(points.len() + 4);
+        padded.extend_from_slice(&[points[0], points[0]]);
+        padded.extend_from_slice(points);
+        padded.extend_from_slice(&[points[points.len() - 1], points[points.len() - 1]]);
+        let segments = padded.len() - 3;
+        padded
+            .windows(4)
+            .enumerate()
+            .map(|(i, w)| {
+                let width = |k: usize| lerp(k as Float / segments as Float, width0, width1);
+                let mut curve = Curve::from_bspline(
+                    [w[0], w[1], w[2], w[3]],
+                    width(i),
+                    width(i + 1),
+                    Rc::clone(&material),
+                );
+                curve.curve_type = curve_type;
+                curve
+            })
+            .collect()
+    }
+
+    /// 改为圆管
+    pub fn tube(mut self) -> Self {
+        self.curve_type = CurveType::Tube;
+        self
+    }
+
+    fn max_width(&self) -> Float {
+        self.width[0].max(self.width[1])
+    }
+
+    /// 在射线坐标系里递归细分，返回交点的深度（沿单位方向的距离）和曲线参数
+    fn intersect(
+        &self,
+        cp: &[Vec3; 4],
+        u0: Float,
+        u1: Float,
+        depth: u32,
+        z_max: Float,
+    ) -> Option<(Float, Float)> {
+        let half = 0.5 * self.max_width();
+        let bbox = bounds(cp);
+        if bbox.min().x() > half
+            || bbox.max().x() < -half
+            || bbox.min().y() > half
+            || bbox.max().y() < -half
+            || bbox.min().z() > z_max + half
+            || bbox.max().z() < -half
+        {
+            return None;
+        }
+        if depth > 0 {
+            let (left, right) = split(cp);
+            let u_mid = 0.5 * (u0 + u1);
+            let first = self.intersect(&left, u0, u_mid, depth - 1, z_max);
+            let z_max = first.map_or(z_max, |(z, _)| z);
+            return self
+                .intersect(&right, u_mid, u1, depth - 1, z_max)
+                .or(first);
+        }
+
+        // 足够平直，当成从第一个控制点到最后一个控制点的线段，两端用切线方向截断
+        let start = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        let end = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if start < 0.0 || end < 0.0 {
+            return None;
+        }
+        let (dx, dy) = (cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y());
+        let length2 = dx * dx + dy * dy;
+        if length2 == 0.0 {
+            return None;
+        }
+        let w = ((-cp[0].x() * dx - cp[0].y() * dy) / length2).clamp(0.0, 1.0);
+        let u = lerp(w, u0, u1);
+        let width = lerp(u, self.width[0], self.width[1]);
+        let p = bezier(cp, w);
+        if p.x() * p.x() + p.y() * p.y() > 0.25 * width * width {
+            return None;
+        }
+        // 射线从曲线自身上出发时深度小于半径，不算相交，避免细小的曲线挡住自己
+        if p.z() < 0.5 * width || p.z() > z_max {
+            return None;
+        }
+        Some((p.z(), u))
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        // 以射线起点为原点、射线方向为z轴的坐标系
+        let speed = ray.direction().length();
+        let dz = *ray.direction() / speed;
+        let helper = if dz.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let dx = dz.cross(&helper).unit_vector();
+        let dy = dz.cross(&dx);
+        let to_ray_space = |p: &Vec3| {
+            let d = p - ray.origin();
+            Vec3::new(d.dot(&dx), d.dot(&dy), d.dot(&dz))
+        };
+        let cp = [
+            to_ray_space(&self.control_points[0]),
+            to_ray_space(&self.control_points[1]),
+            to_ray_space(&self.control_points[2]),
+            to_ray_space(&self.control_points[3]),
+        ];
+
+        let z_max = if t_max < Float::MAX {
+            t_max * speed
+        } else {
+            Float::MAX
+        };
+        let (z, u) = self.intersect(&cp, 0.0, 1.0, self.depth, z_max)?;
+
+        // 控制点重合时（例如B样条的端点）端点处的导数为0，改用整段的方向
+        let tangent = bezier_derivative(&self.control_points, u);
+        let tangent = if tangent.squared_length() > 1e-12 {
+            tangent.unit_vector()
+        } else {
+            (self.control_points[3] - self.control_points[0]).unit_vector()
+        };
+        let center = bezier(&self.control_points, u);
+        let radius = 0.5 * lerp(u, self.width[0], self.width[1]);
+        // 宽度方向同时垂直于曲线和射线，正对射线的法向再和它们都垂直
+        let side = tangent.cross(&dz);
+        let side = if side.squared_length() > 1e-12 {
+            side.unit_vector()
+        } else {
+            dx
+        };
+        let facing = side.cross(&tangent);
+        let facing = if facing.dot(&dz) > 0.0 {
+            -facing
+        } else {
+            facing
+        };
+        let point = ray.point_at_parameter(&(z / speed));
+        let s = if radius > 0.0 {
+            ((point - center).dot(&side) / radius).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        let (t, normal) = match self.curve_type {
+            CurveType::Flat => (z / speed, facing),
+            CurveType::Tube => {
+                let c = (1.0 - s * s).sqrt();
+                ((z - radius * c) / speed, s * side + c * facing)
+            }
+        };
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        Some(HitRecord {
+            u,
+            v: 0.5 + 0.5 * s,
+            dpdu: tangent,
+            dpdv: 2.0 * radius * side,
+            ..HitRecord::new(
+                t,
+                ray.point_at_parameter(&t),
+                normal,
+                Rc::clone(&self.material),
+                ray,
+            )
+        })
+    }
+
+    /// 贝塞尔曲线在控制点的凸包内，再向外扩展半个宽度
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = bounds(&self.control_points);
+        let half = 0.5 * self.max_width();
+        let pad = Vec3::new(half, half, half);
+        Some(Aabb::new(bbox.min() - pad, bbox.max() + pad))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_hit_straight_and_bent_curve() {
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        // 沿x轴的直线，宽度从0.2变到0.1
+        let line = Curve::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+            ],
+            0.2,
+            0.1,
+            material.clone(),
+        )
+        .tube();
+        let ray = Ray::new(Vec3::new(1.5, 0.03, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = line.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.u - 0.5).abs() < 1e-3);
+        // 半径0.075的圆管，偏离中心0.03
+        let c = (1.0 - (0.03 / 0.075 as Float).powi(2)).sqrt();
+        assert!((hit.t - (5.0 - 0.075 * c)).abs() < 1e-3);
+        assert!((hit.normal - Vec3::new(0.0, 0.4, c)).length() < 1e-3);
+        assert!((hit.dpdu - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-4);
+        // 在较细的一端同样的偏移已经在曲线外面
+        let miss = Ray::new(Vec3::new(2.9, 0.06, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(line.hit(&miss, 0.001, Float::MAX).is_none());
+        let miss = Ray::new(Vec3::new(3.2, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(line.hit(&miss, 0.001, Float::MAX).is_none());
+
+        // 经过(0, 0)、(2, 0)并在中间鼓到y = 1.5的拱形，顶点在(1, 1.125)
+        let arch = Curve::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.5, 0.0),
+                Vec3::new(2.0, 1.5, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+            ],
+            0.05,
+            0.05,
+            material,
+        );
+        let ray = Ray::new(Vec3::new(1.0, 1.13, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        let hit = arch.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-3 && (hit.u - 0.5).abs() < 1e-2);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-4);
+        let below = Ray::new(Vec3::new(1.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(arch.hit(&below, 0.001, Float::MAX).is_none());
+    }
+
+    #[test]
+    fn test_bspline_strand_passes_through_ends() {
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        let points = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 2.0, 0.0),
+        ];
+        let strand = Curve::bspline_strand(&points, 0.1, 0.02, CurveType::Tube, material);
+        assert_eq!(strand.len(), 4);
+        assert_eq!(strand[0].control_points[0], points[0]);
+        assert!((strand[3].control_points[3] - points[2]).length() < 1e-6);
+        assert!((strand[3].width[1] - 0.02).abs() < 1e-6);
+        // 端点处的导数为0，切线仍然有效
+        let ray = Ray::new(Vec3::new(0.0, 0.01, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = strand[0].hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.dpdu - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-3);
+        assert!(hit.normal.z() > 0.9);
+    }
+}
@@ -0,0 +1,233 @@
+//! 基于辅助缓冲（反照率、法向、深度）的 À-Trous 小波降噪
+//!
+//! 参考 Dammertz et al. *Edge-Avoiding À-Trous Wavelet Transform for fast
+//! Global Illumination Filtering*，用联合双边权重代替高斯权重，
+//! 每次迭代把采样间隔加倍，几次迭代即可覆盖很大的滤波半径。
+
+use crate::aov::AovBuffers;
+use crate::vec3::{Float, Vec3};
+
+/// B3样条的一维核，二维核为其外积
+const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// À-Trous 降噪器，各个`sigma`越大对应的边缘越容易被抹平
+pub struct Denoiser {
+    pub iterations: u32,
+    pub sigma_color: Float,
+    pub sigma_normal: Float,
+    pub sigma_depth: Float,
+    pub sigma_albedo: Float,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 1.0,
+            sigma_normal: 0.1,
+            sigma_depth: 0.05,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+impl Denoiser {
+    /// 对线性颜色缓冲降噪
+    ///
+    /// 先除以反照率得到“光照”，只对光照滤波，最后再乘回反照率，
+    /// 这样纹理和颜色的细节不会被抹掉
+    pub fn denoise(&self, color: &[Vec3], aov: &AovBuffers) -> Vec<Vec3> {
+        let (width, height) = (aov.width(), aov.height());
+        let mut albedo = Vec::with_capacity(width * height);
+        let mut normal = Vec::with_capacity(width * height);
+        let mut depth = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                albedo.push(aov.albedo(x, y));
+                normal.push(aov.normal(x, y));
+                depth.push(aov.depth(x, y));
+            }
+        }
+
+        let mut irradiance: Vec<Vec3> = color
+            .iter()
+            .zip(albedo.iter())
+            .map(|(c, a)| demodulate(c, a))
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            // 每次迭代后噪声减少，颜色权重也应随之收紧
+            let sigma_color = self.sigma_color / (1 << iteration) as Float;
+            let mut filtered = vec![Vec3::zero(); width * height];
+            for y in 0..height {
+                for x in 0..width {
+                    let p = y * width + x;
+                    let mut sum = Vec3::zero();
+                    let mut weight_sum = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as isize + (j as isize - 2) * step;
+                        if qy < 0 || qy >= height as isize {
+                            continue;
+                        }
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (i as isize - 2) * step;
+                            if qx < 0 || qx >= width as isize {
+                                continue;
+                            }
+                            let q = qy as usize * width + qx as usize;
+
+                            let w = kx
+                                * ky
+                                * gaussian(&irradiance[p], &irradiance[q], sigma_color)
+                                * gaussian(&albedo[p], &albedo[q], self.sigma_albedo)
+                                * normal_weight(&normal[p], &normal[q], self.sigma_normal)
+                                * depth_weight(depth[p], depth[q], self.sigma_depth);
+                            sum += w * irradiance[q];
+                            weight_sum += w;
+                        }
+                    }
+                    filtered[p] = if weight_sum > 0.0 {
+                        sum / weight_sum
+                    } else {
+                        irradiance[p]
+                    };
+                }
+            }
+            irradiance = filtered;
+        }
+
+        irradiance
+            .iter()
+            .zip(albedo.iter())
+            .map(|(c, a)| remodulate(c, a))
+            .collect()
+    }
+}
+
+fn demodulate(color: &Vec3, albedo: &Vec3) -> Vec3 {
+    Vec3::new(
+        safe_div(color.x(), albedo.x()),
+        safe_div(color.y(), albedo.y()),
+        safe_div(color.z(), albedo.z()),
+    )
+}
+
+fn remodulate(irradiance: &Vec3, albedo: &Vec3) -> Vec3 {
+    let keep = |a: Float| if a > ALBEDO_EPSILON { a } else { 1.0 };
+    Vec3::new(
+        irradiance.x() * keep(albedo.x()),
+        irradiance.y() * keep(albedo.y()),
+        irradiance.z() * keep(albedo.z()),
+    )
+}
+
+const ALBEDO_EPSILON: Float = 1e-3;
+
+fn safe_div(c: Float, a: Float) -> Float {
+    if a > ALBEDO_EPSILON {
+        c / a
+    } else {
+        c
+    }
+}
+
+fn gaussian(a: &Vec3, b: &Vec3, sigma: Float) -> Float {
+    (-(a - b).squared_length() / (sigma * sigma)).exp()
+}
+
+fn normal_weight(a: &Vec3, b: &Vec3, sigma: Float) -> Float {
+    let d = (1.0 - a.dot(b)).max(0.0);
+    (-d / (sigma * sigma)).exp()
+}
+
+/// 深度用相对差值，这样远处和近处的物体使用同样的容差
+fn depth_weight(a: Float, b: Float, sigma: Float) -> Float {
+    match (a.is_finite(), b.is_finite()) {
+        (true, true) => {
+            let d = (a - b).abs() / a.max(b).max(1e-4);
+            (-d * d / (sigma * sigma)).exp()
+        }
+        (false, false) => 1.0,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::aov::AovSample;
+
+    fn surface(albedo: Vec3, normal: Vec3) -> AovSample {
+        AovSample {
+            albedo,
+            normal,
+            depth: 1.0,
+            ..AovSample::miss(Vec3::zero())
+        }
+    }
+
+    fn flat_aov(width: usize, height: usize) -> AovBuffers {
+        let mut aov = AovBuffers::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let sample = surface(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.0, 1.0, 0.0));
+                aov.add(x, y, &sample);
+            }
+        }
+        aov
+    }
+
+    #[test]
+    fn test_constant_image_unchanged() {
+        let aov = flat_aov(8, 8);
+        let color = vec![Vec3::new(0.2, 0.3, 0.4); 64];
+        let denoised = Denoiser::default().denoise(&color, &aov);
+        for c in denoised {
+            assert!((c - Vec3::new(0.2, 0.3, 0.4)).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_noise_reduced() {
+        let aov = flat_aov(16, 16);
+        let color: Vec<Vec3> = (0..256)
+            .map(|i| {
+                if (i + i / 16) % 2 == 0 {
+                    Vec3::new(0.3, 0.3, 0.3)
+                } else {
+                    Vec3::new(0.5, 0.5, 0.5)
+                }
+            })
+            .collect();
+        let denoised = Denoiser::default().denoise(&color, &aov);
+        for c in denoised {
+            assert!((c.x() - 0.4).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_edges_preserved() {
+        let mut aov = AovBuffers::new(8, 1);
+        for x in 0..8 {
+            let normal = if x < 4 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(1.0, 0.0, 0.0)
+            };
+            aov.add(x, 0, &surface(Vec3::new(1.0, 1.0, 1.0), normal));
+        }
+        let color: Vec<Vec3> = (0..8)
+            .map(|x| {
+                if x < 4 {
+                    Vec3::zero()
+                } else {
+                    Vec3::new(1.0, 1.0, 1.0)
+                }
+            })
+            .collect();
+        let denoised = Denoiser::default().denoise(&color, &aov);
+        assert!(denoised[3].x() < 0.01);
+        assert!(denoised[4].x() > 0.99);
+    }
+}
@@ -0,0 +1,469 @@
+//! 导入glTF 2.0场景（.gltf和.glb），只读取本地文件
+//!
+//! 网格按节点的层级变换烘焙到世界坐标，材质使用金属度-粗糙度模型。
+//! 点光源和聚光灯变成小的发光球，平行光变成天空中的圆盘
+
+use crate::camera::{Camera, OrthographicCamera, PerspectiveCamera};
+use crate::hittable::{Hittable, Sphere};
+use crate::material::{DiffuseLight, Material, PbrMaterial};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::scene::{DistantLight, SceneBuilder};
+use crate::texture::{srgb_to_linear, ImageTexture, Texture, Wrap};
+use crate::transform::Transform;
+use crate::vec3::{Float, Vec3};
+use gltf::khr_lights_punctual::Kind;
+use gltf::mesh::Mode;
+use gltf::texture::WrappingMode;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// 导入时的设置
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// 点光源和聚光灯的发光球半径
+    pub light_radius: Float,
+    /// 平行光在天空中的张角（度）
+    pub sun_angular_diameter: Float,
+    /// 光源强度的缩放，glTF的光强以坎德拉和勒克斯为单位，而天空的亮度为1
+    pub light_intensity_scale: Float,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            light_radius: 0.05,
+            sun_angular_diameter: 2.0,
+            light_intensity_scale: 1.0,
+        }
+    }
+}
+
+/// glTF摄像机的投影方式
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    /// 竖直视角（度），文件没有给出宽高比时由渲染设置决定
+    Perspective {
+        vfov: Float,
+        aspect_ratio: Option<Float>,
+    },
+    /// 水平和竖直方向的半宽
+    Orthographic { xmag: Float, ymag: Float },
+}
+
+/// 文件里的摄像机，朝向节点的-z方向，+y向上
+#[derive(Debug, Clone)]
+pub struct GltfCamera {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub projection: Projection,
+}
+
+impl GltfCamera {
+    /// 生成摄像机，`aspect_ratio`为输出图像的宽高比
+    pub fn to_camera(&self, aspect_ratio: Float) -> Box<dyn Camera> {
+        let lookfrom = self.transform.point(&Vec3::zero());
+        let lookat = lookfrom + self.transform.vector(&Vec3::new(0.0, 0.0, -1.0));
+        let vup = self.transform.vector(&Vec3::new(0.0, 1.0, 0.0));
+        match self.projection {
+            Projection::Perspective { vfov, .. } => Box::new(PerspectiveCamera::new(
+                &lookfrom,
+                &lookat,
+                &vup,
+                vfov,
+                aspect_ratio,
+                0.0,
+                1.0,
+            )),
+            Projection::Orthographic { ymag, .. } => Box::new(OrthographicCamera::new(
+                &lookfrom,
+                &lookat,
+                &vup,
+                2.0 * ymag * vup.length(),
+                aspect_ratio,
+            )),
+        }
+    }
+
+    /// 文件中指定的宽高比
+    pub fn aspect_ratio(&self) -> Option<Float> {
+        match self.projection {
+            Projection::Perspective { aspect_ratio, .. } => aspect_ratio,
+            Projection::Orthographic { xmag, ymag } => Some(xmag / ymag),
+        }
+    }
+}
+
+/// 导入的场景内容
+#[derive(Default)]
+pub struct GltfScene {
+    pub objects: Vec<Box<dyn Hittable>>,
+    pub distant_lights: Vec<DistantLight>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+impl GltfScene {
+    /// 把物体和平行光加入场景
+    pub fn add_to(self, builder: &mut SceneBuilder) -> Vec<GltfCamera> {
+        for object in self.objects {
+            builder.add(object);
+        }
+        for light in self.distant_lights {
+            builder.distant_light(light);
+        }
+        self.cameras
+    }
+}
+
+/// 读取.gltf或.glb文件，外部的缓冲和图片从文件所在目录读取
+pub fn load<P: AsRef<Path>>(path: P, options: &ImportOptions) -> gltf::Result<GltfScene> {
+    let (document, buffers, images) = gltf::import(path)?;
+    Ok(Importer::new(&buffers, &images, options).import(&document))
+}
+
+/// 从内存中读取，外部资源只能是data URI
+pub fn load_slice(bytes: &[u8], options: &ImportOptions) -> gltf::Result<GltfScene> {
+    let (document, buffers, images) = gltf::import_slice(bytes)?;
+    Ok(Importer::new(&buffers, &images, options).import(&document))
+}
+
+fn vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0] as Float, v[1] as Float, v[2] as Float)
+}
+
+/// glTF的按列排列的4×4矩阵
+fn node_transform(node: &gltf::Node) -> Option<Transform> {
+    let m = node.transform().matrix();
+    let mut matrix = [[0.0; 3]; 3];
+    for (i, row) in matrix.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i] as Float;
+        }
+    }
+    Transform::from_matrix(matrix, Vec3::new(m[3][0], m[3][1], m[3][2]))
+}
+
+fn wrap(mode: WrappingMode) -> Wrap {
+    match mode {
+        WrappingMode::Repeat => Wrap::Repeat,
+        WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+        WrappingMode::ClampToEdge => Wrap::ClampToEdge,
+    }
+}
+
+/// 把解码后的图片转换为线性空间的颜色，单通道图复制到三个通道
+fn image_pixels(image: &gltf::image::Data, srgb: bool) -> Vec<Vec3> {
+    use gltf::image::Format;
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |texel: &[u8], c: usize| -> Float {
+        let b = &texel[c * bytes..(c + 1) * bytes];
+        let value = match bytes {
+            1 => b[0] as Float / 255.0,
+            2 => u16::from_le_bytes([b[0], b[1]]) as Float / 65535.0,
+            _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as Float,
+        };
+        if srgb && bytes < 4 {
+            srgb_to_linear(value)
+        } else {
+            value
+        }
+    };
+    image
+        .pixels
+        .chunks_exact(channels * bytes)
+        .map(|texel| match channels {
+            1 => {
+                let r = channel(texel, 0);
+                Vec3::new(r, r, r)
+            }
+            2 => Vec3::new(channel(texel, 0), channel(texel, 1), 0.0),
+            _ => Vec3::new(channel(texel, 0), channel(texel, 1), channel(texel, 2)),
+        })
+        .collect()
+}
+
+struct Importer<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    options: &'a ImportOptions,
+    /// 以(纹理编号, 是否为sRGB)为键，同一张图可能同时用作颜色和数据
+    textures: HashMap<(usize, bool), Rc<dyn Texture>>,
+    materials: HashMap<Option<usize>, Rc<dyn Material>>,
+    scene: GltfScene,
+}
+
+impl<'a> Importer<'a> {
+    fn new(
+        buffers: &'a [gltf::buffer::Data],
+        images: &'a [gltf::image::Data],
+        options: &'a ImportOptions,
+    ) -> Self {
+        Importer {
+            buffers,
+            images,
+            options,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            scene: GltfScene::default(),
+        }
+    }
+
+    /// 导入默认场景，没有指定默认场景时导入第一个
+    fn import(mut self, document: &gltf::Document) -> GltfScene {
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next());
+        if let Some(scene) = scene {
+            for node in scene.nodes() {
+                self.visit(&node, &Transform::identity());
+            }
+        }
+        self.scene
+    }
+
+    fn visit(&mut self, node: &gltf::Node, parent: &Transform) {
+        // 缩放为0的节点不可见，连同子节点一起跳过
+        let transform = match node_transform(node) {
+            Some(local) => local.then(parent),
+            None => return,
+        };
+        if let Some(mesh) = node.mesh() {
+            self.add_mesh(&mesh, &transform);
+        }
+        if let Some(camera) = node.camera() {
+            self.add_camera(&camera, &transform);
+        }
+        if let Some(light) = node.light() {
+            self.add_light(&light, &transform);
+        }
+        for child in node.children() {
+            self.visit(&child, &transform);
+        }
+    }
+
+    fn add_mesh(&mut self, mesh: &gltf::Mesh, transform: &Transform) {
+        for primitive in mesh.primitives() {
+            let buffers = self.buffers;
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vec3> = match reader.read_positions() {
+                Some(positions) => positions.map(vec3).collect(),
+                None => continue,
+            };
+            let vertices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            let indices: Vec<[usize; 3]> = match primitive.mode() {
+                Mode::Triangles => vertices
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect(),
+                Mode::TriangleStrip => (2..vertices.len())
+                    .map(|i| {
+                        if i % 2 == 0 {
+                            [vertices[i - 2], vertices[i - 1], vertices[i]]
+                        } else {
+                            [vertices[i - 1], vertices[i - 2], vertices[i]]
+                        }
+                    })
+                    .collect(),
+                Mode::TriangleFan => (2..vertices.len())
+                    .map(|i| [vertices[0], vertices[i - 1], vertices[i]])
+                    .collect(),
+                // 点和线没有面积，渲染不出来
+                _ => continue,
+            };
+            let mut mesh = Mesh {
+                positions,
+                indices,
+                normals: reader
+                    .read_normals()
+                    .map_or_else(Vec::new, |n| n.map(vec3).collect()),
+                uvs: reader.read_tex_coords(0).map_or_else(Vec::new, |uv| {
+                    uv.into_f32()
+                        .map(|[u, v]| [u as Float, v as Float])
+                        .collect()
+                }),
+                tangents: reader.read_tangents().map_or_else(Vec::new, |t| {
+                    t.map(|[x, y, z, w]| (Vec3::new(x, y, z), w as Float))
+                        .collect()
+                }),
+                colors: reader
+                    .read_colors(0)
+                    .map_or_else(Vec::new, |c| c.into_rgb_f32().map(vec3).collect()),
+            };
+            mesh.transform(transform);
+            let material = self.material(&primitive.material());
+            self.scene
+                .objects
+                .push(Box::new(TriangleMesh::new(mesh, material)));
+        }
+    }
+
+    fn texture(&mut self, texture: &gltf::Texture, srgb: bool) -> Rc<dyn Texture> {
+        let images = self.images;
+        self.textures
+            .entry((texture.index(), srgb))
+            .or_insert_with(|| {
+                let image = &images[texture.source().index()];
+                let sampler = texture.sampler();
+                Rc::new(
+                    ImageTexture::new(
+                        image.width as usize,
+                        image.height as usize,
+                        image_pixels(image, srgb),
+                    )
+                    .with_wrap(wrap(sampler.wrap_s()), wrap(sampler.wrap_t())),
+                )
+            })
+            .clone()
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Rc<dyn Material> {
+        if let Some(m) = self.materials.get(&material.index()) {
+            return Rc::clone(m);
+        }
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let mut result = PbrMaterial::new(
+            Vec3::new(r, g, b),
+            pbr.metallic_factor(),
+            pbr.roughness_factor(),
+        );
+        if let Some(info) = pbr.base_color_texture() {
+            result = result.with_base_color_texture(self.texture(&info.texture(), true));
+        }
+        if let Some(info) = pbr.metallic_roughness_texture() {
+            result = result.with_metallic_roughness_texture(self.texture(&info.texture(), false));
+        }
+        let emissive =
+            vec3(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0);
+        let emissive_texture = material
+            .emissive_texture()
+            .map(|info| self.texture(&info.texture(), true));
+        result = result.with_emissive(emissive, emissive_texture);
+        if let Some(normal) = material.normal_texture() {
+            result =
+                result.with_normal_texture(self.texture(&normal.texture(), false), normal.scale());
+        }
+        if let Some(occlusion) = material.occlusion_texture() {
+            result = result.with_occlusion_texture(
+                self.texture(&occlusion.texture(), false),
+                occlusion.strength(),
+            );
+        }
+        let result: Rc<dyn Material> = Rc::new(result);
+        self.materials.insert(material.index(), Rc::clone(&result));
+        result
+    }
+
+    fn add_camera(&mut self, camera: &gltf::Camera, transform: &Transform) {
+        let projection = match camera.projection() {
+            gltf::camera::Projection::Perspective(p) => Projection::Perspective {
+                vfov: p.yfov().to_degrees(),
+                aspect_ratio: p.aspect_ratio(),
+            },
+            gltf::camera::Projection::Orthographic(o) => Projection::Orthographic {
+                xmag: o.xmag(),
+                ymag: o.ymag(),
+            },
+        };
+        self.scene.cameras.push(GltfCamera {
+            name: camera.name().map(String::from),
+            transform: *transform,
+            projection,
+        });
+    }
+
+    /// 点光源的光强I（坎德拉）对应半径为r、亮度为I/(πr²)的发光球
+    fn add_light(&mut self, light: &gltf::khr_lights_punctual::Light, transform: &Transform) {
+        let intensity =
+            vec3(light.color()) * light.intensity() * self.options.light_intensity_scale;
+        let position = transform.point(&Vec3::zero());
+        let direction = transform.vector(&Vec3::new(0.0, 0.0, -1.0));
+        let r = self.options.light_radius;
+        let radiance = intensity / (std::f32::consts::PI * r * r);
+        let material = match light.kind() {
+            Kind::Directional => {
+                self.scene.distant_lights.push(DistantLight::new(
+                    direction,
+                    intensity,
+                    self.options.sun_angular_diameter,
+                ));
+                return;
+            }
+            Kind::Point => DiffuseLight::new(radiance),
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => DiffuseLight::spot(radiance, direction, inner_cone_angle, outer_cone_angle),
+        };
+        self.scene
+            .objects
+            .push(Box::new(Sphere::new(position, r, Rc::new(material))));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::ray::Ray;
+
+    /// 一个三角形挂在平移过的父节点下面，另有一个摄像机和一个点光源
+    pub(crate) const TRIANGLE: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"nodes": [0, 2, 3]}],
+        "nodes": [
+            {"translation": [0, 0, -5], "children": [1]},
+            {"mesh": 0},
+            {"camera": 0, "translation": [0, 0, 5]},
+            {"translation": [0, 3, 0], "extensions": {"KHR_lights_punctual": {"light": 0}}}
+        ],
+        "extensionsUsed": ["KHR_lights_punctual"],
+        "extensions": {"KHR_lights_punctual": {"lights": [{"type": "point", "intensity": 2}]}},
+        "cameras": [{"type": "perspective", "perspective": {"yfov": 0.8, "znear": 0.1}}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "material": 0}]}],
+        "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}}],
+        "accessors": [{
+            "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+            "min": [-1, -1, 0], "max": [1, 1, 0]
+        }],
+        "bufferViews": [{"buffer": 0, "byteLength": 36}],
+        "buffers": [{"byteLength": 36, "uri": "data:application/octet-stream;base64,AACAvwAAgL8AAAAAAACAPwAAgL8AAAAAAAAAAAAAgD8AAAAA"}]
+    }"#;
+
+    #[test]
+    fn test_import_triangle() {
+        let scene = load_slice(TRIANGLE.as_bytes(), &ImportOptions::default()).unwrap();
+        // 三角形和点光源的发光球
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(scene.cameras.len(), 1);
+        let camera = &scene.cameras[0];
+        assert_eq!(
+            camera.transform.point(&Vec3::zero()),
+            Vec3::new(0.0, 0.0, 5.0)
+        );
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = scene.objects[0].hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+        let albedo = hit.material.albedo(&hit);
+        assert_eq!(albedo, Vec3::new(1.0, 0.0, 0.0));
+
+        let to_light = Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0));
+        let light = scene.objects[1].hit(&to_light, 0.001, Float::MAX).unwrap();
+        assert!(light.material.emitted(&to_light, &light).x() > 0.0);
+    }
+}
@@ -0,0 +1,364 @@
+//! 高度场地形：规则网格上的高度采样，每个格子分成两个三角形
+//!
+//! 求交时用最小/最大高度的mipmap做四叉树遍历，射线经过的区域整块高于或低于地形时直接跳过
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Float, Vec3};
+use image::ImageResult;
+use std::path::Path;
+use std::rc::Rc;
+
+/// mipmap的一层，每个元素是一块格子的最低和最高高度
+struct Level {
+    width: usize,
+    depth: usize,
+    ranges: Vec<(Float, Float)>,
+}
+
+/// 高度场，采样点均匀分布在`corner`到`corner + size`的`xz`矩形上，
+/// 高度`[0, 1]`映射到`corner.y`到`corner.y + size.y`
+pub struct Heightfield {
+    /// 采样点的个数，格子的个数各少一个
+    width: usize,
+    depth: usize,
+    heights: Vec<Float>,
+    normals: Vec<Vec3>,
+    corner: Vec3,
+    size: Vec3,
+    levels: Vec<Level>,
+    material: Rc<dyn Material>,
+}
+
+impl Heightfield {
+    /// `heights`按行存放，每行`width`个沿`x`方向的采样，共`depth`行沿`z`方向排列
+    pub fn new(
+        width: usize,
+        depth: usize,
+        heights: Vec<Float>,
+        corner: Vec3,
+        size: Vec3,
+        material: Rc<dyn Material>,
+    ) -> Self {
+        assert!(
+            width >= 2 && depth >= 2,
+            "heightfield needs at least 2x2 samples"
+        );
+        assert_eq!(heights.len(), width * depth);
+        let mut heightfield = Heightfield {
+            width,
+            depth,
+            heights,
+            normals: Vec::new(),
+            corner,
+            size,
+            levels: Vec::new(),
+            material,
+        };
+        heightfield.normals = (0..width * depth)
+            .map(|k| heightfield.vertex_normal(k % width, k / width))
+            .collect();
+        heightfield.build_levels();
+        heightfield
+    }
+
+    /// 从灰度图读取高度，图片的左上角对应`corner`，向右是`x`，向下是`z`
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        corner: Vec3,
+        size: Vec3,
+        material: Rc<dyn Material>,
+    ) -> ImageResult<Self> {
+        let (width, depth, heights) = read_heights(path)?;
+        Ok(Self::new(width, depth, heights, corner, size, material))
+    }
+
+    fn point(&self, i: usize, j: usize) -> Vec3 {
+        self.corner
+            + Vec3::new(
+                i as Float / (self.width - 1) as Float * self.size.x(),
+                self.heights[j * self.width + i] * self.size.y(),
+                j as Float / (self.depth - 1) as Float * self.size.z(),
+            )
+    }
+
+    /// 用相邻采样的中心差分估计顶点法向
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.width - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.depth - 1));
+        let dx = self.point(i1, j) - self.point(i0, j);
+        let dz = self.point(i, j1) - self.point(i, j0);
+        dz.cross(&dx).unit_vector()
+    }
+
+    /// 第0层是每个格子四个角的高度范围，之后每层把相邻2×2块合并
+    fn build_levels(&mut self) {
+        let (width, depth) = (self.width - 1, self.depth - 1);
+        let mut ranges = Vec::with_capacity(width * depth);
+        for j in 0..depth {
+            for i in 0..width {
+                let corners = [
+                    self.heights[j * self.width + i],
+                    self.heights[j * self.width + i + 1],
+                    self.heights[(j + 1) * self.width + i],
+                    self.heights[(j + 1) * self.width + i + 1],
+                ];
+                let low = corners.iter().cloned().fold(Float::MAX, Float::min);
+                let high = corners.iter().cloned().fold(Float::MIN, Float::max);
+                ranges.push((low, high));
+            }
+        }
+        self.levels.push(Level {
+            width,
+            depth,
+            ranges,
+        });
+        loop {
+            let below = self.levels.last().unwrap();
+            if below.width == 1 && below.depth == 1 {
+                break;
+            }
+            let width = below.width.div_ceil(2);
+            let depth = below.depth.div_ceil(2);
+            let mut ranges = vec![(Float::MAX, Float::MIN); width * depth];
+            for j in 0..below.depth {
+                for i in 0..below.width {
+                    let (low, high) = below.ranges[j * below.width + i];
+                    let range = &mut ranges[(j / 2) * width + i / 2];
+                    range.0 = range.0.min(low);
+                    range.1 = range.1.max(high);
+                }
+            }
+            self.levels.push(Level {
+                width,
+                depth,
+                ranges,
+            });
+        }
+    }
+
+    /// 第`level`层第`(i, j)`块的包围盒
+    fn node_bounds(&self, level: usize, i: usize, j: usize) -> Aabb {
+        let cells = 1 << level;
+        let (low, high) = self.levels[level].ranges[j * self.levels[level].width + i];
+        let x0 = i * cells;
+        let z0 = j * cells;
+        let x1 = ((i + 1) * cells).min(self.width - 1);
+        let z1 = ((j + 1) * cells).min(self.depth - 1);
+        let cell_x = self.size.x() / (self.width - 1) as Float;
+        let cell_z = self.size.z() / (self.depth - 1) as Float;
+        // 稍微加厚，避免平坦的地形包围盒厚度为0
+        let pad = 1e-4;
+        Aabb::new(
+            self.corner
+                + Vec3::new(
+                    x0 as Float * cell_x - pad,
+                    low * self.size.y() - pad,
+                    z0 as Float * cell_z - pad,
+                ),
+            self.corner
+                + Vec3::new(
+                    x1 as Float * cell_x + pad,
+                    high * self.size.y() + pad,
+                    z1 as Float * cell_z + pad,
+                ),
+        )
+    }
+
+    /// 和格子`(i, j)`的两个三角形求交
+    fn hit_cell(
+        &self,
+        i: usize,
+        j: usize,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<HitRecord> {
+        let corners = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)];
+        let mut closest = t_max;
+        let mut result = None;
+        for triangle in [[0, 2, 1], [1, 2, 3]].iter() {
+            let vertices = [
+                corners[triangle[0]],
+                corners[triangle[1]],
+                corners[triangle[2]],
+            ];
+            let hit = hit_triangle(
+                ray,
+                [
+                    self.point(vertices[0].0, vertices[0].1),
+                    self.point(vertices[1].0, vertices[1].1),
+                    self.point(vertices[2].0, vertices[2].1),
+                ],
+                t_min,
+                closest,
+            );
+            if let Some((t, b1, b2)) = hit {
+                closest = t;
+                result = Some((vertices, t, b1, b2));
+            }
+        }
+        let (vertices, t, b1, b2) = result?;
+        let b0 = 1.0 - b1 - b2;
+        let normal = |(i, j): (usize, usize)| self.normals[j * self.width + i];
+        let normal =
+            (b0 * normal(vertices[0]) + b1 * normal(vertices[1]) + b2 * normal(vertices[2]))
+                .unit_vector();
+        let point = ray.point_at_parameter(&t);
+        let u = (point.x() - self.corner.x()) / self.size.x();
+        let v = (point.z() - self.corner.z()) / self.size.z();
+        let [p0, p1, p2] = [
+            self.point(vertices[0].0, vertices[0].1),
+            self.point(vertices[1].0, vertices[1].1),
+            self.point(vertices[2].0, vertices[2].1),
+        ];
+        let geometric_normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+        let geometric_normal = if geometric_normal.dot(ray.direction()) < 0.0 {
+            geometric_normal
+        } else {
+            -geometric_normal
+        };
+        Some(HitRecord {
+            u,
+            v,
+            geometric_normal,
+            dpdu: Vec3::new(self.size.x(), 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, self.size.z()),
+            ..HitRecord::new(t, point, normal, Rc::clone(&self.material), ray)
+        })
+    }
+}
+
+/// 读取灰度图，返回宽、高和`[0, 1]`范围的高度
+///
+/// 16位的图片保留全部精度，其它格式转换成8位灰度
+pub fn read_heights<P: AsRef<Path>>(path: P) -> ImageResult<(usize, usize, Vec<Float>)> {
+    let img = image::open(path)?;
+    let (width, depth, heights) = match img.as_luma16() {
+        Some(img) => (
+            img.width(),
+            img.height(),
+            img.pixels().map(|p| p[0] as Float / 65535.0).collect(),
+        ),
+        None => {
+            let img = img.into_luma();
+            (
+                img.width(),
+                img.height(),
+                img.pixels().map(|p| p[0] as Float / 255.0).collect(),
+            )
+        }
+    };
+    Ok((width as usize, depth as usize, heights))
+}
+
+/// Möller–Trumbore算法，返回`t`和后两个顶点的重心坐标
+fn hit_triangle(
+    ray: &Ray,
+    [p0, p1, p2]: [Vec3; 3],
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float, Float)> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let pvec = ray.direction().cross(&e2);
+    let det = e1.dot(&pvec);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin() - p0;
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(&e1);
+    let b2 = ray.direction().dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = e2.dot(&qvec) * inv_det;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+impl Hittable for Heightfield {
+    /// 从最粗的一层开始，按射线进入的先后访问子块，找到的第一个交点就是最近的
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let top = self.levels.len() - 1;
+        let mut stack = vec![(top, 0, 0)];
+        while let Some((level, i, j)) = stack.pop() {
+            if level == 0 {
+                if let Some(hit) = self.hit_cell(i, j, ray, t_min, t_max) {
+                    return Some(hit);
+                }
+                continue;
+            }
+            let below = &self.levels[level - 1];
+            let mut children = Vec::with_capacity(4);
+            for (ci, cj) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                let (ci, cj) = (2 * i + ci, 2 * j + cj);
+                if ci >= below.width || cj >= below.depth {
+                    continue;
+                }
+                let bounds = self.node_bounds(level - 1, ci, cj);
+                if let Some((t0, _)) = bounds.hit_interval(ray, t_min, t_max) {
+                    children.push((t0, ci, cj));
+                }
+            }
+            // 后进先出，先进入的子块最后压栈
+            children.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+            stack.extend(children.into_iter().map(|(_, i, j)| (level - 1, i, j)));
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.node_bounds(self.levels.len() - 1, 0, 0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_hit_matches_analytic_slope() {
+        // 高度沿x线性增长的斜坡，y = x / 2
+        let (width, depth) = (33, 17);
+        let heights = (0..width * depth)
+            .map(|k| (k % width) as Float / (width - 1) as Float)
+            .collect();
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        let field = Heightfield::new(
+            width,
+            depth,
+            heights,
+            Vec3::zero(),
+            Vec3::new(4.0, 2.0, 4.0),
+            material,
+        );
+        assert_eq!(field.levels.len(), 6);
+
+        for &(x, z) in &[(0.3, 0.2), (2.0, 3.9), (3.7, 1.1)] {
+            let ray = Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -1.0, 0.0));
+            let hit = field.hit(&ray, 0.001, Float::MAX).unwrap();
+            assert!((hit.point.y() - x / 2.0).abs() < 1e-4);
+            assert!((hit.normal - Vec3::new(-1.0, 2.0, 0.0).unit_vector()).length() < 1e-4);
+            assert!((hit.u - x / 4.0).abs() < 1e-4 && (hit.v - z / 4.0).abs() < 1e-4);
+        }
+
+        // 斜着射入，先穿过高处的包围盒再落到低处
+        let ray = Ray::new(Vec3::new(5.0, 1.0, 2.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = field.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.point.x() - 2.0).abs() < 1e-4);
+
+        let miss = Ray::new(Vec3::new(-1.0, 3.0, 2.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(field.hit(&miss, 0.001, Float::MAX).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::aabb::Aabb;
 use crate::material::*;
 use crate::ray::Ray;
 use crate::vec3::{Float, Vec3};
@@ -8,10 +9,24 @@ pub struct HitRecord {
     pub t: Float,
     /// 交汇点
     pub point: Vec3,
-    /// 法向量
+    /// 着色用的法向量，可能被插值或法线贴图扰动过
     pub normal: Vec3,
+    /// 几何表面的法向，和`normal`一样朝向射线来的一侧
+    pub geometric_normal: Vec3,
     pub material: Rc<dyn Material>,
     pub front_face: bool,
+    /// 物体在场景中的编号，从1开始，由`Bvh`设置
+    pub object_id: usize,
+    /// 纹理坐标
+    pub u: Float,
+    pub v: Float,
+    /// 交点随纹理坐标变化的方向，用于法线贴图的切线空间，没有纹理坐标时为0
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    /// 网格插值得到的顶点颜色，材质把它乘到反照率上
+    pub vertex_color: Option<Vec3>,
+    /// 交点在三角形上的重心坐标，不是三角形时为`None`
+    pub barycentric: Option<Vec3>,
 }
 
 impl HitRecord {
@@ -24,9 +39,9 @@ impl HitRecord {
     //         front_face: true,
     //     }
     // }
-    
+
     /// 新建一个碰撞检测
-    /// 
+    ///
     /// 设置表面是否为前面，通过视线和法向的夹角来确定
     /// 保证这里的normal一定是和视线夹角大于180°
     pub fn new(
@@ -47,8 +62,16 @@ impl HitRecord {
             t,
             point,
             normal,
+            geometric_normal: normal,
             material,
             front_face,
+            object_id: 0,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            vertex_color: None,
+            barycentric: None,
         }
     }
 
@@ -64,8 +87,67 @@ impl HitRecord {
     // }
 }
 
+/// 射线在物体内部的一段，进入或离开的点在查询区间之外时为`None`
+pub struct Span {
+    pub enter: Option<HitRecord>,
+    pub exit: Option<HitRecord>,
+}
+
+impl Span {
+    pub fn enter_t(&self) -> Float {
+        self.enter.as_ref().map_or(Float::NEG_INFINITY, |h| h.t)
+    }
+
+    pub fn exit_t(&self) -> Float {
+        self.exit.as_ref().map_or(Float::INFINITY, |h| h.t)
+    }
+}
+
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord>;
+
+    /// 包围盒，无限大的物体返回`None`
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// 射线在`(t_min, t_max)`内穿过物体内部的所有区间，按`t`排列，用于构造实体几何
+    ///
+    /// 默认沿射线逐个查找交点，由`front_face`判断是进入还是离开，只对封闭的表面有意义
+    fn intervals(&self, ray: &Ray, t_min: Float, t_max: Float) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut enter = None;
+        let mut t = t_min;
+        // 防止退化的表面陷入死循环
+        for _ in 0..64 {
+            let hit_record = match self.hit(ray, t, t_max) {
+                Some(h) => h,
+                None => break,
+            };
+            t = hit_record.t + 1e-4;
+            if hit_record.front_face {
+                enter = Some(hit_record);
+            } else {
+                spans.push(Span {
+                    enter: enter.take(),
+                    exit: Some(hit_record),
+                });
+            }
+        }
+        if enter.is_some() {
+            spans.push(Span { enter, exit: None });
+        }
+        spans
+    }
+
+    /// 在表面上按面积均匀抽样一点，`u`、`v`为`[0, 1)`上的随机数，
+    /// 返回的碰撞记录法向朝外，用于在面光源上抽样。默认不支持抽样
+    fn sample_surface(&self, _u: Float, _v: Float) -> Option<HitRecord> {
+        None
+    }
+
+    /// 表面积，不支持`sample_surface`时为0
+    fn area(&self) -> Float {
+        0.0
+    }
 }
 
 pub struct Sphere {
@@ -84,6 +166,39 @@ impl Sphere {
     }
 }
 
+impl Sphere {
+    /// 经纬度映射的纹理坐标：`u`沿经度从-x方向开始绕一圈，`v`从北极（+y）到南极
+    fn hit_record(&self, t: Float, point: Vec3, outward_normal: Vec3, ray: &Ray) -> HitRecord {
+        let n = if self.radius < 0.0 {
+            -outward_normal
+        } else {
+            outward_normal
+        };
+        let phi = (-n.z()).atan2(n.x()) + std::f32::consts::PI;
+        let theta = n.y().clamp(-1.0, 1.0).acos();
+        let r = self.radius.abs();
+        let sin_theta = theta.sin();
+        let pi = std::f32::consts::PI;
+        let dpdv = if sin_theta > 1e-6 {
+            pi * r
+                * Vec3::new(
+                    n.y() * n.x() / sin_theta,
+                    -sin_theta,
+                    n.y() * n.z() / sin_theta,
+                )
+        } else {
+            Vec3::zero()
+        };
+        HitRecord {
+            u: phi / (2.0 * pi),
+            v: theta / pi,
+            dpdu: 2.0 * pi * r * Vec3::new(n.z(), 0.0, -n.x()),
+            dpdv,
+            ..HitRecord::new(t, point, outward_normal, Rc::clone(&self.material), ray)
+        }
+    }
+}
+
 impl Hittable for Sphere {
     /// 注意：这里作者把delta的系数进行了约分，所以求根公式里的系数消掉了
     /// 同时有两个求解的过程，分别对应着两个根，即和球的两个交点
@@ -106,28 +221,39 @@ impl Hittable for Sphere {
             let root1 = (-b - (b * b - a * c).sqrt()) / a;
             if root1 < t_max && root1 > t_min {
                 let point = ray.point_at_parameter(&root1);
-                let hit_record = HitRecord::new(
-                    root1,
-                    point,
-                    (point - self.center) / self.radius,
-                    Rc::clone(&self.material),
-                    ray,
-                );
+                let hit_record =
+                    self.hit_record(root1, point, (point - self.center) / self.radius, ray);
                 return Some(hit_record);
             }
             let root2 = (-b + (b * b - a * c).sqrt()) / a;
             if root2 < t_max && root2 > t_min {
                 let point = ray.point_at_parameter(&root2);
-                let hit_record = HitRecord::new(
-                    root2,
-                    point,
-                    (point - self.center) / self.radius,
-                    Rc::clone(&self.material),
-                    ray,
-                );
+                let hit_record =
+                    self.hit_record(root2, point, (point - self.center) / self.radius, ray);
                 return Some(hit_record);
             }
         }
         None
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius.abs();
+        let r = Vec3::new(r, r, r);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn sample_surface(&self, u: Float, v: Float) -> Option<HitRecord> {
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * v;
+        let n = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+        let point = self.center + self.radius.abs() * n;
+        let outward_normal = (point - self.center) / self.radius;
+        let ray = Ray::new(point + outward_normal, -outward_normal);
+        Some(self.hit_record(1.0, point, outward_normal, &ray))
+    }
+
+    fn area(&self) -> Float {
+        4.0 * std::f32::consts::PI * self.radius * self.radius
+    }
 }
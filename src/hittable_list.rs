@@ -1,22 +1,52 @@
+use crate::aabb::Aabb;
 use crate::hittable::*;
 use crate::ray::Ray;
 use crate::vec3::*;
+use std::rc::Rc;
 
-// struct HittableList {
-//     list: Vec<dyn Hittable>,
-// }
-
-pub fn hit(list: &Vec<Box<dyn Hittable>>, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
-    let mut closest_so_far = t_max;
-    let mut hit_record = None;
-    for i in list.iter() {
-        hit_record = match i.hit(ray, t_min, closest_so_far) {
-            Some(hit_record) => {
-                closest_so_far = hit_record.t;
-                Some(hit_record)
+/// 逐个检测列表中的物体，返回最近的碰撞
+impl Hittable for Vec<Box<dyn Hittable>> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+        for i in self.iter() {
+            hit_record = match i.hit(ray, t_min, closest_so_far) {
+                Some(hit_record) => {
+                    closest_so_far = hit_record.t;
+                    Some(hit_record)
+                }
+                None => hit_record,
             }
-            None => hit_record,
         }
+        hit_record
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.iter().map(|i| i.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, bbox| Some(acc.surrounding(&bbox?)))
+    }
+}
+
+/// 共享的物体，例如同时放进加速结构和光源列表的面光源
+impl<T: Hittable + ?Sized> Hittable for Rc<T> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn intervals(&self, ray: &Ray, t_min: Float, t_max: Float) -> Vec<Span> {
+        (**self).intervals(ray, t_min, t_max)
+    }
+
+    fn sample_surface(&self, u: Float, v: Float) -> Option<HitRecord> {
+        (**self).sample_surface(u, v)
+    }
+
+    fn area(&self) -> Float {
+        (**self).area()
     }
-    hit_record
 }
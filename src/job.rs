@@ -0,0 +1,652 @@
+//! 命令行的一次渲染任务：选择场景和摄像机，渲染一个或多个视角、立体的左右眼或者动画帧，
+//! 保存为图像文件
+//!
+//! 各个字段对应的命令行参数见README
+
+use crate::animation::{AnimatedCamera, Interpolation, Track};
+use crate::camera::*;
+use crate::gltf_import::{self, ImportOptions};
+use crate::heightfield;
+use crate::hittable::Sphere;
+use crate::material::Material;
+use crate::merl;
+use crate::render::{Image, Integrator, RenderSettings, Renderer};
+use crate::scene::{self, MeshOptions, Scene};
+use crate::vec3::{Float, Vec3};
+use std::error::Error;
+use std::rc::Rc;
+
+/// 读取文件、保存图像或者参数互相矛盾时的错误
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// 带输出文件名的摄像机
+type NamedCameras = Vec<(String, Box<dyn Camera>)>;
+
+/// 摄像机的投影方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    /// 等距柱状投影的360°全景
+    Panorama,
+    Fisheye,
+    /// 用双高斯镜头组追踪光线
+    Lens,
+}
+
+/// 内置场景，见`scene`模块中的同名函数
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BuiltinScene {
+    Random,
+    Shapes,
+    Sdf,
+    Terrain,
+    Hair,
+    Layered,
+    Room,
+    Prism,
+}
+
+/// 一次渲染任务
+pub struct RenderJob {
+    pub width: u32,
+    pub samples_per_pixel: u32,
+    pub denoise: bool,
+    /// 同时把所有渲染通道保存为EXR文件
+    pub aov: bool,
+    /// 光谱模式
+    pub spectral: bool,
+    pub integrator: Integrator,
+    pub projection: Projection,
+    /// 指定焦距（毫米）时用全画幅镜头的物理参数代替视角和光圈
+    pub focal_length: Option<Float>,
+    pub f_stop: Float,
+    /// 对焦点，默认对焦在`lookat`前方10个单位处
+    pub focus_point: Option<Vec3>,
+    pub lens_shift: (Float, Float),
+    pub aperture_blades: Option<u32>,
+    pub aperture_rotation: Float,
+    pub aperture_mask: Option<String>,
+    pub optical_vignetting: Option<OpticalVignetting>,
+    pub stereo: Option<StereoRig>,
+    /// 多个视角的摄像机位置，共用同一个场景，为空时只有默认的一个视角
+    pub views: Vec<Vec3>,
+    /// 渲染转台动画的帧范围（含两端）
+    pub frames: Option<(u32, u32)>,
+    pub fps: Float,
+    /// 代替内置场景的glTF文件
+    pub gltf: Option<String>,
+    pub scene: BuiltinScene,
+    /// 放进随机小球场景的PLY或STL网格
+    pub mesh: Option<String>,
+    pub mesh_options: MeshOptions,
+    /// 网格使用的MERL测量BRDF，没有网格时用在玻璃球位置上的一个球上
+    pub merl: Option<String>,
+    /// `terrain`场景的高度图
+    pub heightfield: Option<String>,
+}
+
+impl Default for RenderJob {
+    fn default() -> Self {
+        RenderJob {
+            width: 1200,
+            samples_per_pixel: 500,
+            denoise: false,
+            aov: false,
+            spectral: false,
+            integrator: Integrator::PathTracing,
+            projection: Projection::Perspective,
+            focal_length: None,
+            f_stop: 8.0,
+            focus_point: None,
+            lens_shift: (0.0, 0.0),
+            aperture_blades: None,
+            aperture_rotation: 0.0,
+            aperture_mask: None,
+            optical_vignetting: None,
+            stereo: None,
+            views: Vec::new(),
+            frames: None,
+            fps: 24.0,
+            gltf: None,
+            scene: BuiltinScene::Random,
+            mesh: None,
+            mesh_options: MeshOptions::default(),
+            merl: None,
+            heightfield: None,
+        }
+    }
+}
+
+impl RenderJob {
+    /// 渲染并保存所有图像，每保存一个PNG文件就用文件名调用一次`saved`
+    pub fn run<F: FnMut(&str)>(&self, mut saved: F) -> Result<()> {
+        if self.spectral && self.integrator != Integrator::PathTracing {
+            return Err("--spectral only works with --integrator path".into());
+        }
+        let settings = self.settings();
+        if let Some((first, last)) = self.frames {
+            return self.render_frames(settings, first, last, &mut saved);
+        }
+        if let Some(path) = &self.gltf {
+            return self.render_gltf(settings, path, &mut saved);
+        }
+        // 场景和加速结构只建一次，所有视角共用
+        let scene = self.build_scene()?;
+        let renderer = Renderer::new(settings);
+        for (name, camera) in self.cameras()? {
+            let image = renderer.render(&scene, camera.as_ref());
+            self.save(&image, &name, &mut saved)?;
+        }
+        Ok(())
+    }
+
+    fn aspect_ratio(&self) -> Float {
+        if self.projection == Projection::Panorama {
+            2.0
+        } else {
+            3.0 / 2.0
+        }
+    }
+
+    fn settings(&self) -> RenderSettings {
+        RenderSettings {
+            width: self.width,
+            height: (self.width as Float / self.aspect_ratio()) as u32,
+            samples_per_pixel: self.samples_per_pixel,
+            denoise: self.denoise,
+            spectral: self.spectral,
+            integrator: self.integrator,
+            ..RenderSettings::default()
+        }
+    }
+
+    fn save<F: FnMut(&str)>(&self, image: &Image, name: &str, saved: &mut F) -> Result<()> {
+        if self.aov {
+            image.save_exr(format!("{}.exr", name))?;
+        }
+        let path = format!("{}.png", name);
+        image.save(&path)?;
+        saved(&path);
+        Ok(())
+    }
+
+    /// 选出的内置场景，随机小球场景里可以再放一个网格或者测量材质的球
+    pub fn build_scene(&self) -> Result<Scene> {
+        let measured = match &self.merl {
+            Some(path) => {
+                let brdf =
+                    merl::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+                Some(Rc::new(brdf) as Rc<dyn Material>)
+            }
+            None => None,
+        };
+        Ok(match self.scene {
+            BuiltinScene::Random => match (&self.mesh, measured) {
+                (Some(path), material) => {
+                    let mut world = scene::random_scene_builder();
+                    world.add(Box::new(scene::load_mesh(
+                        path,
+                        &self.mesh_options,
+                        material,
+                    )?));
+                    world.build()
+                }
+                (None, Some(material)) => {
+                    let mut world = scene::random_scene_builder();
+                    world.add(Box::new(Sphere::new(
+                        Vec3::new(0.0, 1.0, 2.5),
+                        1.0,
+                        material,
+                    )));
+                    world.build()
+                }
+                (None, None) => scene::random_scene(),
+            },
+            BuiltinScene::Shapes => scene::shapes_scene(),
+            BuiltinScene::Sdf => scene::sdf_scene(),
+            BuiltinScene::Hair => scene::hair_scene(),
+            BuiltinScene::Layered => scene::layered_scene(),
+            BuiltinScene::Room => scene::room_scene(),
+            BuiltinScene::Prism => scene::prism_scene(),
+            BuiltinScene::Terrain => {
+                let heights = match &self.heightfield {
+                    Some(path) => Some(
+                        heightfield::read_heights(path)
+                            .map_err(|e| format!("Failed to load {}: {}", path, e))?,
+                    ),
+                    None => None,
+                };
+                scene::terrain_scene(heights)
+            }
+        })
+    }
+
+    /// 所有视角的摄像机和输出文件名（不含扩展名）：多个视角时加上`_序号`，立体模式下再加上`_left`和`_right`
+    fn cameras(&self) -> Result<NamedCameras> {
+        self.cameras_looking_at(&Vec3::new(13.0, 2.0, 3.0), &Vec3::zero(), self.focus_point)
+    }
+
+    /// 从每个视角看向`lookat`的摄像机和输出文件名，没有设置视角时只有`default_view`
+    fn cameras_looking_at(
+        &self,
+        default_view: &Vec3,
+        lookat: &Vec3,
+        focus_point: Option<Vec3>,
+    ) -> Result<NamedCameras> {
+        let views = if self.views.is_empty() {
+            vec![*default_view]
+        } else {
+            self.views.clone()
+        };
+        let mut cameras = Vec::new();
+        for (i, lookfrom) in views.iter().enumerate() {
+            let view_suffix = if views.len() > 1 {
+                format!("_{}", i)
+            } else {
+                String::new()
+            };
+            for (eye_suffix, camera) in self.view_cameras(lookfrom, lookat, focus_point)? {
+                cameras.push((format!("final{}{}", view_suffix, eye_suffix), camera));
+            }
+        }
+        Ok(cameras)
+    }
+
+    /// 一个视角的所有摄像机，立体模式下为左右两眼，返回(文件名后缀, 摄像机)
+    fn view_cameras(
+        &self,
+        lookfrom: &Vec3,
+        lookat: &Vec3,
+        focus_point: Option<Vec3>,
+    ) -> Result<Vec<(&'static str, Box<dyn Camera>)>> {
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+        let aspect_ratio = self.aspect_ratio();
+        if let Some(rig) = self.stereo {
+            if self.projection != Projection::Perspective {
+                return Err("Stereo rendering needs the perspective camera".into());
+            }
+            let aperture = self.aperture()?;
+            let (left, right) = rig.eyes(lookfrom, lookat, &vup, |lookfrom, lookat| {
+                self.perspective_camera(lookfrom, lookat, focus_point, aperture.clone())
+            });
+            return Ok(vec![("_left", Box::new(left)), ("_right", Box::new(right))]);
+        }
+
+        let dist_to_focus = match focus_point {
+            Some(point) => focus_distance(lookfrom, lookat, &point),
+            None => 10.0,
+        };
+        let camera: Box<dyn Camera> = match self.projection {
+            Projection::Perspective => {
+                Box::new(self.perspective_camera(lookfrom, lookat, focus_point, self.aperture()?))
+            }
+            Projection::Orthographic => Box::new(OrthographicCamera::new(
+                lookfrom,
+                lookat,
+                &vup,
+                5.0,
+                aspect_ratio,
+            )),
+            Projection::Panorama => Box::new(EquirectangularCamera::new(lookfrom, lookat, &vup)),
+            Projection::Fisheye => Box::new(FisheyeCamera::new(
+                lookfrom,
+                lookat,
+                &vup,
+                180.0,
+                aspect_ratio,
+            )),
+            Projection::Lens => Box::new(RealisticCamera::new(
+                lookfrom,
+                lookat,
+                &vup,
+                double_gauss_50mm(),
+                36.0,
+                aspect_ratio,
+                dist_to_focus,
+            )),
+        };
+        Ok(vec![("", camera)])
+    }
+
+    fn aperture(&self) -> Result<Aperture> {
+        Ok(match (&self.aperture_mask, self.aperture_blades) {
+            (Some(path), _) => Aperture::Mask(
+                ApertureMask::open(path).map_err(|e| format!("Failed to load {}: {}", path, e))?,
+            ),
+            (None, Some(blades)) if blades < 3 => {
+                return Err(format!("aperture needs at least 3 blades, got {}", blades).into())
+            }
+            (None, Some(blades)) => Aperture::Polygon {
+                blades,
+                rotation: self.aperture_rotation,
+            },
+            (None, None) => Aperture::Circle,
+        })
+    }
+
+    fn perspective_camera(
+        &self,
+        lookfrom: &Vec3,
+        lookat: &Vec3,
+        focus_point: Option<Vec3>,
+        aperture: Aperture,
+    ) -> PerspectiveCamera {
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+        let aspect_ratio = self.aspect_ratio();
+        let dist_to_focus = match focus_point {
+            Some(point) => focus_distance(lookfrom, lookat, &point),
+            None => 10.0,
+        };
+        let camera = match self.focal_length {
+            None => PerspectiveCamera::new(
+                lookfrom,
+                lookat,
+                &vup,
+                20.0,
+                aspect_ratio,
+                0.1,
+                dist_to_focus,
+            ),
+            Some(focal_length) => PerspectiveCamera::physical(
+                lookfrom,
+                lookat,
+                &vup,
+                &PhysicalLens::full_frame(focal_length, self.f_stop),
+                aspect_ratio,
+                dist_to_focus,
+            ),
+        };
+        let mut camera = camera
+            .with_lens_shift(self.lens_shift.0, self.lens_shift.1)
+            .with_aperture(aperture);
+        if let Some(vignetting) = self.optical_vignetting {
+            camera = camera.with_optical_vignetting(vignetting);
+        }
+        camera
+    }
+
+    /// 选择内置场景和往里面添加物体的参数，以及它们是否设置了
+    fn scene_options(&self) -> [(bool, &'static str); 4] {
+        [
+            (self.scene != BuiltinScene::Random, "--scene"),
+            (self.mesh.is_some(), "--mesh"),
+            (self.merl.is_some(), "--merl"),
+            (self.heightfield.is_some(), "--heightfield"),
+        ]
+    }
+
+    /// 摄像机的参数，以及它们是否设置了
+    fn camera_options(&self) -> [(bool, &'static str); 9] {
+        [
+            (!self.views.is_empty(), "--view"),
+            (self.stereo.is_some(), "--stereo"),
+            (self.projection != Projection::Perspective, "--camera"),
+            (self.focal_length.is_some(), "--focal-length"),
+            (self.focus_point.is_some(), "--focus-point"),
+            (self.lens_shift != (0.0, 0.0), "--lens-shift"),
+            (self.aperture_blades.is_some(), "--aperture-blades"),
+            (self.aperture_mask.is_some(), "--aperture-mask"),
+            (self.optical_vignetting.is_some(), "--optical-vignetting"),
+        ]
+    }
+
+    /// 和`frames`一起设置时会被忽略的参数，返回第一个的命令行名字
+    fn conflicting_with_frames(&self) -> Option<&'static str> {
+        let gltf = [(self.gltf.is_some(), "--gltf")];
+        first_set(
+            gltf.iter()
+                .chain(&self.scene_options())
+                .chain(&self.camera_options()),
+        )
+    }
+
+    /// 渲染转台动画的帧序列，第0帧对应时刻0，快门角度为180°，即每帧曝光半个帧间隔
+    ///
+    /// 动画的场景和摄像机都是固定的，设置了其他场景或摄像机参数时返回错误
+    fn render_frames<F: FnMut(&str)>(
+        &self,
+        settings: RenderSettings,
+        first: u32,
+        last: u32,
+        saved: &mut F,
+    ) -> Result<()> {
+        if let Some(name) = self.conflicting_with_frames() {
+            return Err(format!(
+                "--frames renders the built-in turntable animation and can't be combined with {}",
+                name
+            )
+            .into());
+        }
+        let scene = scene::animated_scene();
+        let camera = turntable_camera(4.0, settings.width as Float / settings.height as Float);
+        for frame in first..=last {
+            let time = frame as Float / self.fps;
+            let renderer = Renderer::new(RenderSettings {
+                shutter: (time, time + 0.5 / self.fps),
+                ..settings.clone()
+            });
+            let image = renderer.render(&scene, &camera);
+            self.save(&image, &format!("frame_{:04}", frame), saved)?;
+        }
+        Ok(())
+    }
+
+    /// 渲染glTF场景的每个视角
+    fn render_gltf<F: FnMut(&str)>(
+        &self,
+        settings: RenderSettings,
+        path: &str,
+        saved: &mut F,
+    ) -> Result<()> {
+        let (scene, cameras) = self.load_gltf(path)?;
+        let renderer = Renderer::new(settings);
+        for (name, camera) in cameras {
+            let image = renderer.render(&scene, camera.as_ref());
+            self.save(&image, &name, saved)?;
+        }
+        Ok(())
+    }
+
+    /// 读取glTF场景和渲染它的摄像机，优先使用文件里的第一个摄像机，这时不能再设置摄像机的参数；
+    /// 没有摄像机时从右上方或者`views`对准整个场景，和内置场景一样生成每个视角
+    fn load_gltf(&self, path: &str) -> Result<(Scene, NamedCameras)> {
+        if let Some(name) = first_set(&self.scene_options()) {
+            return Err(format!(
+                "--gltf replaces the built-in scene and can't be combined with {}",
+                name
+            )
+            .into());
+        }
+        let imported = gltf_import::load(path, &ImportOptions::default())
+            .map_err(|e| format!("Failed to load {}: {}", path, e))?;
+        let mut builder = Scene::builder();
+        let file_cameras = imported.add_to(&mut builder);
+        let scene = builder.build();
+
+        let cameras = match file_cameras.first() {
+            Some(camera) => {
+                if let Some(name) = first_set(&self.camera_options()) {
+                    return Err(format!(
+                        "{} has its own camera, which can't be combined with {}",
+                        path, name
+                    )
+                    .into());
+                }
+                vec![(String::from("final"), camera.to_camera(self.aspect_ratio()))]
+            }
+            None => {
+                let bbox = scene
+                    .world()
+                    .bounding_box()
+                    .ok_or("glTF scene has no bounded objects")?;
+                let lookat = bbox.centroid();
+                let diagonal = (bbox.max() - bbox.min()).length();
+                let lookfrom = lookat + 3.0 * diagonal * Vec3::new(13.0, 2.0, 3.0).unit_vector();
+                let focus_point = self.focus_point.unwrap_or(lookat);
+                self.cameras_looking_at(&lookfrom, &lookat, Some(focus_point))?
+            }
+        };
+        Ok((scene, cameras))
+    }
+}
+
+/// 第一个设置了的参数的名字
+fn first_set<'a, I: IntoIterator<Item = &'a (bool, &'static str)>>(
+    options: I,
+) -> Option<&'static str> {
+    options
+        .into_iter()
+        .find(|(set, _)| *set)
+        .map(|&(_, name)| name)
+}
+
+/// 绕场景中心一周的转台动画，在`duration`秒内转完一圈
+fn turntable_camera(duration: Float, aspect_ratio: Float) -> AnimatedCamera {
+    let radius = (13.0 as Float).hypot(3.0);
+    let start_angle = (3.0 as Float).atan2(13.0);
+    let steps = 8;
+    let mut lookfrom = Track::new();
+    for i in 0..=steps {
+        let angle = start_angle + 2.0 * std::f32::consts::PI * i as Float / steps as Float;
+        lookfrom = lookfrom.key(
+            duration * i as Float / steps as Float,
+            Vec3::new(radius * angle.cos(), 2.0, radius * angle.sin()),
+            Interpolation::Bezier,
+        );
+    }
+    AnimatedCamera {
+        lookfrom,
+        lookat: Track::constant(Vec3::new(0.0, 0.0, 0.0)),
+        vfov: Track::constant(20.0),
+        focus_dist: Track::constant(10.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        aspect_ratio,
+        aperture: 0.1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gltf_import::test::TRIANGLE;
+
+    #[test]
+    fn test_view_and_eye_names() {
+        let job = RenderJob {
+            views: vec![Vec3::new(13.0, 2.0, 3.0), Vec3::new(-13.0, 2.0, 3.0)],
+            stereo: Some(StereoRig::new(0.065, 10.0)),
+            ..RenderJob::default()
+        };
+        let names: Vec<String> = job.cameras().unwrap().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(
+            names,
+            [
+                "final_0_left",
+                "final_0_right",
+                "final_1_left",
+                "final_1_right"
+            ]
+        );
+
+        let job = RenderJob {
+            projection: Projection::Fisheye,
+            stereo: Some(StereoRig::new(0.065, 10.0)),
+            ..RenderJob::default()
+        };
+        assert!(job.cameras().is_err());
+        let job = RenderJob {
+            projection: Projection::Panorama,
+            ..RenderJob::default()
+        };
+        let cameras = job.cameras().unwrap();
+        assert_eq!(cameras.len(), 1);
+        assert_eq!(cameras[0].0, "final");
+        assert_eq!(job.settings().height, 600);
+    }
+
+    #[test]
+    fn test_frames_reject_scene_and_camera_options() {
+        let frames = || RenderJob {
+            frames: Some((0, 0)),
+            ..RenderJob::default()
+        };
+        assert_eq!(frames().conflicting_with_frames(), None);
+        let job = RenderJob {
+            scene: BuiltinScene::Room,
+            ..frames()
+        };
+        assert_eq!(job.conflicting_with_frames(), Some("--scene"));
+        let job = RenderJob {
+            stereo: Some(StereoRig::new(0.065, 10.0)),
+            ..frames()
+        };
+        let error = job
+            .run(|_| panic!("nothing should be rendered"))
+            .unwrap_err();
+        assert!(error.to_string().contains("--stereo"));
+    }
+
+    #[test]
+    fn test_gltf_cameras() {
+        let names = |job: &RenderJob, gltf: &str| {
+            let path = std::env::temp_dir().join(format!("job-{}.gltf", std::process::id()));
+            std::fs::write(&path, gltf).unwrap();
+            let loaded = job.load_gltf(path.to_str().unwrap());
+            std::fs::remove_file(&path).unwrap();
+            loaded.map(|(_, cameras)| cameras.into_iter().map(|(n, _)| n).collect::<Vec<_>>())
+        };
+        let stereo = RenderJob {
+            stereo: Some(StereoRig::new(0.065, 10.0)),
+            ..RenderJob::default()
+        };
+        // 文件里有摄像机时不能再设置摄像机
+        assert_eq!(names(&RenderJob::default(), TRIANGLE).unwrap(), ["final"]);
+        assert!(names(&stereo, TRIANGLE).is_err());
+
+        // 没有摄像机时渲染每个视角的左右眼
+        let without_camera = TRIANGLE.replace(r#"{"camera": 0, "translation": [0, 0, 5]},"#, "{},");
+        assert_eq!(
+            names(&stereo, &without_camera).unwrap(),
+            ["final_left", "final_right"]
+        );
+        let job = RenderJob {
+            views: vec![Vec3::new(0.0, 0.0, 5.0), Vec3::new(5.0, 0.0, 0.0)],
+            ..RenderJob::default()
+        };
+        assert_eq!(
+            names(&job, &without_camera).unwrap(),
+            ["final_0", "final_1"]
+        );
+        let job = RenderJob {
+            mesh: Some(String::from("bunny.ply")),
+            ..RenderJob::default()
+        };
+        assert!(names(&job, &without_camera).is_err());
+    }
+
+    #[test]
+    fn test_aperture_needs_three_blades() {
+        let job = RenderJob {
+            aperture_blades: Some(2),
+            ..RenderJob::default()
+        };
+        assert!(job.aperture().is_err());
+        let job = RenderJob {
+            aperture_blades: Some(3),
+            ..RenderJob::default()
+        };
+        assert!(job.aperture().is_ok());
+    }
+
+    #[test]
+    fn test_spectral_needs_path_tracing() {
+        let job = RenderJob {
+            spectral: true,
+            integrator: Integrator::Bidirectional,
+            ..RenderJob::default()
+        };
+        let error = job.run(|_| {}).unwrap_err();
+        assert!(error.to_string().contains("--spectral"));
+    }
+}
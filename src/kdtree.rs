@@ -0,0 +1,109 @@
+//! 三维点的kd树，用于查找某一点附近的光子
+
+use crate::aabb::{axis_of, Aabb};
+use crate::vec3::{Float, Vec3};
+
+/// 静态的kd树，建好之后不能再插入
+///
+/// 所有点按树的结构排在一个数组里，每棵子树占一段连续的区间，区间的中点是子树的根，
+/// 左边的点在根的分割轴上都不大于根，右边的都不小于根
+pub struct KdTree<T> {
+    nodes: Vec<(Vec3, T)>,
+    /// 每个根的分割轴
+    axes: Vec<usize>,
+}
+
+impl<T> KdTree<T> {
+    pub fn new(mut items: Vec<(Vec3, T)>) -> Self {
+        let mut axes = vec![0; items.len()];
+        build(&mut items, &mut axes);
+        KdTree { nodes: items, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// 对和`center`的距离不超过`radius`的每个点调用`f`
+    pub fn for_each_within<F: FnMut(&Vec3, &T)>(&self, center: &Vec3, radius: Float, mut f: F) {
+        self.search(0, self.nodes.len(), center, radius, &mut f);
+    }
+
+    fn search<F: FnMut(&Vec3, &T)>(
+        &self,
+        low: usize,
+        high: usize,
+        center: &Vec3,
+        radius: Float,
+        f: &mut F,
+    ) {
+        if low >= high {
+            return;
+        }
+        let mid = low + (high - low) / 2;
+        let (point, item) = &self.nodes[mid];
+        if (*point - *center).squared_length() <= radius * radius {
+            f(point, item);
+        }
+        let axis = self.axes[mid];
+        let d = axis_of(center, axis) - axis_of(point, axis);
+        if d <= radius {
+            self.search(low, mid, center, radius, f);
+        }
+        if d >= -radius {
+            self.search(mid + 1, high, center, radius, f);
+        }
+    }
+}
+
+/// 沿点分布最长的轴从中位数处分开，递归地排好两边
+fn build<T>(items: &mut [(Vec3, T)], axes: &mut [usize]) {
+    if items.len() <= 1 {
+        return;
+    }
+    let bounds = items
+        .iter()
+        .map(|(p, _)| Aabb::new(*p, *p))
+        .reduce(|a, b| a.surrounding(&b))
+        .unwrap();
+    let axis = bounds.longest_axis();
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+        axis_of(a, axis).partial_cmp(&axis_of(b, axis)).unwrap()
+    });
+    axes[mid] = axis;
+    let (left, right) = items.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches_brute_force() {
+        let points: Vec<(Vec3, usize)> = (0..2000)
+            .map(|i| (Vec3::random() * Vec3::new(4.0, 1.0, 2.0), i))
+            .collect();
+        let tree = KdTree::new(points.clone());
+        assert_eq!(tree.len(), points.len());
+        for _ in 0..50 {
+            let center = Vec3::random() * Vec3::new(4.0, 1.0, 2.0);
+            let mut found = Vec::new();
+            tree.for_each_within(&center, 0.3, |_, &i| found.push(i));
+            found.sort_unstable();
+            let expected: Vec<usize> = points
+                .iter()
+                .filter(|(p, _)| (*p - center).length() <= 0.3)
+                .map(|&(_, i)| i)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
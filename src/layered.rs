@@ -0,0 +1,409 @@
+//! 可以叠加的分层材质：清漆、绒面光泽、金属闪片和薄膜干涉
+//!
+//! 每一层都包在另一个材质外面，按本层的反射率随机决定是在本层散射还是交给下面的材质，
+//! 所以可以任意组合，例如车漆就是清漆包着闪片再包着漫反射的底漆
+
+use crate::hittable::HitRecord;
+use crate::material::{random_in_uint_sphere, random_unit_vector, reflect, Material};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::texture::Texture;
+use crate::vec3::{Float, Vec3};
+use rand::Rng;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 电介质界面的菲涅尔反射率，`eta`为界面另一侧和这一侧折射率之比，两种偏振取平均
+pub fn fresnel_dielectric(cos_theta: Float, eta: Float) -> Float {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// 红、绿、蓝三个通道分别用的波长，单位为纳米
+const WAVELENGTHS: [Float; 3] = [640.0, 550.0, 460.0];
+
+/// 厚度为`thickness`纳米、折射率为`film_ior`的薄膜夹在折射率为`outside_ior`和`substrate_ior`
+/// 的介质之间，按Airy公式计算每个颜色通道的反射率，两种偏振取平均
+pub fn thin_film_reflectance(
+    cos_theta: Float,
+    thickness: Float,
+    outside_ior: Float,
+    film_ior: Float,
+    substrate_ior: Float,
+) -> Vec3 {
+    let cos1 = cos_theta.clamp(0.0, 1.0);
+    let sin2_1 = 1.0 - cos1 * cos1;
+    let cos_in = |ior: Float| {
+        let sin2 = sin2_1 * (outside_ior / ior).powi(2);
+        if sin2 >= 1.0 {
+            None
+        } else {
+            Some((1.0 - sin2).sqrt())
+        }
+    };
+    // 在薄膜内发生全反射时光进不去，和没有薄膜一样
+    let cos2 = match cos_in(film_ior) {
+        Some(cos) => cos,
+        None => return Vec3::new(1.0, 1.0, 1.0),
+    };
+    let cos3 = match cos_in(substrate_ior) {
+        Some(cos) => cos,
+        None => return Vec3::new(1.0, 1.0, 1.0),
+    };
+    let (n1, n2, n3) = (outside_ior, film_ior, substrate_ior);
+    let r12s = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let r12p = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    let r23s = (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3);
+    let r23p = (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3);
+    let airy = |a: Float, b: Float, cos_delta: Float| {
+        let ab = 2.0 * a * b * cos_delta;
+        (a * a + b * b + ab) / (1.0 + a * a * b * b + ab)
+    };
+    let channel = |wavelength: Float| {
+        // 两束反射光的相位差
+        let delta = 4.0 * PI * n2 * thickness * cos2 / wavelength;
+        let cos_delta = delta.cos();
+        (0.5 * (airy(r12s, r23s, cos_delta) + airy(r12p, r23p, cos_delta))).clamp(0.0, 1.0)
+    };
+    Vec3::new(
+        channel(WAVELENGTHS[0]),
+        channel(WAVELENGTHS[1]),
+        channel(WAVELENGTHS[2]),
+    )
+}
+
+fn average(v: Vec3) -> Float {
+    (v.x() + v.y() + v.z()) / 3.0
+}
+
+/// 光泽反射，模糊程度为粗糙度的平方，方向穿到表面另一侧时返回`None`
+fn glossy_reflection(
+    ray_in: &Ray,
+    hit_record: &HitRecord,
+    normal: &Vec3,
+    roughness: Float,
+) -> Option<Ray> {
+    let reflected = reflect(&ray_in.direction().unit_vector(), normal);
+    let direction = reflected + roughness * roughness * random_in_uint_sphere();
+    if direction.dot(&hit_record.normal) <= 0.0
+        || direction.dot(&hit_record.geometric_normal) <= 0.0
+    {
+        return None;
+    }
+    Some(Ray::with_time(hit_record.point, direction, ray_in.time()))
+}
+
+/// 表面上一层透明的清漆，例如车漆和清漆木器
+///
+/// 按电介质的菲涅尔项在清漆表面光泽反射，其余的光进入下面的材质。
+/// 清漆只有外侧，从物体内部射出的光线直接交给下面的材质
+pub struct Clearcoat {
+    base: Rc<dyn Material>,
+    roughness: Float,
+    ior: Float,
+}
+
+impl Clearcoat {
+    pub fn new(base: Rc<dyn Material>, roughness: Float) -> Self {
+        Clearcoat {
+            base,
+            roughness: roughness.clamp(0.0, 1.0),
+            ior: 1.5,
+        }
+    }
+
+    /// 清漆的折射率，默认为1.5
+    pub fn with_ior(mut self, ior: Float) -> Self {
+        self.ior = ior;
+        self
+    }
+}
+
+impl Material for Clearcoat {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        if !hit_record.front_face {
+            return self.base.scatter(ray_in, hit_record);
+        }
+        let mut rng = sampler::rng();
+        let cos_theta = (-ray_in.direction().unit_vector()).dot(&hit_record.normal);
+        let fresnel = fresnel_dielectric(cos_theta, self.ior);
+        // 选中的概率就是反射率，两条路径的权重都是1
+        if rng.gen_range(0.0, 1.0) < fresnel {
+            let scattered =
+                glossy_reflection(ray_in, hit_record, &hit_record.normal, self.roughness)?;
+            Some((Vec3::new(1.0, 1.0, 1.0), scattered))
+        } else {
+            self.base.scatter(ray_in, hit_record)
+        }
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        self.base.albedo(hit_record)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        self.base.emitted(ray_in, hit_record)
+    }
+}
+
+/// 布料表面的绒面光泽：细小的纤维在掠射角把光反射回来，让天鹅绒的轮廓发亮
+///
+/// 用Estevez和Kulla的Charlie分布，和下面的材质相加而不互相削弱，和Disney材质的做法一样
+pub struct Sheen {
+    base: Rc<dyn Material>,
+    color: Vec3,
+    roughness: Float,
+}
+
+impl Sheen {
+    pub fn new(base: Rc<dyn Material>, color: Vec3, roughness: Float) -> Self {
+        Sheen {
+            base,
+            color,
+            roughness: roughness.clamp(0.01, 1.0),
+        }
+    }
+}
+
+impl Material for Sheen {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        // 光泽越强越常选中
+        let p_sheen = average(self.color).clamp(0.0, 0.5);
+        if !hit_record.front_face || rng.gen_range(0.0, 1.0) >= p_sheen {
+            let (attenuation, scattered) = self.base.scatter(ray_in, hit_record)?;
+            return Some((attenuation / (1.0 - p_sheen), scattered));
+        }
+        let normal = hit_record.normal;
+        let direction = normal + random_unit_vector();
+        if direction.squared_length() < 1e-12 || direction.dot(&hit_record.geometric_normal) <= 0.0
+        {
+            return None;
+        }
+        let incoming = direction.unit_vector();
+        let outgoing = -ray_in.direction().unit_vector();
+        let cos_i = incoming.dot(&normal).max(0.0);
+        let cos_o = outgoing.dot(&normal).max(0.0);
+        let half = (incoming + outgoing).unit_vector();
+        let cos_h = half.dot(&normal).clamp(-1.0, 1.0);
+        let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+        let inverse_alpha = 1.0 / (self.roughness * self.roughness);
+        let distribution = (2.0 + inverse_alpha) * sin_h.powf(inverse_alpha) / (2.0 * PI);
+        // Ashikhmin的遮挡项，BRDF乘以余弦除以余弦采样的概率密度
+        let brdf_over_pdf = PI * distribution / (4.0 * (cos_i + cos_o - cos_i * cos_o)).max(1e-4);
+        Some((
+            brdf_over_pdf / p_sheen * self.color,
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        self.base.albedo(hit_record)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        self.base.emitted(ray_in, hit_record)
+    }
+}
+
+/// 金属漆里的铝片：空间被分成边长为`size`的小格子，一部分格子里有一片随机倾斜的小镜子，
+/// 在一定角度下闪闪发光，其余的光交给下面的材质
+pub struct Flakes {
+    base: Rc<dyn Material>,
+    color: Vec3,
+    size: Float,
+    density: Float,
+    spread: Float,
+}
+
+impl Flakes {
+    pub fn new(base: Rc<dyn Material>, color: Vec3, size: Float) -> Self {
+        Flakes {
+            base,
+            color,
+            size,
+            density: 0.5,
+            spread: 0.3,
+        }
+    }
+
+    /// 有铝片的格子所占的比例，默认为0.5
+    pub fn with_density(mut self, density: Float) -> Self {
+        self.density = density.clamp(0.0, 1.0);
+        self
+    }
+
+    /// 铝片法向偏离表面法向的程度，默认为0.3
+    pub fn with_spread(mut self, spread: Float) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    /// 格子坐标和序号对应的伪随机数，范围为[0, 1)
+    fn cell_random(cell: [i64; 3], k: u64) -> Float {
+        let mut h = k.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        for &c in &cell {
+            h ^= (c as u64).wrapping_add(0x632B_E59B_D9B4_E019);
+            h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            h ^= h >> 31;
+        }
+        h = h.wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^= h >> 29;
+        (h >> 40) as Float / (1u64 << 24) as Float
+    }
+}
+
+impl Material for Flakes {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let p = hit_record.point / self.size;
+        let cell = [
+            p.x().floor() as i64,
+            p.y().floor() as i64,
+            p.z().floor() as i64,
+        ];
+        if !hit_record.front_face || Self::cell_random(cell, 0) >= self.density {
+            return self.base.scatter(ray_in, hit_record);
+        }
+        let tilt = Vec3::new(
+            2.0 * Self::cell_random(cell, 1) - 1.0,
+            2.0 * Self::cell_random(cell, 2) - 1.0,
+            2.0 * Self::cell_random(cell, 3) - 1.0,
+        );
+        let normal = (hit_record.normal + self.spread * tilt).unit_vector();
+        let reflected = reflect(&ray_in.direction().unit_vector(), &normal);
+        // 倾斜的铝片把光反射到表面以下时，光在漆里被下面的材质散射
+        if reflected.dot(&hit_record.normal) <= 0.0
+            || reflected.dot(&hit_record.geometric_normal) <= 0.0
+        {
+            return self.base.scatter(ray_in, hit_record);
+        }
+        Some((
+            self.color,
+            Ray::with_time(hit_record.point, reflected, ray_in.time()),
+        ))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        self.base.albedo(hit_record)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        self.base.emitted(ray_in, hit_record)
+    }
+}
+
+/// 薄膜干涉，厚度和光的波长相近的薄膜上下两个表面的反射光相互干涉，
+/// 反射的颜色随厚度和观察角度变化，例如肥皂泡和水面上的油膜
+///
+/// 单独使用时是两侧都是空气的肥皂膜，没有反射的光沿原方向穿过；
+/// 用`over`放在另一个材质上面时是一层镀膜，没有反射的光交给下面的材质
+pub struct ThinFilm {
+    thickness: Float,
+    ior: Float,
+    thickness_texture: Option<Rc<dyn Texture>>,
+    base: Option<(Rc<dyn Material>, Float)>,
+    roughness: Float,
+}
+
+impl ThinFilm {
+    /// `thickness`单位为纳米，肥皂泡大约为几百纳米
+    pub fn new(thickness: Float, ior: Float) -> Self {
+        ThinFilm {
+            thickness,
+            ior,
+            thickness_texture: None,
+            base: None,
+            roughness: 0.0,
+        }
+    }
+
+    /// 厚度乘以纹理的亮度，真实的肥皂泡和油膜厚薄不均，才会出现一圈圈的彩色条纹
+    pub fn with_thickness_texture(mut self, texture: Rc<dyn Texture>) -> Self {
+        self.thickness_texture = Some(texture);
+        self
+    }
+
+    /// 把薄膜镀在折射率为`substrate_ior`的`base`上
+    pub fn over(mut self, base: Rc<dyn Material>, substrate_ior: Float) -> Self {
+        self.base = Some((base, substrate_ior));
+        self
+    }
+
+    /// 薄膜表面反射的模糊程度，默认是光滑的
+    pub fn with_roughness(mut self, roughness: Float) -> Self {
+        self.roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl Material for ThinFilm {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        if let (Some((base, _)), false) = (&self.base, hit_record.front_face) {
+            return base.scatter(ray_in, hit_record);
+        }
+        let substrate_ior = self.base.as_ref().map_or(1.0, |(_, ior)| *ior);
+        let cos_theta = (-ray_in.direction().unit_vector()).dot(&hit_record.normal);
+        let thickness = match &self.thickness_texture {
+            Some(texture) => {
+                self.thickness
+                    * average(texture.value(hit_record.u, hit_record.v, &hit_record.point))
+            }
+            None => self.thickness,
+        };
+        let reflectance = thin_film_reflectance(cos_theta, thickness, 1.0, self.ior, substrate_ior);
+        let p_reflect = average(reflectance).clamp(0.01, 0.99);
+        if rng.gen_range(0.0, 1.0) < p_reflect {
+            let scattered =
+                glossy_reflection(ray_in, hit_record, &hit_record.normal, self.roughness)?;
+            return Some((reflectance / p_reflect, scattered));
+        }
+        let transmittance = (Vec3::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - p_reflect);
+        match &self.base {
+            Some((base, _)) => {
+                let (attenuation, scattered) = base.scatter(ray_in, hit_record)?;
+                Some((transmittance * attenuation, scattered))
+            }
+            None => Some((
+                transmittance,
+                Ray::with_time(hit_record.point, *ray_in.direction(), ray_in.time()),
+            )),
+        }
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        match &self.base {
+            Some((base, _)) => base.albedo(hit_record),
+            None => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_thin_film_reflectance() {
+        // 厚度为0时就是空气和基底之间的界面
+        for &cos in &[1.0, 0.7, 0.2] {
+            let r = thin_film_reflectance(cos, 0.0, 1.0, 1.33, 1.5);
+            let expected = fresnel_dielectric(cos, 1.5);
+            assert!((r.x() - expected).abs() < 1e-5);
+            assert!((r.z() - expected).abs() < 1e-5);
+        }
+        // 四分之一波长的增透膜，折射率为基底的平方根时绿光几乎不反射
+        let ior = (1.5 as Float).sqrt();
+        let r = thin_film_reflectance(1.0, WAVELENGTHS[1] / (4.0 * ior), 1.0, ior, 1.5);
+        assert!(r.y() < 1e-5);
+        assert!(r.x() > r.y() && r.z() > r.y());
+        assert!(r.x() < fresnel_dielectric(1.0, 1.5));
+    }
+}
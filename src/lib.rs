@@ -0,0 +1,76 @@
+//! 用Rust写的一个光线追踪器，参考[_Ray Tracing in One Weekend_](https://raytracing.github.io/books/RayTracingInOneWeekend.html)
+//!
+//! ```no_run
+//! use ray_tracing::camera::PerspectiveCamera;
+//! use ray_tracing::scene::random_scene;
+//! use ray_tracing::{RenderSettings, Renderer, Vec3};
+//!
+//! let scene = random_scene();
+//! let settings = RenderSettings {
+//!     width: 300,
+//!     height: 200,
+//!     samples_per_pixel: 16,
+//!     ..RenderSettings::default()
+//! };
+//! let camera = PerspectiveCamera::new(
+//!     &Vec3::new(13.0, 2.0, 3.0),
+//!     &Vec3::new(0.0, 0.0, 0.0),
+//!     &Vec3::new(0.0, 1.0, 0.0),
+//!     20.0,
+//!     1.5,
+//!     0.1,
+//!     10.0,
+//! );
+//! let image = Renderer::new(settings).render(&scene, &camera);
+//! image.save("final.png").unwrap();
+//! ```
+
+// 早期的vec3和hittable沿用了这几种写法
+#![allow(
+    clippy::assign_op_pattern,
+    clippy::op_ref,
+    clippy::redundant_field_names
+)]
+
+pub mod aabb;
+pub mod animation;
+pub mod aov;
+pub mod bdpt;
+pub mod bvh;
+pub mod camera;
+pub mod csg;
+pub mod curve;
+pub mod denoise;
+pub mod gltf_import;
+pub mod heightfield;
+pub mod hittable;
+mod hittable_list;
+pub mod job;
+pub mod kdtree;
+pub mod layered;
+pub mod light;
+pub mod material;
+pub mod merl;
+pub mod mesh;
+pub mod microfacet;
+pub mod mlt;
+pub mod normal_map;
+pub mod ply;
+pub mod preview;
+pub mod primitives;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod sdf;
+pub mod spectrum;
+pub mod sppm;
+pub mod stl;
+pub mod subdivision;
+pub mod texture;
+pub mod transform;
+pub mod vec3;
+
+pub use render::{Image, Integrator, RenderSettings, Renderer};
+pub use scene::{Scene, SceneBuilder};
+pub use vec3::{Float, Vec3};
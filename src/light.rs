@@ -0,0 +1,176 @@
+//! 光源的抽样，供双向路径追踪和光子映射使用
+//!
+//! 光源包括用`SceneBuilder::add_light`添加的面光源和天空，每个被选中的概率相同。
+//! 面光源两面都发光，天空的光从场景包围球外垂直于光线的圆盘上射进来，
+//! 圆盘上被无限大的物体挡住、看不到天空的点不发光
+
+use crate::hittable::HitRecord;
+use crate::material::random_unit_vector;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::vec3::{Float, Vec3};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// 从光源发出的一条光线
+pub struct Emission {
+    pub ray: Ray,
+    /// 起点沿光线方向发出的辐射度
+    pub radiance: Vec3,
+    /// 在面光源上抽样的起点，来自天空时为`None`
+    pub hit_record: Option<HitRecord>,
+    /// 选中这个光源的概率
+    pub pdf_light: Float,
+    /// 起点的面积概率密度
+    pub pdf_position: Float,
+    /// 方向的立体角概率密度
+    pub pdf_direction: Float,
+}
+
+impl Emission {
+    /// 光线携带的功率，已经除以了抽样的概率密度
+    pub fn power(&self) -> Vec3 {
+        let cos = self.hit_record.as_ref().map_or(1.0, |h| {
+            h.geometric_normal
+                .dot(&self.ray.direction().unit_vector())
+                .abs()
+        });
+        self.radiance * (cos / (self.pdf_light * self.pdf_position * self.pdf_direction))
+    }
+}
+
+/// 从某一点看向光源的样本，没有检测遮挡
+pub struct LightSample {
+    /// 指向光源的单位向量
+    pub direction: Vec3,
+    /// 到光源的距离，天空为无穷远
+    pub distance: Float,
+    /// 从光源射来的辐射度
+    pub radiance: Vec3,
+    /// 立体角概率密度，包括选中这个光源的概率
+    pub pdf: Float,
+    /// 面光源上的点，天空为`None`
+    pub hit_record: Option<HitRecord>,
+}
+
+/// 选中某个光源的概率
+pub fn selection_pdf(scene: &Scene) -> Float {
+    1.0 / (scene.light_count() + 1) as Float
+}
+
+/// 以`n`为轴按余弦分布抽样的方向
+fn cosine_direction(n: &Vec3) -> Vec3 {
+    loop {
+        let d = *n + random_unit_vector();
+        if d.squared_length() > 1e-8 {
+            return d.unit_vector();
+        }
+    }
+}
+
+/// 面光源上的点向`w`方向发出的光
+pub fn emitted_toward(hit_record: &HitRecord, w: &Vec3) -> Vec3 {
+    let ray = Ray::new(hit_record.point + *w, -*w);
+    hit_record.material.emitted(&ray, hit_record)
+}
+
+/// 选一个光源，在上面抽样起点和发光的方向
+pub fn sample_emission(scene: &Scene, time: Float) -> Option<Emission> {
+    let mut rng = sampler::rng();
+    let pdf_light = selection_pdf(scene);
+    let index = rng.gen_range(0, scene.light_count() + 1);
+    if index < scene.light_count() {
+        let hit_record =
+            scene.sample_light(index, rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0))?;
+        // 随机选一面按余弦分布抽样
+        let n = hit_record.geometric_normal;
+        let mut w = cosine_direction(&n);
+        if rng.gen_range(0.0, 1.0) < 0.5 {
+            w = -w;
+        }
+        Some(Emission {
+            ray: Ray::with_time(hit_record.point, w, time),
+            radiance: emitted_toward(&hit_record, &w),
+            pdf_light,
+            pdf_position: 1.0 / scene.light_area(index),
+            pdf_direction: 0.5 * n.dot(&w).abs() / PI,
+            hit_record: Some(hit_record),
+        })
+    } else {
+        let (center, radius) = scene.bounding_sphere();
+        let direction = scene.sample_background();
+        let helper = if direction.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t = direction.cross(&helper).unit_vector();
+        let b = direction.cross(&t);
+        let u: Float = rng.gen_range(0.0, 1.0);
+        let r = radius * u.sqrt();
+        let phi = rng.gen_range(0.0, 2.0 * PI);
+        let origin = center + radius * direction + r * phi.cos() * t + r * phi.sin() * b;
+        // 圆盘只在有限大的物体之外，无限大的平面可能把它和天空隔开，例如封闭的房间
+        if scene
+            .hit(&Ray::with_time(origin, direction, time), 0.001, Float::MAX)
+            .is_some()
+        {
+            return None;
+        }
+        Some(Emission {
+            ray: Ray::with_time(origin, -direction, time),
+            radiance: scene.background(&Ray::new(origin, direction)),
+            hit_record: None,
+            pdf_light,
+            pdf_position: background_position_pdf(scene),
+            pdf_direction: scene.background_pdf(&direction),
+        })
+    }
+}
+
+/// 天空的光线起点在圆盘上的面积概率密度
+pub fn background_position_pdf(scene: &Scene) -> Float {
+    let (_, radius) = scene.bounding_sphere();
+    1.0 / (PI * radius * radius)
+}
+
+/// 从`point`出发选一个光源，按面光源的面积或天空的`sample_background`抽样光源上的点
+pub fn sample_incident(scene: &Scene, point: &Vec3) -> Option<LightSample> {
+    let mut rng = sampler::rng();
+    let pdf_light = selection_pdf(scene);
+    let index = rng.gen_range(0, scene.light_count() + 1);
+    if index < scene.light_count() {
+        let hit_record =
+            scene.sample_light(index, rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0))?;
+        let d = hit_record.point - *point;
+        let distance = d.length();
+        let direction = d / distance;
+        let cos = hit_record.geometric_normal.dot(&direction).abs();
+        if cos <= 0.0 || distance <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: emitted_toward(&hit_record, &-direction),
+            pdf: pdf_light * distance * distance / (cos * scene.light_area(index)),
+            hit_record: Some(hit_record),
+        })
+    } else {
+        let direction = scene.sample_background();
+        Some(LightSample {
+            direction,
+            distance: Float::INFINITY,
+            radiance: scene.background(&Ray::new(*point, direction)),
+            pdf: pdf_light * scene.background_pdf(&direction),
+            hit_record: None,
+        })
+    }
+}
+
+/// `point`和光源样本之间没有遮挡
+pub fn visible(scene: &Scene, point: &Vec3, sample: &LightSample, time: Float) -> bool {
+    let ray = Ray::with_time(*point, sample.direction, time);
+    scene.hit(&ray, 0.001, sample.distance - 0.001).is_none()
+}
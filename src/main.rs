@@ -1,162 +1,222 @@
-extern crate image;
-mod camera;
-mod hittable;
-mod hittable_list;
-mod material;
-mod ray;
-mod vec3;
-
-use camera::*;
-use hittable::*;
-use hittable_list::hit;
-use image::ImageBuffer;
-use material::*;
-use rand::Rng;
-use ray::Ray;
-use std::rc::Rc;
+use ray_tracing::camera::{OpticalVignetting, StereoRig};
+use ray_tracing::job::{BuiltinScene, Projection, RenderJob};
+use ray_tracing::{Float, Integrator, Vec3};
+use std::env;
 use std::time::Instant;
-use vec3::{Float, Vec3};
-
-fn ray_color(ray: &Ray, world: &Vec<Box<dyn Hittable>>, depth: i32) -> Vec3 {
-    if depth < 0 {
-        return Vec3::zero();
-    }
 
-    match hit(world, ray, 0.001, Float::MAX) {
-        Some(hit_record) => match hit_record.material.scatter(ray, &hit_record) {
-            Some((attenuation, scattered)) => {
-                attenuation * ray_color(&scattered, &world, depth - 1)
+/// 解析命令行参数，各参数的含义见README
+fn parse_args() -> RenderJob {
+    let mut options = RenderJob::default();
+    // Metropolis光传输中大步变异的概率和环境光遮蔽预览的半径，和积分器的先后顺序无关
+    let mut large_step = None;
+    let mut ao_radius = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => {
+                options.width = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&n| n > 0)
+                    .expect("--width needs a positive integer")
             }
-            None => Vec3::zero(),
-        },
-        None => {
-            let t = 0.5 * (ray.direction().unit_vector().y() + 1.0);
-            (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+            "--spp" => {
+                options.samples_per_pixel = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&n| n > 0)
+                    .expect("--spp needs a positive integer")
+            }
+            "--denoise" => options.denoise = true,
+            "--aov" => options.aov = true,
+            "--spectral" => options.spectral = true,
+            "--integrator" => {
+                options.integrator = match args.next().as_deref() {
+                    Some("path") => Integrator::PathTracing,
+                    Some("bdpt") => Integrator::Bidirectional,
+                    Some("sppm") => Integrator::PhotonMapping,
+                    Some("mlt") => Integrator::Metropolis {
+                        large_step_probability: 0.3,
+                    },
+                    Some("normals") => Integrator::Normals,
+                    Some("depth") => Integrator::Depth,
+                    Some("albedo") => Integrator::Albedo,
+                    Some("cost") => Integrator::TraversalCost,
+                    Some("wireframe") => Integrator::Wireframe,
+                    Some("ao") => Integrator::AmbientOcclusion { radius: 1.0 },
+                    Some("direct") => Integrator::DirectLighting,
+                    _ => panic!(
+                        "--integrator needs path, bdpt, sppm, mlt, normals, depth, albedo, cost, wireframe, ao or direct"
+                    ),
+                }
+            }
+            "--ao-radius" => {
+                ao_radius = Some(
+                    args.next()
+                        .and_then(|n| n.parse().ok())
+                        .expect("--ao-radius needs a distance"),
+                )
+            }
+            "--large-step" => {
+                large_step = Some(
+                    args.next()
+                        .and_then(|n| n.parse().ok())
+                        .expect("--large-step needs a probability"),
+                )
+            }
+            "--camera" => {
+                options.projection = match args.next().as_deref() {
+                    Some("perspective") => Projection::Perspective,
+                    Some("orthographic") => Projection::Orthographic,
+                    Some("panorama") => Projection::Panorama,
+                    Some("fisheye") => Projection::Fisheye,
+                    Some("lens") => Projection::Lens,
+                    _ => panic!(
+                        "--camera needs perspective, orthographic, panorama, fisheye or lens"
+                    ),
+                }
+            }
+            "--focal-length" => {
+                options.focal_length = Some(
+                    args.next()
+                        .and_then(|n| n.parse().ok())
+                        .expect("--focal-length needs a number in mm"),
+                )
+            }
+            "--focus-point" => {
+                let p = parse_floats(args.next(), 3).expect("--focus-point needs x,y,z");
+                options.focus_point = Some(Vec3::new(p[0], p[1], p[2]))
+            }
+            "--lens-shift" => {
+                let p = parse_floats(args.next(), 2).expect("--lens-shift needs x,y");
+                options.lens_shift = (p[0], p[1])
+            }
+            "--aperture-blades" => {
+                options.aperture_blades = Some(
+                    args.next()
+                        .and_then(|n| n.parse().ok())
+                        .expect("--aperture-blades needs a positive integer"),
+                )
+            }
+            "--aperture-rotation" => {
+                options.aperture_rotation = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--aperture-rotation needs an angle in degrees")
+            }
+            "--aperture-mask" => {
+                options.aperture_mask = Some(args.next().expect("--aperture-mask needs a path"))
+            }
+            "--optical-vignetting" => {
+                let p = parse_floats(args.next(), 2)
+                    .expect("--optical-vignetting needs distance,radius");
+                options.optical_vignetting = Some(OpticalVignetting {
+                    distance: p[0],
+                    radius: p[1],
+                })
+            }
+            "--stereo" => {
+                let p = parse_floats(args.next(), 2).expect("--stereo needs ipd,convergence");
+                options.stereo = Some(StereoRig::new(p[0], p[1]))
+            }
+            "--view" => {
+                let p = parse_floats(args.next(), 3).expect("--view needs x,y,z");
+                options.views.push(Vec3::new(p[0], p[1], p[2]))
+            }
+            "--frames" => {
+                let range = args.next().expect("--frames needs start-end");
+                let mut bounds = range.splitn(2, '-').map(|n| n.parse().ok());
+                match (bounds.next().flatten(), bounds.next().flatten()) {
+                    (Some(first), Some(last)) => options.frames = Some((first, last)),
+                    _ => panic!("--frames needs start-end, got {}", range),
+                }
+            }
+            "--fps" => {
+                options.fps = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--fps needs a number")
+            }
+            "--scene" => {
+                options.scene = match args.next().as_deref() {
+                    Some("random") => BuiltinScene::Random,
+                    Some("shapes") => BuiltinScene::Shapes,
+                    Some("sdf") => BuiltinScene::Sdf,
+                    Some("terrain") => BuiltinScene::Terrain,
+                    Some("hair") => BuiltinScene::Hair,
+                    Some("layered") => BuiltinScene::Layered,
+                    Some("room") => BuiltinScene::Room,
+                    Some("prism") => BuiltinScene::Prism,
+                    _ => panic!(
+                        "--scene needs random, shapes, sdf, terrain, hair, layered, room or prism"
+                    ),
+                }
+            }
+            "--mesh" => options.mesh = Some(args.next().expect("--mesh needs a path")),
+            "--subdivide" => {
+                options.mesh_options.subdivide = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--subdivide needs a number")
+            }
+            "--displacement" => {
+                options.mesh_options.displacement = Some(args.next().expect("--displacement needs a path"))
+            }
+            "--displacement-scale" => {
+                options.mesh_options.displacement_scale = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--displacement-scale needs a number")
+            }
+            "--merl" => options.merl = Some(args.next().expect("--merl needs a path")),
+            "--heightfield" => {
+                options.heightfield = Some(args.next().expect("--heightfield needs a path"))
+            }
+            "--gltf" => options.gltf = Some(args.next().expect("--gltf needs a path")),
+            "--f-stop" => {
+                options.f_stop = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--f-stop needs a number")
+            }
+            _ => panic!("Unknown argument: {}", arg),
         }
     }
+    if let (
+        Integrator::Metropolis {
+            large_step_probability,
+        },
+        Some(p),
+    ) = (&mut options.integrator, large_step)
+    {
+        *large_step_probability = p;
+    }
+    if let (Integrator::AmbientOcclusion { radius }, Some(r)) = (&mut options.integrator, ao_radius)
+    {
+        *radius = r;
+    }
+    options
+}
+
+/// 解析以逗号分隔的`n`个数
+fn parse_floats(arg: Option<String>, n: usize) -> Option<Vec<Float>> {
+    let values: Vec<Float> = arg?
+        .split(',')
+        .map(|x| x.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if values.len() == n {
+        Some(values)
+    } else {
+        None
+    }
 }
 
 fn main() {
+    let job = parse_args();
     println!("Start running...");
     let start = Instant::now();
-    let aspect_ratio = 3.0 / 2.0;
-    let width = 1200;
-    let height = (width as Float / aspect_ratio) as u32;
-    let samples_per_pixel = 500;
-    let max_depth = 50;
-
-    let mut rng = rand::thread_rng();
-    let lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    let lookat = Vec3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-
-    let camera = Camera::new(
-        &lookfrom,
-        &lookat,
-        &vup,
-        20.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    );
-
-    let world = random_scene();
-
-    let img = ImageBuffer::from_fn(width, height, |x, y| {
-        let mut pixel_color = Vec3::zero();
-        for _ in 0..samples_per_pixel {
-            let u = (x as Float + rng.gen_range(0.0, 1.0)) / (width - 1) as Float;
-            let v = 1.0 - (y as Float + rng.gen_range(0.0, 1.0)) / (height - 1) as Float;
-            let ray = camera.get_ray(u, v);
-            pixel_color += ray_color(&ray, &world, max_depth);
-        }
-
-        // Divide the color by the number of samples and gamma-correct for gamma=2.0.
-        let scale = 1.0 / samples_per_pixel as Float;
-        let r = pixel_color.x();
-        let g = pixel_color.y();
-        let b = pixel_color.z();
-
-        let r = (scale * r).sqrt();
-        let g = (scale * g).sqrt();
-        let b = (scale * b).sqrt();
-
-        let r = (255.0 * r) as u8;
-        let g = (255.0 * g) as u8;
-        let b = (255.0 * b) as u8;
-
-        image::Rgb([r, g, b])
-    });
-    let elapsed = start.elapsed();
-    img.save("final.png").unwrap();
-    println!("Time spent: {} ms", elapsed.as_millis());
-}
-
-fn random_scene() -> Vec<Box<dyn Hittable>> {
-    let mut world: Vec<Box<dyn Hittable>> = Vec::new();
-    let mut rng = rand::thread_rng();
-    let material_ground = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
-    world.push(Box::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        material_ground,
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rng.gen_range(0.0, 1.0);
-            let center = Vec3::new(
-                a as Float + 0.9 * rng.gen_range(0.0, 1.0),
-                0.2,
-                b as Float + 0.9 * rng.gen_range(0.0, 1.0),
-            );
-
-            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Vec3::random() * Vec3::random();
-                    let sphere_material = Rc::new(Lambertian::new(&albedo));
-
-                    world.push(Box::new(Sphere::new(center, 0.2, sphere_material)));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Vec3::new(
-                        rng.gen_range(0.5, 1.0),
-                        rng.gen_range(0.5, 1.0),
-                        rng.gen_range(0.5, 1.0),
-                    );
-                    let fuzz = rng.gen_range(0.0, 0.5);
-                    let sphere_material = Rc::new(Metal::new(&albedo, fuzz));
-
-                    world.push(Box::new(Sphere::new(center, 0.2, sphere_material)));
-                } else {
-                    // glass
-                    let sphere_material = Rc::new(Dielectric::new(1.5));
-                    world.push(Box::new(Sphere::new(center, 0.2, sphere_material)));
-                }
-            }
-        }
-    }
-    let material1 = Rc::new(Dielectric::new(1.5));
-    world.push(Box::new(Sphere::new(
-        Vec3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
-    let material2 = Rc::new(Lambertian::new(&Vec3::new(0.4, 0.2, 0.1)));
-    world.push(Box::new(Sphere::new(
-        Vec3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
-    let material3 = Rc::new(Metal::new(&Vec3::new(0.7, 0.6, 0.5), 0.0));
-    world.push(Box::new(Sphere::new(
-        Vec3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
-
-    world
+    job.run(|path| println!("Saved {}", path))
+        .unwrap_or_else(|e| panic!("{}", e));
+    println!("Time spent: {} ms", start.elapsed().as_millis());
 }
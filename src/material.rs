@@ -1,28 +1,140 @@
 use crate::hittable::*;
+use crate::normal_map::{face_viewer, tangent_space_normal};
 use crate::ray::*;
+use crate::sampler;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths, Spectrum};
+use crate::texture::Texture;
 use crate::vec3::*;
 use rand::Rng;
+use std::rc::Rc;
 
 pub trait Material {
     fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)>;
+
+    /// 表面的反照率，用于降噪的辅助缓冲
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+
+    /// 表面自身发出的光，`ray_in`为看向表面的射线
+    fn emitted(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::zero()
+    }
+
+    /// 光谱模式下的散射，返回各个波长上的衰减，默认把`scatter`的RGB衰减转换成光谱。
+    /// 出射方向随波长变化时调用`wavelengths.terminate_secondary`只保留主波长
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut SampledWavelengths,
+    ) -> Option<(SampledSpectrum, Ray)> {
+        let (attenuation, scattered) = self.scatter(ray_in, hit_record)?;
+        Some((
+            SampledSpectrum::from_rgb(attenuation, wavelengths),
+            scattered,
+        ))
+    }
+
+    /// 光谱模式下的自发光，默认把`emitted`的RGB值转换成光谱
+    fn emitted_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        SampledSpectrum::from_rgb(self.emitted(ray_in, hit_record), wavelengths)
+    }
+
+    /// 双向路径追踪连接两条子路径时用的BSDF，`wo`和`wi`都是从交点指向外面的单位向量：
+    /// 返回光从`wi`射入、从`wo`射出的BSDF值（不含余弦项），以及`scatter`在出射方向为`wo`时
+    /// 抽样到`wi`的概率密度（立体角）。BSDF需要满足互易性
+    ///
+    /// 默认返回`None`，表示材质只能用`scatter`抽样、不能求值，与方向无关
+    fn bsdf(&self, _hit_record: &HitRecord, _wo: &Vec3, _wi: &Vec3) -> Option<(Vec3, Float)> {
+        None
+    }
+
+    /// 是否只沿确定的方向反射或折射，例如光滑的金属和玻璃
+    fn is_specular(&self, _hit_record: &HitRecord) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
     albedo: Vec3,
+    /// 用光谱定义的反射率，只在光谱模式下使用，RGB模式下用转换成的`albedo`
+    spectrum: Option<Spectrum>,
 }
 
 impl Lambertian {
     pub fn new(albedo: &Vec3) -> Self {
-        Self { albedo: *albedo }
+        Self {
+            albedo: *albedo,
+            spectrum: None,
+        }
+    }
+
+    /// 用反射光谱定义的漫反射
+    pub fn from_spectrum(spectrum: Spectrum) -> Self {
+        Self {
+            albedo: spectrum.to_rgb(),
+            spectrum: Some(spectrum),
+        }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let target = hit_record.normal + random_unit_vector();
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let target = mirror_above(
+            hit_record.normal + random_unit_vector(),
+            &hit_record.geometric_normal,
+        );
+        // 随机向量和法向几乎相反时方向退化
+        if target.dot(&hit_record.geometric_normal) <= 0.0 {
+            return None;
+        }
+
+        let scattered = Ray::with_time(hit_record.point, target, ray_in.time());
+        Some((self.albedo(hit_record), scattered))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        match hit_record.vertex_color {
+            Some(color) => self.albedo * color,
+            None => self.albedo,
+        }
+    }
 
-        let scattered = Ray::new(hit_record.point, target);
-        Some((self.albedo, scattered))
+    fn bsdf(&self, hit_record: &HitRecord, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, Float)> {
+        let pi = std::f32::consts::PI;
+        let cos = wi.dot(&hit_record.normal);
+        if cos <= 0.0
+            || wi.dot(&hit_record.geometric_normal) <= 0.0
+            || wo.dot(&hit_record.geometric_normal) <= 0.0
+        {
+            return Some((Vec3::zero(), 0.0));
+        }
+        Some((self.albedo(hit_record) / pi, cos / pi))
+    }
+
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut SampledWavelengths,
+    ) -> Option<(SampledSpectrum, Ray)> {
+        let (attenuation, scattered) = self.scatter(ray_in, hit_record)?;
+        let reflectance = match &self.spectrum {
+            Some(spectrum) => {
+                let color = hit_record
+                    .vertex_color
+                    .unwrap_or_else(|| Vec3::new(1.0, 1.0, 1.0));
+                spectrum.sample(wavelengths) * SampledSpectrum::from_rgb(color, wavelengths)
+            }
+            None => SampledSpectrum::from_rgb(attenuation, wavelengths),
+        };
+        Some((reflectance, scattered))
     }
 }
 
@@ -44,25 +156,58 @@ impl Material for Metal {
     fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
         let reflected = reflect(&ray_in.direction().unit_vector(), &hit_record.normal);
 
-        let scattered = Ray::new(
-            hit_record.point,
-            reflected + self.fuzz * random_in_uint_sphere(),
-        );
-        if scattered.direction().dot(&hit_record.normal) > 0.0 {
-            Some((self.albedo, scattered))
-        } else {
-            None
+        let direction = reflected + self.fuzz * random_in_uint_sphere();
+        // 模糊后低于表面的方向被表面吸收
+        if direction.dot(&hit_record.normal) <= 0.0 {
+            return None;
         }
+        let direction = mirror_above(direction, &hit_record.geometric_normal);
+        Some((
+            self.albedo,
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+
+    fn is_specular(&self, _hit_record: &HitRecord) -> bool {
+        self.fuzz == 0.0
     }
 }
 
 pub struct Dielectric {
     ir: Float,
+    abbe_number: Option<Float>,
 }
 
 impl Dielectric {
     pub fn new(ir: Float) -> Self {
-        Dielectric { ir }
+        Dielectric {
+            ir,
+            abbe_number: None,
+        }
+    }
+
+    /// 色散，阿贝数越小色散越强，冕牌玻璃约为60，火石玻璃约为30。
+    /// 只在光谱模式下起作用，这时`ir`为587.6nm处的折射率
+    pub fn with_abbe_number(mut self, abbe_number: Float) -> Self {
+        self.abbe_number = Some(abbe_number);
+        self
+    }
+
+    /// 按柯西公式n = A + B / λ²计算波长`lambda`（纳米）处的折射率
+    pub fn ior_at(&self, lambda: Float) -> Float {
+        let abbe_number = match self.abbe_number {
+            Some(v) => v,
+            None => return self.ir,
+        };
+        // 由d、F、C三条谱线的定义：V = (n_d - 1) / (n_F - n_C)
+        let inverse_square = |nm: Float| 1.0 / (nm * 1e-3).powi(2);
+        let b = (self.ir - 1.0) / (abbe_number * (inverse_square(486.1) - inverse_square(656.3)));
+        let a = self.ir - b * inverse_square(587.6);
+        a + b * inverse_square(lambda)
     }
 
     /// Schlick近似菲涅尔方程
@@ -75,12 +220,50 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let mut rng = rand::thread_rng();
-        let refraction_ratio = if hit_record.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+        let direction = self.boundary_direction(ray_in, hit_record);
+        Some((
+            Vec3::new(1.0, 1.0, 1.0),
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+
+    fn is_specular(&self, _hit_record: &HitRecord) -> bool {
+        true
+    }
+
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut SampledWavelengths,
+    ) -> Option<(SampledSpectrum, Ray)> {
+        if self.abbe_number.is_none() {
+            let direction = self.boundary_direction(ray_in, hit_record);
+            return Some((
+                SampledSpectrum::constant(1.0),
+                Ray::with_time(hit_record.point, direction, ray_in.time()),
+            ));
+        }
+        // 不同波长折射到不同方向，只能沿主波长继续
+        wavelengths.terminate_secondary();
+        let direction =
+            Self::refract_or_reflect(self.ior_at(wavelengths.hero()), ray_in, hit_record);
+        Some((
+            SampledSpectrum::constant(1.0),
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+}
+
+impl Dielectric {
+    /// 在光滑的界面上按菲涅尔项随机选择反射或折射，返回出射方向
+    fn boundary_direction(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        Self::refract_or_reflect(self.ir, ray_in, hit_record)
+    }
+
+    fn refract_or_reflect(ir: Float, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let mut rng = sampler::rng();
+        let refraction_ratio = if hit_record.front_face { 1.0 / ir } else { ir };
 
         let unit_direction = ray_in.direction().unit_vector();
 
@@ -88,28 +271,646 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction;
-
+        // 着色法向和几何法向不同时，反射可能穿进几何表面，折射也可能留在射入的一侧
+        let g = hit_record.geometric_normal;
         if cannot_refract
             || Self::reflectance(cos_theta, refraction_ratio) > rng.gen_range(0.0, 1.0)
         {
-            direction = reflect(&unit_direction, &hit_record.normal);
+            mirror_above(reflect(&unit_direction, &hit_record.normal), &g)
         } else {
-            direction = refract(&unit_direction, &hit_record.normal, refraction_ratio);
+            mirror_above(
+                refract(&unit_direction, &hit_record.normal, refraction_ratio),
+                &-g,
+            )
         }
+    }
+}
 
+/// 次表面散射材质，用于皮肤、蜡、大理石和牛奶这类半透明的物体
+///
+/// 表面和`Dielectric`一样是光滑的界面，光折射进入物体后在内部随机游走：
+/// 每一步按消光系数抽样自由程，没有走到边界就在该处按Henyey-Greenstein相函数改变方向，
+/// 走到边界再按菲涅尔项离开或反射回内部。物体需要是封闭的，
+/// 平均自由程相对物体太小时游走步数很多，可能超过最大深度而变暗
+pub struct Subsurface {
+    color: Vec3,
+    /// 每个颜色通道的消光系数，是平均自由程的倒数
+    extinction: Vec3,
+    /// 单次散射的反照率，由多次散射后看到的颜色反推
+    single_scattering_albedo: Vec3,
+    anisotropy: Float,
+    boundary: Dielectric,
+}
+
+impl Subsurface {
+    /// `color`是多次散射后表面呈现的颜色，`mean_free_path`是每个通道光在内部平均走多远，
+    /// 红光走得远的材质（例如皮肤）看起来更透
+    pub fn new(color: Vec3, mean_free_path: Vec3, ir: Float) -> Self {
+        // Chiang等人2016年拟合的多次散射反照率到单次散射反照率的换算
+        let invert = |a: Float| {
+            let a = a.clamp(0.0, 1.0);
+            let x = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            (1.0 - x * x).clamp(0.0, 1.0)
+        };
+        let reciprocal = |d: Float| 1.0 / d.max(1e-6);
+        Subsurface {
+            color,
+            extinction: Vec3::new(
+                reciprocal(mean_free_path.x()),
+                reciprocal(mean_free_path.y()),
+                reciprocal(mean_free_path.z()),
+            ),
+            single_scattering_albedo: Vec3::new(
+                invert(color.x()),
+                invert(color.y()),
+                invert(color.z()),
+            ),
+            anisotropy: 0.0,
+            boundary: Dielectric::new(ir),
+        }
+    }
+
+    /// 相函数的各向异性，正值向前散射，0为各向同性
+    pub fn with_anisotropy(mut self, g: Float) -> Self {
+        self.anisotropy = g.clamp(-0.99, 0.99);
+        self
+    }
+
+    /// 按Henyey-Greenstein相函数抽样新的传播方向
+    fn sample_phase(&self, direction: &Vec3) -> Vec3 {
+        let mut rng = sampler::rng();
+        let g = self.anisotropy;
+        let u: Float = rng.gen_range(0.0, 1.0);
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - sq * sq) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+        let w = direction.unit_vector();
+        let helper = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u_axis = w.cross(&helper).unit_vector();
+        let v_axis = w.cross(&u_axis);
+        sin_theta * (phi.cos() * u_axis + phi.sin() * v_axis) + cos_theta * w
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        if hit_record.front_face {
+            return self.boundary.scatter(ray_in, hit_record);
+        }
+        // 射线在物体内部，走到这个交点之前可能已经发生了散射。
+        // 随机选一个通道抽样距离，再按三个通道的平均概率密度加权
+        let mut rng = sampler::rng();
+        let sigma = self.extinction;
+        let distance = hit_record.t * ray_in.direction().length();
+        let channel_sigma = match rng.gen_range(0, 3) {
+            0 => sigma.x(),
+            1 => sigma.y(),
+            _ => sigma.z(),
+        };
+        let s = -(1.0 - rng.gen_range(0.0 as Float, 1.0)).ln() / channel_sigma;
+        let transmittance = |d: Float| {
+            Vec3::new(
+                (-sigma.x() * d).exp(),
+                (-sigma.y() * d).exp(),
+                (-sigma.z() * d).exp(),
+            )
+        };
+        let average = |v: Vec3| (v.x() + v.y() + v.z()) / 3.0;
+
+        if s < distance {
+            let t = transmittance(s);
+            let pdf = average(sigma * t);
+            let weight = self.single_scattering_albedo * sigma * t / pdf;
+            let point = *ray_in.origin() + s * ray_in.direction().unit_vector();
+            let direction = self.sample_phase(ray_in.direction());
+            return Some((weight, Ray::with_time(point, direction, ray_in.time())));
+        }
+        let t = transmittance(distance);
+        let weight = t / average(t);
+        let direction = self.boundary.boundary_direction(ray_in, hit_record);
         Some((
-            Vec3::new(1.0, 1.0, 1.0),
-            Ray::new(hit_record.point, direction),
+            weight,
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
         ))
     }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        self.color
+    }
+}
+
+/// 发光的表面，不反射光线
+///
+/// 设置了聚光方向时只向锥形范围内发光，在内外锥角之间平滑衰减
+pub struct DiffuseLight {
+    emit: Vec3,
+    spot: Option<(Vec3, Float, Float)>,
+    /// 用光谱定义的发射光，只在光谱模式下使用
+    spectrum: Option<(Spectrum, Float)>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> Self {
+        DiffuseLight {
+            emit,
+            spot: None,
+            spectrum: None,
+        }
+    }
+
+    /// 发射光谱乘以`scale`的光源，例如`Spectrum::Blackbody(3000.0)`是暖白色的白炽灯
+    pub fn spectral(spectrum: Spectrum, scale: Float) -> Self {
+        DiffuseLight {
+            emit: scale * spectrum.to_rgb(),
+            spot: None,
+            spectrum: Some((spectrum, scale)),
+        }
+    }
+
+    /// 聚光灯，`direction`为光照射的方向，锥角为半角，单位为弧度
+    pub fn spot(
+        emit: Vec3,
+        direction: Vec3,
+        inner_cone_angle: Float,
+        outer_cone_angle: Float,
+    ) -> Self {
+        DiffuseLight {
+            emit,
+            spot: Some((
+                direction.unit_vector(),
+                inner_cone_angle.cos(),
+                outer_cone_angle.cos(),
+            )),
+            spectrum: None,
+        }
+    }
+
+    /// 聚光灯锥形范围带来的衰减
+    fn falloff(&self, ray_in: &Ray) -> Float {
+        match self.spot {
+            None => 1.0,
+            Some((direction, cos_inner, cos_outer)) => {
+                let cos = -ray_in.direction().unit_vector().dot(&direction);
+                let t = ((cos - cos_outer) / (cos_inner - cos_outer).max(1e-6)).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        None
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        self.emit
+    }
+
+    fn emitted(&self, ray_in: &Ray, _hit_record: &HitRecord) -> Vec3 {
+        self.falloff(ray_in) * self.emit
+    }
+
+    fn emitted_spectral(
+        &self,
+        ray_in: &Ray,
+        _hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let emit = match &self.spectrum {
+            Some((spectrum, scale)) => *scale * spectrum.sample(wavelengths),
+            None => SampledSpectrum::from_rgb(self.emit, wavelengths),
+        };
+        self.falloff(ray_in) * emit
+    }
+}
+
+/// glTF的金属度-粗糙度材质
+///
+/// 按菲涅尔项随机选择镜面反射或漫反射，镜面反射的模糊程度为粗糙度的平方。
+/// 贴图的值和对应的系数相乘（基础色还要乘上顶点颜色），金属度和粗糙度分别取贴图的蓝色和绿色通道，
+/// 环境光遮蔽贴图只削弱漫反射，用来补充几何体上没有的细节
+pub struct PbrMaterial {
+    base_color: Vec3,
+    base_color_texture: Option<Rc<dyn Texture>>,
+    metallic: Float,
+    roughness: Float,
+    metallic_roughness_texture: Option<Rc<dyn Texture>>,
+    emissive: Vec3,
+    emissive_texture: Option<Rc<dyn Texture>>,
+    normal_texture: Option<(Rc<dyn Texture>, Float)>,
+    occlusion_texture: Option<(Rc<dyn Texture>, Float)>,
+}
+
+impl PbrMaterial {
+    pub fn new(base_color: Vec3, metallic: Float, roughness: Float) -> Self {
+        PbrMaterial {
+            base_color,
+            base_color_texture: None,
+            metallic,
+            roughness,
+            metallic_roughness_texture: None,
+            emissive: Vec3::zero(),
+            emissive_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+        }
+    }
+
+    pub fn with_base_color_texture(mut self, texture: Rc<dyn Texture>) -> Self {
+        self.base_color_texture = Some(texture);
+        self
+    }
+
+    pub fn with_metallic_roughness_texture(mut self, texture: Rc<dyn Texture>) -> Self {
+        self.metallic_roughness_texture = Some(texture);
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: Vec3, texture: Option<Rc<dyn Texture>>) -> Self {
+        self.emissive = emissive;
+        self.emissive_texture = texture;
+        self
+    }
+
+    /// 切线空间的法线贴图，`scale`缩放法线的xy分量
+    pub fn with_normal_texture(mut self, texture: Rc<dyn Texture>, scale: Float) -> Self {
+        self.normal_texture = Some((texture, scale));
+        self
+    }
+
+    /// 环境光遮蔽贴图，取红色通道，`strength`为0时没有效果
+    pub fn with_occlusion_texture(mut self, texture: Rc<dyn Texture>, strength: Float) -> Self {
+        self.occlusion_texture = Some((texture, strength));
+        self
+    }
+
+    fn sample(texture: &Option<Rc<dyn Texture>>, hit_record: &HitRecord) -> Vec3 {
+        match texture {
+            Some(t) => t.value(hit_record.u, hit_record.v, &hit_record.point),
+            None => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// 经过法线贴图扰动的着色法向，切线方向未知时退化为插值的法向
+    fn shading_normal(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        match &self.normal_texture {
+            Some((texture, scale)) => {
+                let color = texture.value(hit_record.u, hit_record.v, &hit_record.point);
+                let normal = tangent_space_normal(hit_record, color, *scale);
+                face_viewer(normal, ray_in.direction())
+            }
+            None => hit_record.normal,
+        }
+    }
+}
+
+impl Material for PbrMaterial {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        let base_color = self.albedo(hit_record);
+        let mr = Self::sample(&self.metallic_roughness_texture, hit_record);
+        let metallic = (self.metallic * mr.z()).clamp(0.0, 1.0);
+        let roughness = (self.roughness * mr.y()).clamp(0.0, 1.0);
+        let normal = self.shading_normal(ray_in, hit_record);
+        let unit_direction = ray_in.direction().unit_vector();
+
+        let cos_theta = (-unit_direction).dot(&normal).clamp(0.0, 1.0);
+        let dielectric_f0 = Vec3::new(0.04, 0.04, 0.04);
+        let f0 = (1.0 - metallic) * dielectric_f0 + metallic * base_color;
+        let fresnel = f0 + (1.0 - cos_theta).powi(5) * (Vec3::new(1.0, 1.0, 1.0) - f0);
+        let specular_weight = (fresnel.x() + fresnel.y() + fresnel.z()) / 3.0;
+        let diffuse_weight = (1.0 - metallic) * (1.0 - specular_weight);
+        let p_specular = specular_weight / (specular_weight + diffuse_weight).max(1e-6);
+
+        let (attenuation, direction) = if rng.gen_range(0.0, 1.0) < p_specular {
+            let reflected = reflect(&unit_direction, &normal);
+            (
+                fresnel / p_specular,
+                reflected + roughness * roughness * random_in_uint_sphere(),
+            )
+        } else {
+            let occlusion = match &self.occlusion_texture {
+                Some((texture, strength)) => {
+                    let ao = texture.value(hit_record.u, hit_record.v, &hit_record.point);
+                    1.0 + strength * (ao.x() - 1.0)
+                }
+                None => 1.0,
+            };
+            let direction = normal + random_unit_vector();
+            let direction = if direction.squared_length() < 1e-12 {
+                normal
+            } else {
+                direction
+            };
+            (
+                occlusion * diffuse_weight / (1.0 - p_specular) * base_color,
+                direction,
+            )
+        };
+        // 法线贴图可能让出射方向穿到几何表面的另一侧
+        if direction.dot(&hit_record.geometric_normal) <= 0.0 {
+            return None;
+        }
+        Some((
+            attenuation,
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        let color = self.base_color * Self::sample(&self.base_color_texture, hit_record);
+        match hit_record.vertex_color {
+            Some(vertex_color) => color * vertex_color,
+            None => color,
+        }
+    }
+
+    fn emitted(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        if self.emissive.squared_length() == 0.0 {
+            return Vec3::zero();
+        }
+        self.emissive * Self::sample(&self.emissive_texture, hit_record)
+    }
+}
+
+/// Kajiya-Kay头发模型，用于曲线这类只有切线方向的表面
+///
+/// 漫反射正比于出射方向和发丝夹角的正弦，镜面反射集中在以发丝为轴的圆锥上，
+/// 出射方向在发丝方向上的分量和入射方向相同，`exponent`越大圆锥越窄。
+/// 切线取交点的`dpdu`，没有切线时退化为漫反射
+pub struct Hair {
+    diffuse: Vec3,
+    specular: Vec3,
+    exponent: Float,
+}
+
+impl Hair {
+    pub fn new(diffuse: Vec3, specular: Vec3, exponent: Float) -> Self {
+        Hair {
+            diffuse,
+            specular,
+            exponent,
+        }
+    }
+}
+
+impl Material for Hair {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        if hit_record.dpdu.squared_length() < 1e-12 {
+            let direction = hit_record.normal + random_unit_vector();
+            return Some((
+                self.diffuse,
+                Ray::with_time(hit_record.point, direction, ray_in.time()),
+            ));
+        }
+        let tangent = hit_record.dpdu.unit_vector();
+        let diffuse_weight = (self.diffuse.x() + self.diffuse.y() + self.diffuse.z()) / 3.0;
+        let specular_weight = (self.specular.x() + self.specular.y() + self.specular.z()) / 3.0;
+        if diffuse_weight + specular_weight <= 0.0 {
+            return None;
+        }
+        let p_specular = specular_weight / (diffuse_weight + specular_weight);
+
+        let (attenuation, direction) = if rng.gen_range(0.0, 1.0) < p_specular {
+            // 在圆锥附近按cos^exponent分布扰动和发丝的夹角，绕发丝的方位角均匀分布
+            let unit_direction = ray_in.direction().unit_vector();
+            let theta_in = unit_direction.dot(&tangent).clamp(-1.0, 1.0).asin();
+            let offset = rng
+                .gen_range(0.0 as Float, 1.0)
+                .powf(1.0 / (self.exponent + 1.0))
+                .acos();
+            let theta = if rng.gen_range(0.0, 1.0) < 0.5 {
+                theta_in + offset
+            } else {
+                theta_in - offset
+            };
+            let normal =
+                (hit_record.normal - hit_record.normal.dot(&tangent) * tangent).unit_vector();
+            let binormal = tangent.cross(&normal);
+            let phi = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+            let around = phi.cos() * normal + phi.sin() * binormal;
+            (
+                self.specular / p_specular,
+                theta.sin() * tangent + theta.cos() * around,
+            )
+        } else {
+            // 均匀采样球面，正弦的平均值是π/4
+            let direction = random_unit_vector();
+            let cos = direction.dot(&tangent);
+            let sin = (1.0 - cos * cos).max(0.0).sqrt();
+            (
+                sin * 4.0 / std::f32::consts::PI / (1.0 - p_specular) * self.diffuse,
+                direction,
+            )
+        };
+        Some((
+            attenuation,
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        self.diffuse
+    }
+}
+
+/// 按权重随机选择两种材质之一，例如生锈的金属和贴花
+///
+/// 权重为0时完全是`a`，为1时完全是`b`，纹理的权重取三个通道的平均值
+pub struct MixMaterial {
+    a: Rc<dyn Material>,
+    b: Rc<dyn Material>,
+    amount: Float,
+    texture: Option<Rc<dyn Texture>>,
+}
+
+impl MixMaterial {
+    pub fn new(a: Rc<dyn Material>, b: Rc<dyn Material>, amount: Float) -> Self {
+        MixMaterial {
+            a,
+            b,
+            amount,
+            texture: None,
+        }
+    }
+
+    /// 用纹理作为权重，和`amount`相乘
+    pub fn with_texture(mut self, texture: Rc<dyn Texture>) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    fn weight(&self, hit_record: &HitRecord) -> Float {
+        let w = match &self.texture {
+            Some(texture) => {
+                let c = texture.value(hit_record.u, hit_record.v, &hit_record.point);
+                self.amount * (c.x() + c.y() + c.z()) / 3.0
+            }
+            None => self.amount,
+        };
+        w.clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        if rng.gen_range(0.0, 1.0) < self.weight(hit_record) {
+            self.b.scatter(ray_in, hit_record)
+        } else {
+            self.a.scatter(ray_in, hit_record)
+        }
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        let w = self.weight(hit_record);
+        (1.0 - w) * self.a.albedo(hit_record) + w * self.b.albedo(hit_record)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let w = self.weight(hit_record);
+        (1.0 - w) * self.a.emitted(ray_in, hit_record) + w * self.b.emitted(ray_in, hit_record)
+    }
+
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut SampledWavelengths,
+    ) -> Option<(SampledSpectrum, Ray)> {
+        let mut rng = sampler::rng();
+        if rng.gen_range(0.0, 1.0) < self.weight(hit_record) {
+            self.b.scatter_spectral(ray_in, hit_record, wavelengths)
+        } else {
+            self.a.scatter_spectral(ray_in, hit_record, wavelengths)
+        }
+    }
+
+    fn emitted_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let w = self.weight(hit_record);
+        (1.0 - w) * self.a.emitted_spectral(ray_in, hit_record, wavelengths)
+            + w * self.b.emitted_spectral(ray_in, hit_record, wavelengths)
+    }
+
+    /// 两种材质都能求值时才能求值
+    fn bsdf(&self, hit_record: &HitRecord, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, Float)> {
+        let (fa, pdf_a) = self.a.bsdf(hit_record, wo, wi)?;
+        let (fb, pdf_b) = self.b.bsdf(hit_record, wo, wi)?;
+        let w = self.weight(hit_record);
+        Some(((1.0 - w) * fa + w * fb, (1.0 - w) * pdf_a + w * pdf_b))
+    }
+
+    fn is_specular(&self, hit_record: &HitRecord) -> bool {
+        self.a.is_specular(hit_record) && self.b.is_specular(hit_record)
+    }
+}
+
+/// 透明度遮罩，被挖空的地方光线沿原方向直接穿过，用一个面片就能做出树叶和铁丝网
+///
+/// 遮罩纹理取三个通道的平均值作为不透明度，默认按不透明度随机决定是否穿过，
+/// 设置了阈值时低于阈值的地方完全透明，其余完全不透明，和glTF的`MASK`模式相同
+pub struct AlphaMask {
+    base: Rc<dyn Material>,
+    mask: Rc<dyn Texture>,
+    cutoff: Option<Float>,
+}
+
+impl AlphaMask {
+    pub fn new(base: Rc<dyn Material>, mask: Rc<dyn Texture>) -> Self {
+        AlphaMask {
+            base,
+            mask,
+            cutoff: None,
+        }
+    }
+
+    pub fn with_cutoff(mut self, cutoff: Float) -> Self {
+        self.cutoff = Some(cutoff);
+        self
+    }
+
+    fn opacity(&self, hit_record: &HitRecord) -> Float {
+        let c = self
+            .mask
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        let alpha = ((c.x() + c.y() + c.z()) / 3.0).clamp(0.0, 1.0);
+        match self.cutoff {
+            Some(cutoff) if alpha < cutoff => 0.0,
+            Some(_) => 1.0,
+            None => alpha,
+        }
+    }
+}
+
+impl Material for AlphaMask {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        let opacity = self.opacity(hit_record);
+        if opacity < 1.0 && rng.gen_range(0.0, 1.0) >= opacity {
+            return Some((
+                Vec3::new(1.0, 1.0, 1.0),
+                Ray::with_time(hit_record.point, *ray_in.direction(), ray_in.time()),
+            ));
+        }
+        self.base.scatter(ray_in, hit_record)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        self.base.albedo(hit_record)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        self.opacity(hit_record) * self.base.emitted(ray_in, hit_record)
+    }
+
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut SampledWavelengths,
+    ) -> Option<(SampledSpectrum, Ray)> {
+        let mut rng = sampler::rng();
+        let opacity = self.opacity(hit_record);
+        if opacity < 1.0 && rng.gen_range(0.0, 1.0) >= opacity {
+            return Some((
+                SampledSpectrum::constant(1.0),
+                Ray::with_time(hit_record.point, *ray_in.direction(), ray_in.time()),
+            ));
+        }
+        self.base.scatter_spectral(ray_in, hit_record, wavelengths)
+    }
+
+    fn emitted_spectral(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.opacity(hit_record) * self.base.emitted_spectral(ray_in, hit_record, wavelengths)
+    }
 }
 
 /// 返回一个三维空间内的随机向量
 /// 首先筛选在以原点为球心半径小于1的球内的向量
 /// 这样能保证是均匀的分布
 pub fn random_in_uint_sphere() -> Vec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
     loop {
         let p = Vec3::new(
             rng.gen_range(-1.0, 1.0),
@@ -122,8 +923,9 @@ pub fn random_in_uint_sphere() -> Vec3 {
     }
 }
 
-fn random_unit_vector() -> Vec3 {
-    let mut rng = rand::thread_rng();
+/// 单位球面上均匀分布的随机向量
+pub fn random_unit_vector() -> Vec3 {
+    let mut rng = sampler::rng();
     let a = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
     let z: Float = rng.gen_range(-1.0, 1.0);
     let r = (1.0 - z * z).sqrt();
@@ -134,6 +936,18 @@ pub fn reflect(vector: &Vec3, normal: &Vec3) -> Vec3 {
     vector - 2.0 * vector.dot(normal) * normal
 }
 
+/// 在`normal`所指的一侧之外的方向沿垂直于`normal`的平面镜像回来
+///
+/// 着色法向和几何法向不同时，按着色法向得到的方向可能穿到几何表面的另一侧，
+/// 丢弃这些方向会损失能量，镜像回来则留在正确的一侧
+fn mirror_above(direction: Vec3, normal: &Vec3) -> Vec3 {
+    if direction.dot(normal) < 0.0 {
+        reflect(&direction, normal)
+    } else {
+        direction
+    }
+}
+
 /// 折射
 pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: Float) -> Vec3 {
     let cos_theta = -uv.dot(n);
@@ -141,3 +955,120 @@ pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: Float) -> Vec3 {
     let r_out_parallel = -(1.0 - r_out_perp.squared_length()).abs().sqrt() * n;
     r_out_perp + r_out_parallel
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_subsurface_random_walk_is_unbiased() {
+        // 白色的介质不吸收光，无论自由程怎么抽样，每个通道的期望权重都是1
+        let material = Rc::new(Subsurface::new(
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.5, 1.0, 2.0),
+            1.3,
+        ));
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 2.0));
+        // 射线从内部射向z = 1处的界面
+        let hit = HitRecord::new(
+            0.5,
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            material.clone(),
+            &ray,
+        );
+        assert!(!hit.front_face);
+        let n = 100_000;
+        let mut sum = Vec3::zero();
+        let mut scattered_inside = 0;
+        for _ in 0..n {
+            let (weight, scattered) = material.scatter(&ray, &hit).unwrap();
+            sum += weight;
+            if scattered.origin().z() < 0.999 {
+                scattered_inside += 1;
+            }
+        }
+        let mean = sum / n as Float;
+        for c in [mean.x(), mean.y(), mean.z()].iter() {
+            assert!((c - 1.0).abs() < 0.03, "mean weight {:?}", mean);
+        }
+        // 三个通道自由程的平均逃逸概率约为(e^-2 + e^-1 + e^-0.5) / 3
+        let escape = 1.0 - scattered_inside as Float / n as Float;
+        assert!((escape - 0.370).abs() < 0.01, "escape {}", escape);
+    }
+
+    #[test]
+    fn test_mix_and_alpha_mask() {
+        use crate::texture::SolidColor;
+        let red: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(1.0, 0.0, 0.0)));
+        let blue: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.0, 0.0, 1.0)));
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = |material: Rc<dyn Material>| {
+            HitRecord::new(1.0, Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), material, &ray)
+        };
+
+        // 纹理权重0.5乘以0.5，四分之一的概率选中蓝色
+        let mix: Rc<dyn Material> = Rc::new(
+            MixMaterial::new(red.clone(), blue.clone(), 0.5)
+                .with_texture(Rc::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5)))),
+        );
+        let record = hit(mix.clone());
+        let n = 20_000;
+        let blue_count = (0..n)
+            .filter(|_| mix.scatter(&ray, &record).unwrap().0.z() > 0.0)
+            .count();
+        assert!((blue_count as Float / n as Float - 0.25).abs() < 0.02);
+        assert_eq!(mix.albedo(&record), Vec3::new(0.75, 0.0, 0.25));
+
+        // 低于阈值的地方总是沿原方向穿过，权重为1
+        let mask: Rc<dyn Material> = Rc::new(
+            AlphaMask::new(red, Rc::new(SolidColor::new(Vec3::new(0.3, 0.3, 0.3))))
+                .with_cutoff(0.5),
+        );
+        let record = hit(mask.clone());
+        for _ in 0..100 {
+            let (attenuation, scattered) = mask.scatter(&ray, &record).unwrap();
+            assert_eq!(attenuation, Vec3::new(1.0, 1.0, 1.0));
+            assert_eq!(*scattered.direction(), *ray.direction());
+        }
+    }
+
+    #[test]
+    fn test_tilted_shading_normal_keeps_energy() {
+        // 着色法向背离视线倾斜，沿它反射会穿到朝上的几何表面下方
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let hit = |material: Rc<dyn Material>| {
+            let mut record = HitRecord::new(1.0, Vec3::zero(), up, material, &ray);
+            record.normal = Vec3::new(0.5, 0.866, 0.0).unit_vector();
+            record
+        };
+        let n = 10_000;
+        let diffuse: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        let mirror: Rc<dyn Material> = Rc::new(Metal::new(&Vec3::new(0.9, 0.9, 0.9), 0.0));
+        for material in &[diffuse, mirror] {
+            let record = hit(material.clone());
+            for _ in 0..n {
+                let (_, scattered) = material.scatter(&ray, &record).unwrap();
+                assert!(scattered.direction().dot(&up) > 0.0);
+            }
+        }
+
+        // 菲涅尔反射率约为0.25，反射的方向留在上方，折射的方向进入下方
+        let glass: Rc<dyn Material> = Rc::new(Dielectric::new(1.5));
+        let record = hit(glass.clone());
+        let reflected = (0..n)
+            .filter(|_| glass.scatter(&ray, &record).unwrap().1.direction().dot(&up) > 0.0)
+            .count();
+        assert!((reflected as Float / n as Float - 0.25).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let glass = Dielectric::new(1.5).with_abbe_number(40.0);
+        assert!((glass.ior_at(587.6) - 1.5).abs() < 1e-5);
+        let spread = glass.ior_at(486.1) - glass.ior_at(656.3);
+        assert!((spread - 0.5 / 40.0).abs() < 1e-5);
+        assert_eq!(Dielectric::new(1.5).ior_at(450.0), 1.5);
+    }
+}
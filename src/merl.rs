@@ -0,0 +1,292 @@
+//! 读取MERL格式的测量BRDF
+//!
+//! 文件开头是三个32位整数表示的表格大小（90、90、180），之后依次是红、绿、蓝三个通道的双精度浮点数表格。
+//! 表格按半角向量和差角向量参数化（Rusinkiewicz 1998）：半角向量的天顶角θh非线性地分成90份，
+//! 差角向量的天顶角θd和方位角φd分别分成90份和180份，各向同性的材质不依赖半角向量的方位角
+
+use crate::hittable::HitRecord;
+use crate::material::{random_unit_vector, Material};
+use crate::microfacet::{shading_frame, to_local, to_world};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::{Float, Vec3};
+use rand::Rng;
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+const THETA_H: usize = 90;
+const THETA_D: usize = 90;
+const PHI_D: usize = 180;
+
+/// 三个通道的缩放系数，来自MERL数据集附带的读取代码
+const SCALE: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// θh的下标，为了在镜面反射附近有更多的样本，下标和角度的平方根成正比
+fn theta_h_index(theta_h: Float) -> usize {
+    let x = (theta_h / (0.5 * PI)).max(0.0).sqrt() * THETA_H as Float;
+    (x as usize).min(THETA_H - 1)
+}
+
+/// 第`i`个θh区间的范围
+fn theta_h_range(i: usize) -> (Float, Float) {
+    let angle = |i: usize| (i as Float / THETA_H as Float).powi(2) * 0.5 * PI;
+    (angle(i), angle(i + 1))
+}
+
+/// 用测量得到的表格表示的各向同性BRDF
+///
+/// 抽样时一半的概率按余弦分布，一半的概率按表格里每个θh区间的平均反射率抽样半角向量，
+/// 再按两种策略的平均概率密度加权，镜面和漫反射的材质都不会有太大的噪点
+pub struct MeasuredBrdf {
+    table: Vec<Vec3>,
+    /// 按θh抽样半角向量的累积分布
+    theta_h_cdf: Vec<Float>,
+}
+
+impl MeasuredBrdf {
+    /// `table`按MERL的顺序排列，已经乘过缩放系数
+    fn new(table: Vec<Vec3>) -> Self {
+        let mut theta_h_cdf = Vec::with_capacity(THETA_H + 1);
+        theta_h_cdf.push(0.0);
+        let slice = THETA_D * PHI_D;
+        for i in 0..THETA_H {
+            let sum = table[i * slice..(i + 1) * slice]
+                .iter()
+                .fold(0.0, |sum, c| sum + (c.x() + c.y() + c.z()) / 3.0);
+            let (low, high) = theta_h_range(i);
+            let mid = 0.5 * (low + high);
+            let weight = sum / slice as Float * mid.sin() * mid.cos() * (high - low);
+            theta_h_cdf.push(theta_h_cdf[i] + weight.max(0.0));
+        }
+        let total = theta_h_cdf[THETA_H];
+        if total > 0.0 {
+            for c in theta_h_cdf.iter_mut() {
+                *c /= total;
+            }
+        } else {
+            theta_h_cdf = (0..=THETA_H)
+                .map(|i| i as Float / THETA_H as Float)
+                .collect();
+        }
+        MeasuredBrdf { table, theta_h_cdf }
+    }
+
+    /// 局部坐标系中入射方向`wi`和出射方向`wo`之间的BRDF值
+    pub fn evaluate(&self, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return Vec3::zero();
+        }
+        let h = (*wi + *wo).unit_vector();
+        let theta_h = h.z().clamp(-1.0, 1.0).acos();
+        let phi_h = h.y().atan2(h.x());
+        // 把入射方向转到半角向量为z轴的坐标系：先绕z轴转-φh，再绕y轴转-θh
+        let (sin_p, cos_p) = (-phi_h).sin_cos();
+        let w = Vec3::new(
+            cos_p * wi.x() - sin_p * wi.y(),
+            sin_p * wi.x() + cos_p * wi.y(),
+            wi.z(),
+        );
+        let (sin_t, cos_t) = (-theta_h).sin_cos();
+        let d = Vec3::new(
+            cos_t * w.x() + sin_t * w.z(),
+            w.y(),
+            -sin_t * w.x() + cos_t * w.z(),
+        );
+        let theta_d = d.z().clamp(-1.0, 1.0).acos();
+        let mut phi_d = d.y().atan2(d.x());
+        // 互易性，φd和φd + π相同
+        if phi_d < 0.0 {
+            phi_d += PI;
+        }
+        let i = theta_h_index(theta_h);
+        let j = ((theta_d / (0.5 * PI) * THETA_D as Float) as usize).min(THETA_D - 1);
+        let k = ((phi_d / PI * PHI_D as Float) as usize).min(PHI_D - 1);
+        self.table[(i * THETA_D + j) * PHI_D + k]
+    }
+
+    /// 按θh的累积分布抽样半角向量，θh区间内均匀分布，方位角均匀分布
+    fn sample_half_vector(&self, u1: Float, u2: Float) -> Vec3 {
+        let i = self.theta_h_cdf[1..]
+            .iter()
+            .position(|&c| c > u1)
+            .unwrap_or(THETA_H - 1);
+        let (low, high) = theta_h_range(i);
+        let width = (self.theta_h_cdf[i + 1] - self.theta_h_cdf[i]).max(1e-12);
+        let t = ((u1 - self.theta_h_cdf[i]) / width).clamp(0.0, 1.0);
+        let theta = low + t * (high - low);
+        let phi = 2.0 * PI * u2;
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        )
+    }
+
+    /// 半角向量抽样策略下`wi`的概率密度
+    fn half_vector_pdf(&self, wi: &Vec3, wo: &Vec3) -> Float {
+        let h = (*wi + *wo).unit_vector();
+        let theta = h.z().clamp(-1.0, 1.0).acos();
+        let i = theta_h_index(theta);
+        let (low, high) = theta_h_range(i);
+        let p = self.theta_h_cdf[i + 1] - self.theta_h_cdf[i];
+        let sin = theta.sin().max(1e-6);
+        let pdf_h = p / (high - low) / (2.0 * PI * sin);
+        pdf_h / (4.0 * wo.dot(&h).abs().max(1e-6))
+    }
+}
+
+impl Material for MeasuredBrdf {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        let frame = shading_frame(hit_record);
+        let wo = to_local(&frame, &-ray_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wi = if rng.gen_range(0.0, 1.0) < 0.5 {
+            let d = Vec3::new(0.0, 0.0, 1.0) + random_unit_vector();
+            if d.squared_length() < 1e-12 {
+                return None;
+            }
+            d.unit_vector()
+        } else {
+            let h = self.sample_half_vector(rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0));
+            2.0 * wo.dot(&h) * h - wo
+        };
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let direction = to_world(&frame, &wi);
+        if direction.dot(&hit_record.geometric_normal) <= 0.0 {
+            return None;
+        }
+        let pdf = 0.5 * wi.z() / PI + 0.5 * self.half_vector_pdf(&wi, &wo);
+        let weight = self.evaluate(&wi, &wo) * (wi.z() / pdf);
+        Some((
+            weight,
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+
+    fn bsdf(&self, hit_record: &HitRecord, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, Float)> {
+        let frame = shading_frame(hit_record);
+        let wo = to_local(&frame, wo);
+        let wi = to_local(&frame, wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Some((Vec3::zero(), 0.0));
+        }
+        let pdf = 0.5 * wi.z() / PI + 0.5 * self.half_vector_pdf(&wi, &wo);
+        Some((self.evaluate(&wi, &wo), pdf))
+    }
+
+    /// 正入射时反射率的近似值
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        let wi = Vec3::new(0.5, 0.0, 0.866);
+        PI * self.evaluate(&wi, &wo)
+    }
+}
+
+/// 解析MERL二进制文件的内容
+pub fn parse(bytes: &[u8]) -> io::Result<MeasuredBrdf> {
+    if bytes.len() < 12 {
+        return Err(invalid("MERL file is too short".to_string()));
+    }
+    let dims: Vec<usize> = (0..3)
+        .map(|i| {
+            i32::from_le_bytes([
+                bytes[4 * i],
+                bytes[4 * i + 1],
+                bytes[4 * i + 2],
+                bytes[4 * i + 3],
+            ]) as usize
+        })
+        .collect();
+    if dims != [THETA_H, THETA_D, PHI_D] {
+        return Err(invalid(format!(
+            "unexpected MERL table size {}x{}x{}",
+            dims[0], dims[1], dims[2]
+        )));
+    }
+    let n = THETA_H * THETA_D * PHI_D;
+    if bytes.len() != 12 + 3 * n * 8 {
+        return Err(invalid(format!(
+            "MERL file has {} bytes, expected {}",
+            bytes.len(),
+            12 + 3 * n * 8
+        )));
+    }
+    let value = |channel: usize, i: usize| {
+        let offset = 12 + 8 * (channel * n + i);
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&bytes[offset..offset + 8]);
+        // 没有测量到的值为负数
+        (f64::from_le_bytes(b) * SCALE[channel]).max(0.0) as Float
+    };
+    let table = (0..n)
+        .map(|i| Vec3::new(value(0, i), value(1, i), value(2, i)))
+        .collect();
+    Ok(MeasuredBrdf::new(table))
+}
+
+/// 读取MERL二进制文件，例如`gold-metallic-paint.binary`
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<MeasuredBrdf> {
+    parse(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 数值为常数的表格就是朗伯反射
+    fn lambertian(reflectance: Float) -> Vec<u8> {
+        let n = THETA_H * THETA_D * PHI_D;
+        let mut bytes = Vec::with_capacity(12 + 24 * n);
+        for &d in &[THETA_H, THETA_D, PHI_D] {
+            bytes.extend_from_slice(&(d as i32).to_le_bytes());
+        }
+        for &scale in &SCALE {
+            let value = (reflectance / PI) as f64 / scale;
+            for _ in 0..n {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_parse_and_sample() {
+        assert!(parse(&lambertian(0.5)[..1000]).is_err());
+        let brdf = parse(&lambertian(0.5)).unwrap();
+        let wo = Vec3::new(0.3, 0.1, 0.8).unit_vector();
+        let wi = Vec3::new(-0.6, 0.2, 0.4).unit_vector();
+        let f = brdf.evaluate(&wi, &wo);
+        assert!((f.x() * PI - 0.5).abs() < 1e-5 && (f.z() * PI - 0.5).abs() < 1e-5);
+        assert_eq!(brdf.evaluate(&-wi, &wo), Vec3::zero());
+
+        // 抽样权重的期望等于反射率
+        let material: std::rc::Rc<dyn Material> = std::rc::Rc::new(brdf);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+        let hit = HitRecord::new(
+            1.0,
+            Vec3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+            material.clone(),
+            &ray,
+        );
+        let n = 200_000;
+        let mut sum = Vec3::zero();
+        for _ in 0..n {
+            if let Some((weight, _)) = material.scatter(&ray, &hit) {
+                sum += weight;
+            }
+        }
+        let mean = sum / n as Float;
+        assert!((mean.y() - 0.5).abs() < 0.02, "mean {:?}", mean);
+    }
+}
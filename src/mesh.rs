@@ -0,0 +1,309 @@
+//! 三角形网格，每个网格内部有自己的BVH，在场景里作为一个物体
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::transform::Transform;
+use crate::vec3::{Float, Vec3};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// 网格的顶点数据，可选的属性要么为空，要么和顶点一一对应
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    /// 每个三角形三个顶点的下标，逆时针为正面
+    pub indices: Vec<[usize; 3]>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<[Float; 2]>,
+    /// 切线方向和副切线的手性（±1），副切线为`normal × tangent * w`
+    pub tangents: Vec<(Vec3, Float)>,
+    /// 线性空间的顶点颜色
+    pub colors: Vec<Vec3>,
+}
+
+impl Mesh {
+    /// 所有顶点的包围盒，没有顶点时返回`None`
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.positions
+            .iter()
+            .map(|p| Aabb::new(*p, *p))
+            .reduce(|a, b| a.surrounding(&b))
+    }
+
+    /// 把顶点变换到新的坐标系下
+    pub fn transform(&mut self, transform: &Transform) {
+        for p in self.positions.iter_mut() {
+            *p = transform.point(p);
+        }
+        for n in self.normals.iter_mut() {
+            *n = transform.normal(n).unit_vector();
+        }
+        for (t, _) in self.tangents.iter_mut() {
+            *t = transform.vector(t).unit_vector();
+        }
+    }
+
+    /// 把位置相同的顶点归为一组，返回每个顶点所在组的编号和组的个数
+    ///
+    /// 纹理接缝处的顶点会被拆成几份，它们在几何上仍然是同一个点
+    pub fn weld(&self) -> (Vec<usize>, usize) {
+        let mut groups = HashMap::new();
+        let ids = self
+            .positions
+            .iter()
+            .map(|p| {
+                let key = [p.x().to_bits(), p.y().to_bits(), p.z().to_bits()];
+                let next = groups.len();
+                *groups.entry(key).or_insert(next)
+            })
+            .collect();
+        (ids, groups.len())
+    }
+
+    /// 用相邻三角形按面积加权重新计算顶点法向，位置相同的顶点共用一个法向
+    pub fn compute_normals(&mut self) {
+        let (ids, count) = self.weld();
+        let mut normals = vec![Vec3::zero(); count];
+        for &[i0, i1, i2] in &self.indices {
+            let p0 = self.positions[i0];
+            // 叉积的长度是面积的两倍，直接累加就是按面积加权
+            let n = (self.positions[i1] - p0).cross(&(self.positions[i2] - p0));
+            for &i in &[i0, i1, i2] {
+                normals[ids[i]] += n;
+            }
+        }
+        self.normals = ids
+            .iter()
+            .map(|&id| {
+                let n = normals[id];
+                if n.squared_length() > 0.0 {
+                    n.unit_vector()
+                } else {
+                    Vec3::new(0.0, 1.0, 0.0)
+                }
+            })
+            .collect();
+    }
+
+    /// 位移贴图：顶点沿法向移动纹理亮度乘以`scale`的距离，之后重新计算法向。
+    /// 没有纹理坐标时所有顶点都取纹理在`(0, 0)`处的值
+    ///
+    /// 接缝两侧的顶点取平均位移，避免网格裂开。细节的精细程度取决于顶点的密度，
+    /// 通常先细分再做位移
+    pub fn displace(&mut self, texture: &dyn Texture, scale: Float) {
+        if self.normals.is_empty() {
+            self.compute_normals();
+        }
+        let (ids, count) = self.weld();
+        let mut offsets = vec![(Vec3::zero(), 0.0); count];
+        for (i, p) in self.positions.iter().enumerate() {
+            let [u, v] = self.uvs.get(i).copied().unwrap_or([0.0, 0.0]);
+            let value = texture.value(u, v, p);
+            let height = (value.x() + value.y() + value.z()) / 3.0;
+            let offset = &mut offsets[ids[i]];
+            offset.0 += height * scale * self.normals[i];
+            offset.1 += 1.0;
+        }
+        for (i, p) in self.positions.iter_mut().enumerate() {
+            let (sum, n) = offsets[ids[i]];
+            *p += sum / n;
+        }
+        self.tangents.clear();
+        self.compute_normals();
+    }
+}
+
+struct Triangle {
+    mesh: Rc<Mesh>,
+    material: Rc<dyn Material>,
+    index: usize,
+}
+
+impl Triangle {
+    fn vertices(&self) -> [usize; 3] {
+        self.mesh.indices[self.index]
+    }
+
+    /// 没有纹理坐标时用重心坐标代替
+    fn uvs(&self) -> [[Float; 2]; 3] {
+        let [i0, i1, i2] = self.vertices();
+        if self.mesh.uvs.is_empty() {
+            [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]
+        } else {
+            [self.mesh.uvs[i0], self.mesh.uvs[i1], self.mesh.uvs[i2]]
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    /// Möller–Trumbore算法
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+        let (p0, p1, p2) = (positions[i0], positions[i1], positions[i2]);
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+        let pvec = ray.direction().cross(&e2);
+        let det = e1.dot(&pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let tvec = ray.origin() - p0;
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = tvec.cross(&e1);
+        let b2 = ray.direction().dot(&qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = e2.dot(&qvec) * inv_det;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        let geometric_normal = e1.cross(&e2).unit_vector();
+        let normal = if self.mesh.normals.is_empty() {
+            geometric_normal
+        } else {
+            let normals = &self.mesh.normals;
+            let n = b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2];
+            // 插值法向和几何法向不在同一侧时以几何法向为准
+            if n.dot(&geometric_normal) < 0.0 {
+                -n.unit_vector()
+            } else {
+                n.unit_vector()
+            }
+        };
+
+        let [uv0, uv1, uv2] = self.uvs();
+        let u = b0 * uv0[0] + b1 * uv1[0] + b2 * uv2[0];
+        let v = b0 * uv0[1] + b1 * uv1[1] + b2 * uv2[1];
+
+        let (dpdu, dpdv) = if self.mesh.tangents.is_empty() {
+            // 解 e1 = du1·dpdu + dv1·dpdv，e2 = du2·dpdu + dv2·dpdv
+            let (du1, dv1) = (uv1[0] - uv0[0], uv1[1] - uv0[1]);
+            let (du2, dv2) = (uv2[0] - uv0[0], uv2[1] - uv0[1]);
+            let uv_det = du1 * dv2 - dv1 * du2;
+            if uv_det.abs() < 1e-12 {
+                (Vec3::zero(), Vec3::zero())
+            } else {
+                (
+                    (dv2 * e1 - dv1 * e2) / uv_det,
+                    (du1 * e2 - du2 * e1) / uv_det,
+                )
+            }
+        } else {
+            let tangents = &self.mesh.tangents;
+            let tangent = b0 * tangents[i0].0 + b1 * tangents[i1].0 + b2 * tangents[i2].0;
+            (tangent, tangents[i0].1 * normal.cross(&tangent))
+        };
+
+        let vertex_color = if self.mesh.colors.is_empty() {
+            None
+        } else {
+            let colors = &self.mesh.colors;
+            Some(b0 * colors[i0] + b1 * colors[i1] + b2 * colors[i2])
+        };
+
+        let point = ray.point_at_parameter(&t);
+        let geometric_normal = if geometric_normal.dot(ray.direction()) < 0.0 {
+            geometric_normal
+        } else {
+            -geometric_normal
+        };
+        Some(HitRecord {
+            u,
+            v,
+            dpdu,
+            dpdv,
+            vertex_color,
+            barycentric: Some(Vec3::new(b0, b1, b2)),
+            geometric_normal,
+            ..HitRecord::new(t, point, normal, Rc::clone(&self.material), ray)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+        let bbox = Aabb::new(positions[i0], positions[i0])
+            .surrounding(&Aabb::new(positions[i1], positions[i1]))
+            .surrounding(&Aabb::new(positions[i2], positions[i2]));
+        // 和坐标轴平行的三角形包围盒厚度为0，稍微加厚一点
+        let pad = Vec3::new(1e-4, 1e-4, 1e-4);
+        Some(Aabb::new(bbox.min() - pad, bbox.max() + pad))
+    }
+}
+
+/// 由网格构建的物体，整个网格使用同一种材质
+pub struct TriangleMesh {
+    triangles: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh: Mesh, material: Rc<dyn Material>) -> Self {
+        let mesh = Rc::new(mesh);
+        let triangles = (0..mesh.indices.len())
+            .map(|index| {
+                Box::new(Triangle {
+                    mesh: Rc::clone(&mesh),
+                    material: Rc::clone(&material),
+                    index,
+                }) as Box<dyn Hittable>
+            })
+            .collect();
+        TriangleMesh {
+            triangles: Bvh::new(triangles),
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        self.triangles.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.triangles.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_hit_interpolates_uv() {
+        let mesh = Mesh {
+            positions: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+            ],
+            indices: vec![[0, 1, 2]],
+            uvs: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+            ..Mesh::default()
+        };
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        let object = TriangleMesh::new(mesh, material);
+
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = object.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!((hit.u - 0.25).abs() < 1e-5 && (hit.v - 0.25).abs() < 1e-5);
+        assert!((hit.normal.z() - 1.0).abs() < 1e-5);
+        assert!((hit.dpdu.x() - 2.0).abs() < 1e-5 && (hit.dpdv.y() - 2.0).abs() < 1e-5);
+
+        let miss = Ray::new(Vec3::new(1.5, 1.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(object.hit(&miss, 0.001, Float::MAX).is_none());
+    }
+}
@@ -0,0 +1,231 @@
+//! 微表面模型：表面由许多朝向不同的小镜面组成，GGX分布描述镜面法向的分布
+//!
+//! 局部坐标系以着色法向为z轴，x轴沿切线方向。x和y方向的粗糙度不同时就是各向异性的，
+//! 例如拉丝金属沿拉丝方向的高光被拉长
+
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::{Float, Vec3};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// 以着色法向为z轴的局部坐标系`(切线, 副切线, 法向)`，切线取交点的`dpdu`，没有时任取一个
+pub fn shading_frame(hit_record: &HitRecord) -> (Vec3, Vec3, Vec3) {
+    let n = hit_record.normal;
+    let tangent = hit_record.dpdu - hit_record.dpdu.dot(&n) * n;
+    let tangent = if tangent.squared_length() < 1e-12 {
+        let helper = if n.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        n.cross(&helper).unit_vector()
+    } else {
+        tangent.unit_vector()
+    };
+    (tangent, n.cross(&tangent), n)
+}
+
+/// 世界坐标的向量转换到局部坐标系
+pub fn to_local(frame: &(Vec3, Vec3, Vec3), v: &Vec3) -> Vec3 {
+    Vec3::new(v.dot(&frame.0), v.dot(&frame.1), v.dot(&frame.2))
+}
+
+/// 局部坐标系的向量转换回世界坐标
+pub fn to_world(frame: &(Vec3, Vec3, Vec3), v: &Vec3) -> Vec3 {
+    v.x() * frame.0 + v.y() * frame.1 + v.z() * frame.2
+}
+
+/// GGX（Trowbridge-Reitz）微表面分布，下面的向量都在局部坐标系中
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ggx {
+    alpha_x: Float,
+    alpha_y: Float,
+}
+
+impl Ggx {
+    /// 粗糙度按惯例取平方作为分布的宽度
+    pub fn new(roughness_x: Float, roughness_y: Float) -> Self {
+        let alpha = |r: Float| (r * r).clamp(1e-3, 1.0);
+        Ggx {
+            alpha_x: alpha(roughness_x),
+            alpha_y: alpha(roughness_y),
+        }
+    }
+
+    /// 法向分布函数
+    pub fn d(&self, h: &Vec3) -> Float {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let x = h.x() / self.alpha_x;
+        let y = h.y() / self.alpha_y;
+        let t = x * x + y * y + h.z() * h.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    /// Smith遮挡函数里的Λ
+    fn lambda(&self, w: &Vec3) -> Float {
+        let z2 = w.z() * w.z();
+        if z2 == 0.0 {
+            return Float::INFINITY;
+        }
+        let a2 = (self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2);
+        0.5 * ((1.0 + a2 / z2).sqrt() - 1.0)
+    }
+
+    /// 从方向`w`看过去没有被遮挡的比例
+    pub fn g1(&self, w: &Vec3) -> Float {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// 入射和出射方向同时不被遮挡的比例
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// 按从`wo`方向可见的法向分布抽样（Heitz 2018），`u1`、`u2`为`[0, 1)`上的随机数
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: Float, u2: Float) -> Vec3 {
+        // 拉伸成粗糙度为1的各向同性分布，在投影的半圆盘上均匀抽样
+        let v = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit_vector();
+        let length2 = v.x() * v.x() + v.y() * v.y();
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-v.y(), v.x(), 0.0) / length2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross(&t1);
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+        Vec3::new(self.alpha_x * n.x(), self.alpha_y * n.y(), n.z().max(1e-6)).unit_vector()
+    }
+}
+
+/// 各向异性的GGX金属，例如拉丝金属
+///
+/// `roughness_u`沿切线方向`dpdu`，`roughness_v`沿副切线方向，切线可以绕法向旋转。
+/// 按可见法向抽样，权重为菲涅尔项乘以G/G1，菲涅尔项用以`albedo`为正入射反射率的Schlick近似
+pub struct AnisotropicMetal {
+    albedo: Vec3,
+    distribution: Ggx,
+    rotation: Float,
+}
+
+impl AnisotropicMetal {
+    pub fn new(albedo: &Vec3, roughness_u: Float, roughness_v: Float) -> Self {
+        AnisotropicMetal {
+            albedo: *albedo,
+            distribution: Ggx::new(roughness_u, roughness_v),
+            rotation: 0.0,
+        }
+    }
+
+    /// 切线绕法向旋转的角度，单位为度
+    pub fn with_rotation(mut self, degrees: Float) -> Self {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    /// 切线旋转过的局部坐标系
+    fn frame(&self, hit_record: &HitRecord) -> (Vec3, Vec3, Vec3) {
+        let (t, b, n) = shading_frame(hit_record);
+        let (sin, cos) = self.rotation.sin_cos();
+        (cos * t + sin * b, cos * b - sin * t, n)
+    }
+
+    /// 以`albedo`为正入射反射率的Schlick菲涅尔项
+    fn fresnel(&self, wo: &Vec3, h: &Vec3) -> Vec3 {
+        let cos_d = wo.dot(h).clamp(0.0, 1.0);
+        self.albedo + (1.0 - cos_d).powi(5) * (Vec3::new(1.0, 1.0, 1.0) - self.albedo)
+    }
+}
+
+impl Material for AnisotropicMetal {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let mut rng = sampler::rng();
+        let frame = self.frame(hit_record);
+        let wo = to_local(&frame, &-ray_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let h = self.distribution.sample_visible_normal(
+            &wo,
+            rng.gen_range(0.0, 1.0),
+            rng.gen_range(0.0, 1.0),
+        );
+        let wi = 2.0 * wo.dot(&h) * h - wo;
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let direction = to_world(&frame, &wi);
+        if direction.dot(&hit_record.geometric_normal) <= 0.0 {
+            return None;
+        }
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        Some((
+            weight * self.fresnel(&wo, &h),
+            Ray::with_time(hit_record.point, direction, ray_in.time()),
+        ))
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+
+    /// 可见法向抽样的概率密度为G1(wo)D(h)/(4cosθo)
+    fn bsdf(&self, hit_record: &HitRecord, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, Float)> {
+        let frame = self.frame(hit_record);
+        let wo = to_local(&frame, wo);
+        let wi = to_local(&frame, wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Some((Vec3::zero(), 0.0));
+        }
+        let h = (wo + wi).unit_vector();
+        let d = self.distribution.d(&h);
+        let f = d * self.distribution.g(&wo, &wi) / (4.0 * wo.z() * wi.z());
+        let pdf = self.distribution.g1(&wo) * d / (4.0 * wo.z());
+        Some((f * self.fresnel(&wo, &h), pdf))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ggx_normalized_and_visible_normals() {
+        let ggx = Ggx::new(0.3, 0.8);
+        // 微表面投影到宏观表面上的面积之和为1：∫D(h)cosθ dω = 1，在半球面上均匀抽样估计
+        let mut rng = sampler::rng();
+        let n = 400_000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let z: Float = rng.gen_range(0.0, 1.0);
+            let phi: Float = rng.gen_range(0.0, 2.0 * PI);
+            let r = (1.0 - z * z).sqrt();
+            let h = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            sum += ggx.d(&h) * z * 2.0 * PI;
+        }
+        let integral = sum / n as Float;
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+
+        // 抽样的法向朝向观察方向，并且沿粗糙度大的y方向散开得更多
+        let wo = Vec3::new(0.3, -0.2, 0.9).unit_vector();
+        let (mut spread_x, mut spread_y) = (0.0, 0.0);
+        for _ in 0..10_000 {
+            let h =
+                ggx.sample_visible_normal(&wo, rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0));
+            assert!(h.z() > 0.0 && wo.dot(&h) >= -1e-5);
+            assert!((h.length() - 1.0).abs() < 1e-4);
+            spread_x += h.x().abs();
+            spread_y += h.y().abs();
+        }
+        assert!(spread_y > 2.0 * spread_x);
+    }
+}
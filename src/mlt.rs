@@ -0,0 +1,232 @@
+//! 主样本空间的Metropolis光传输（Kelemen等人2002）
+//!
+//! 路径追踪用到的所有随机数组成主样本空间里的一个点，前两个分量决定胶片上的位置。
+//! 每条马尔可夫链不断变异这个点，按亮度的比值决定是否接受，把变异前后的路径按接受概率
+//! 分摊到胶片上，于是亮的路径附近会被反复探索，锁孔里透进来的光和玻璃罩里的灯也能找到。
+//! 链只知道相对亮度，整幅图的亮度由开始时的一批独立样本估计
+
+use crate::aov::{AovBuffers, AovSample};
+use crate::camera::Camera;
+use crate::render::{camera_ray, ray_color, RenderSettings};
+use crate::sampler::{self, PrimarySample};
+use crate::scene::Scene;
+use crate::vec3::{Float, Vec3};
+use rand::Rng;
+
+/// 变异时用来比较路径亮度的标量
+fn luminance(c: &Vec3) -> Float {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+/// 胶片上的一个位置和带回的光
+struct PathSample {
+    pixel: usize,
+    radiance: Vec3,
+}
+
+/// Metropolis光传输，`max_depth`为路径最多的反射次数
+pub struct Metropolis<'a> {
+    scene: &'a Scene,
+    max_depth: i32,
+    large_step_probability: Float,
+    sigma: Float,
+    bootstrap_samples: usize,
+    chains: usize,
+}
+
+impl<'a> Metropolis<'a> {
+    pub fn new(scene: &'a Scene, max_depth: i32) -> Self {
+        Metropolis {
+            scene,
+            max_depth,
+            large_step_probability: 0.3,
+            sigma: 0.01,
+            bootstrap_samples: 100_000,
+            chains: 1000,
+        }
+    }
+
+    /// 每次变异是大步变异（重新生成整条路径）的概率，默认0.3
+    pub fn with_large_step_probability(mut self, probability: Float) -> Self {
+        self.large_step_probability = probability;
+        self
+    }
+
+    /// 小步变异的标准差，默认0.01
+    pub fn with_sigma(mut self, sigma: Float) -> Self {
+        self.sigma = sigma;
+        self
+    }
+
+    /// 估计整幅图亮度用的独立样本数，默认100000
+    pub fn with_bootstrap_samples(mut self, samples: usize) -> Self {
+        self.bootstrap_samples = samples;
+        self
+    }
+
+    /// 马尔可夫链的条数，默认1000
+    pub fn with_chains(mut self, chains: usize) -> Self {
+        self.chains = chains;
+        self
+    }
+
+    /// 渲染整幅图像，变异的总数为像素数乘以每个像素的采样数
+    ///
+    /// 渲染通道另外用每个像素一条路径追踪的射线生成
+    pub fn render(
+        &self,
+        camera: &dyn Camera,
+        settings: &RenderSettings,
+    ) -> (Vec<Vec3>, AovBuffers) {
+        let (width, height) = (settings.width as usize, settings.height as usize);
+        let mut aov_buffers = AovBuffers::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut aov = AovSample::miss(Vec3::zero());
+                if let Some(ray) = camera_ray(camera, settings, x as u32, y as u32) {
+                    ray_color(&ray, self.scene, self.max_depth, Some(&mut aov));
+                }
+                aov_buffers.add(x, y, &aov);
+            }
+        }
+
+        // 用独立的样本估计整幅图的平均亮度，同时作为各条链起点的分布
+        let weights: Vec<Float> = (0..self.bootstrap_samples)
+            .map(|seed| {
+                let (_, path) = self.evaluate(camera, settings, self.primary_sample(seed));
+                luminance(&path.radiance).max(0.0)
+            })
+            .collect();
+        let total: Float = weights.iter().sum();
+        let mut film = vec![Vec3::zero(); width * height];
+        if total <= 0.0 {
+            return (film, aov_buffers);
+        }
+        let brightness = total / self.bootstrap_samples as Float;
+
+        let mutations = settings.samples_per_pixel as usize * width * height;
+        let chains = self.chains.clamp(1, mutations.max(1));
+        let mutations_per_chain = mutations.div_ceil(chains);
+        let mut rng = rand::thread_rng();
+        for _ in 0..chains {
+            // 按亮度选一个独立样本作为链的起点，同样的种子重新生成同样的路径
+            let mut target = rng.gen_range(0.0, total);
+            let seed = weights
+                .iter()
+                .position(|&w| {
+                    target -= w;
+                    target < 0.0
+                })
+                .unwrap_or_else(|| weights.iter().rposition(|&w| w > 0.0).unwrap());
+            let (mut sample, mut current) =
+                self.evaluate(camera, settings, self.primary_sample(seed));
+            for _ in 0..mutations_per_chain {
+                sample.start_iteration();
+                let (proposed_sample, proposed) = self.evaluate(camera, settings, sample);
+                sample = proposed_sample;
+                let (l_current, l_proposed) = (
+                    luminance(&current.radiance).max(0.0),
+                    luminance(&proposed.radiance).max(0.0),
+                );
+                let accept = if l_current > 0.0 {
+                    (l_proposed / l_current).min(1.0)
+                } else {
+                    1.0
+                };
+                // 变异前后的路径都按接受的概率记到胶片上，方差比只记录链的当前状态小
+                if l_proposed > 0.0 {
+                    film[proposed.pixel] += proposed.radiance * (accept / l_proposed);
+                }
+                if l_current > 0.0 {
+                    film[current.pixel] += current.radiance * ((1.0 - accept) / l_current);
+                }
+                if rng.gen_range(0.0, 1.0) < accept {
+                    sample.accept();
+                    current = proposed;
+                } else {
+                    sample.reject();
+                }
+            }
+        }
+
+        let scale =
+            brightness * (width * height) as Float / (chains * mutations_per_chain) as Float;
+        let pixels = film.iter().map(|&pixel| pixel * scale).collect();
+        (pixels, aov_buffers)
+    }
+
+    fn primary_sample(&self, seed: usize) -> PrimarySample {
+        PrimarySample::new(seed as u64, self.large_step_probability, self.sigma)
+    }
+
+    /// 用主样本生成一条路径，前两个分量为胶片上的位置
+    fn evaluate(
+        &self,
+        camera: &dyn Camera,
+        settings: &RenderSettings,
+        sample: PrimarySample,
+    ) -> (PrimarySample, PathSample) {
+        let (width, height) = (settings.width, settings.height);
+        let (open, close) = settings.shutter;
+        sampler::with_primary_sample(sample, || {
+            let mut rng = sampler::rng();
+            let x = rng.gen_range(0.0, width as Float);
+            let y = rng.gen_range(0.0, height as Float);
+            let pixel = (y as usize).min(height as usize - 1) * width as usize
+                + (x as usize).min(width as usize - 1);
+            let u = x / (width - 1) as Float;
+            let v = 1.0 - y / (height - 1) as Float;
+            let time = open + (close - open) * rng.gen_range(0.0, 1.0);
+            let radiance = match camera.get_ray_at(u, v, time) {
+                Some(ray) => ray_color(&ray, self.scene, self.max_depth, None),
+                None => Vec3::zero(),
+            };
+            PathSample { pixel, radiance }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::PerspectiveCamera;
+    use crate::scene::fixtures::{assert_close, lit_glass_sphere};
+
+    #[test]
+    fn test_matches_path_tracing() {
+        // 每个像素的期望都应该和路径追踪相同
+        let scene = lit_glass_sphere().build();
+        let lookfrom = Vec3::new(0.0, 1.0, 4.0);
+        let lookat = Vec3::new(0.3, 0.0, 0.9);
+        let camera = PerspectiveCamera::new(
+            &lookfrom,
+            &lookat,
+            &Vec3::new(0.0, 1.0, 0.0),
+            20.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let settings = RenderSettings {
+            width: 2,
+            height: 2,
+            samples_per_pixel: 20_000,
+            max_depth: 10,
+            ..RenderSettings::default()
+        };
+        let (pixels, _) = Metropolis::new(&scene, 10)
+            .with_bootstrap_samples(10_000)
+            .with_chains(100)
+            .render(&camera, &settings);
+
+        let n = 20_000;
+        for (i, actual) in pixels.iter().enumerate() {
+            let mut expected = Vec3::zero();
+            for _ in 0..n {
+                let ray = camera_ray(&camera, &settings, i as u32 % 2, i as u32 / 2).unwrap();
+                expected += ray_color(&ray, &scene, 10, None);
+            }
+            assert_close(expected / n as Float, *actual, 0.15);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! 法线贴图和凹凸贴图：只扰动着色法向，不改变几何形状
+//!
+//! 两者都包在任意物体外面，需要物体提供纹理坐标和切线方向（`dpdu`、`dpdv`），没有时不起作用。
+//! 扰动后的法向可能背对视线，或者让散射方向穿到几何表面的另一侧而漏光，
+//! 所以着色法向会被拉回到朝向视线的一侧，材质再把低于几何法向的散射方向镜像回来或者丢弃
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::{Float, Vec3};
+use std::rc::Rc;
+
+/// 切线空间法线贴图的颜色转换成世界空间的法向，`scale`缩放xy分量
+///
+/// 切线方向未知或者扰动后的法向翻到了表面背后时返回原来的法向
+pub fn tangent_space_normal(hit_record: &HitRecord, color: Vec3, scale: Float) -> Vec3 {
+    let n = hit_record.normal;
+    let tangent = hit_record.dpdu - hit_record.dpdu.dot(&n) * n;
+    if tangent.squared_length() < 1e-12 {
+        return n;
+    }
+    let tangent = tangent.unit_vector();
+    let bitangent = hit_record.dpdv - hit_record.dpdv.dot(&n) * n;
+    let bitangent = if bitangent.squared_length() < 1e-12 {
+        n.cross(&tangent)
+    } else {
+        bitangent.unit_vector()
+    };
+    let x = (2.0 * color.x() - 1.0) * scale;
+    let y = (2.0 * color.y() - 1.0) * scale;
+    let z = 2.0 * color.z() - 1.0;
+    let perturbed = x * tangent + y * bitangent + z * n;
+    if perturbed.dot(&n) > 0.0 {
+        perturbed.unit_vector()
+    } else {
+        n
+    }
+}
+
+/// 把着色法向拉回到朝向视线的一侧，`direction`为看向表面的射线方向
+///
+/// 背对视线的着色法向会让反射方向穿进物体，表现为黑斑或漏光
+pub fn face_viewer(normal: Vec3, direction: &Vec3) -> Vec3 {
+    const MIN_COSINE: Float = 0.01;
+    let outgoing = -direction.unit_vector();
+    let cosine = normal.dot(&outgoing);
+    if cosine >= MIN_COSINE {
+        normal
+    } else {
+        (normal + (MIN_COSINE - cosine) * outgoing).unit_vector()
+    }
+}
+
+/// 用切线空间的法线贴图扰动物体的着色法向
+pub struct NormalMap {
+    object: Box<dyn Hittable>,
+    texture: Rc<dyn Texture>,
+    scale: Float,
+}
+
+impl NormalMap {
+    pub fn new(object: Box<dyn Hittable>, texture: Rc<dyn Texture>) -> Self {
+        NormalMap {
+            object,
+            texture,
+            scale: 1.0,
+        }
+    }
+
+    /// 缩放法线的xy分量，调整凹凸的强度
+    pub fn with_scale(mut self, scale: Float) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+impl Hittable for NormalMap {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let mut hit_record = self.object.hit(ray, t_min, t_max)?;
+        let color = self
+            .texture
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        let normal = tangent_space_normal(&hit_record, color, self.scale);
+        hit_record.normal = face_viewer(normal, ray.direction());
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+}
+
+/// 用灰度图作为高度的凹凸贴图，高度沿法向偏移`scale`倍的纹理亮度
+///
+/// 高度的导数用纹理坐标上的有限差分估计
+pub struct BumpMap {
+    object: Box<dyn Hittable>,
+    texture: Rc<dyn Texture>,
+    scale: Float,
+}
+
+impl BumpMap {
+    pub fn new(object: Box<dyn Hittable>, texture: Rc<dyn Texture>, scale: Float) -> Self {
+        BumpMap {
+            object,
+            texture,
+            scale,
+        }
+    }
+
+    fn height(&self, u: Float, v: Float, point: &Vec3) -> Float {
+        let c = self.texture.value(u, v, point);
+        (c.x() + c.y() + c.z()) / 3.0 * self.scale
+    }
+}
+
+impl Hittable for BumpMap {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        const DELTA: Float = 1.0 / 2048.0;
+        let mut hit_record = self.object.hit(ray, t_min, t_max)?;
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.point);
+        let (dpdu, dpdv, n) = (hit_record.dpdu, hit_record.dpdv, hit_record.normal);
+        if dpdu.cross(&dpdv).squared_length() < 1e-12 {
+            return Some(hit_record);
+        }
+        let h = self.height(u, v, &p);
+        let dhdu = (self.height(u + DELTA, v, &(p + DELTA * dpdu)) - h) / DELTA;
+        let dhdv = (self.height(u, v + DELTA, &(p + DELTA * dpdv)) - h) / DELTA;
+        // 表面沿法向偏移h后的偏导数，忽略法向自身的变化
+        let normal = (dpdu + dhdu * n).cross(&(dpdv + dhdv * n));
+        let normal = if normal.dot(&n) < 0.0 {
+            -normal.unit_vector()
+        } else {
+            normal.unit_vector()
+        };
+        hit_record.normal = face_viewer(normal, ray.direction());
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::primitives::Plane;
+
+    /// 高度沿u方向线性增加的纹理，斜率为1
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: Float, _v: Float, _point: &Vec3) -> Vec3 {
+            Vec3::new(u, u, u)
+        }
+    }
+
+    fn plane() -> Box<dyn Hittable> {
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        Box::new(Plane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), material))
+    }
+
+    #[test]
+    fn test_bump_and_normal_map() {
+        let ray = Ray::new(Vec3::new(0.3, 1.0, 0.2), Vec3::new(0.0, -1.0, 0.0));
+        let flat = plane().hit(&ray, 0.001, Float::MAX).unwrap();
+        let slope = 0.5 / flat.dpdu.length();
+
+        let bumped = BumpMap::new(plane(), Rc::new(Ramp), 0.5);
+        let hit = bumped.hit(&ray, 0.001, Float::MAX).unwrap();
+        // 高度随u增加，法向向-dpdu方向倾斜，几何法向不变
+        let expected = (flat.normal - slope * flat.dpdu.unit_vector()).unit_vector();
+        assert!((hit.normal - expected).length() < 1e-3);
+        assert_eq!(hit.geometric_normal, flat.geometric_normal);
+        assert_eq!(hit.t, flat.t);
+
+        // (0.5, 0.5, 1)是不扰动的法线
+        let color = Rc::new(crate::texture::SolidColor::new(Vec3::new(0.5, 0.5, 1.0)));
+        let mapped = NormalMap::new(plane(), color);
+        let hit = mapped.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.normal - flat.normal).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_face_viewer() {
+        // 着色法向背对视线时被拉回到切平面附近
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let normal = face_viewer(Vec3::new(0.0, 0.6, -0.8), &direction);
+        assert!(normal.dot(&-direction) > 0.0);
+        assert!((normal.length() - 1.0).abs() < 1e-5);
+        let normal = Vec3::new(0.0, 0.6, 0.8);
+        assert_eq!(face_viewer(normal, &direction), normal);
+    }
+}
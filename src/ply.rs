@@ -0,0 +1,461 @@
+//! 读取PLY网格（ASCII和二进制），支持顶点的法向、纹理坐标和颜色，多边形面按扇形拆成三角形
+
+use crate::mesh::Mesh;
+use crate::texture::srgb_to_linear;
+use crate::vec3::{Float, Vec3};
+use std::io;
+use std::path::Path;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid(format!("unknown PLY type {}", name))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// 整数类型的颜色要归一化到`[0, 1]`
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 1.0 / 255.0,
+            Scalar::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Property {
+    Scalar(String, Scalar),
+    /// 名字、个数的类型、元素的类型
+    List(String, Scalar, Scalar),
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// 顶点属性写入的位置
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Slot {
+    Position(usize),
+    Normal(usize),
+    Uv(usize),
+    Color(usize),
+    Ignored,
+}
+
+fn vertex_slot(name: &str) -> Slot {
+    match name {
+        "x" => Slot::Position(0),
+        "y" => Slot::Position(1),
+        "z" => Slot::Position(2),
+        "nx" => Slot::Normal(0),
+        "ny" => Slot::Normal(1),
+        "nz" => Slot::Normal(2),
+        "u" | "s" | "texture_u" | "texture_s" => Slot::Uv(0),
+        "v" | "t" | "texture_v" | "texture_t" => Slot::Uv(1),
+        "red" | "r" | "diffuse_red" => Slot::Color(0),
+        "green" | "g" | "diffuse_green" => Slot::Color(1),
+        "blue" | "b" | "diffuse_blue" => Slot::Color(2),
+        _ => Slot::Ignored,
+    }
+}
+
+/// 数据部分的读取，ASCII按空白分隔，二进制按类型的长度读取
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| invalid("unexpected end of PLY data".to_string()))?;
+                token
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid PLY value {}", token)))
+            }
+            Body::Binary { bytes, big_endian } => {
+                let size = scalar.size();
+                if bytes.len() < size {
+                    return Err(invalid("unexpected end of PLY data".to_string()));
+                }
+                let (head, rest) = bytes.split_at(size);
+                *bytes = rest;
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(head);
+                if *big_endian {
+                    b[..size].reverse();
+                }
+                Ok(match scalar {
+                    Scalar::I8 => b[0] as i8 as f64,
+                    Scalar::U8 => b[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+
+    /// 读取列表的长度或者顶点的下标，必须是非负整数
+    fn read_index(&mut self, scalar: Scalar) -> io::Result<usize> {
+        let value = self.read(scalar)?;
+        if value < 0.0 || value.fract() != 0.0 || value > u32::MAX as f64 {
+            return Err(invalid(format!("invalid PLY index {}", value)));
+        }
+        Ok(value as usize)
+    }
+}
+
+/// 解析文件头，返回格式、元素和数据部分的起始位置
+fn parse_header(bytes: &[u8]) -> io::Result<(Format, Vec<Element>, usize)> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| invalid("missing PLY end_header".to_string()))?;
+    let body_start = bytes[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |i| end + i + 1);
+    let header = std::str::from_utf8(&bytes[..end])
+        .map_err(|_| invalid("PLY header is not ASCII".to_string()))?;
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(invalid("not a PLY file".to_string()));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", ..] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", ..] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", ..] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid element count {}", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let property = Property::List(
+                    name.to_string(),
+                    Scalar::parse(count)?,
+                    Scalar::parse(item)?,
+                );
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("property before element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["property", kind, name] => {
+                let property = Property::Scalar(name.to_string(), Scalar::parse(kind)?);
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("property before element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| invalid("missing PLY format".to_string()))?;
+    Ok((format, elements, body_start))
+}
+
+/// 从内存中解析PLY文件
+pub fn parse(bytes: &[u8]) -> io::Result<Mesh> {
+    let (format, elements, body_start) = parse_header(bytes)?;
+    let data = &bytes[body_start..];
+    let mut body = match format {
+        Format::Ascii => Body::Ascii(
+            std::str::from_utf8(data)
+                .map_err(|_| invalid("PLY data is not ASCII".to_string()))?
+                .split_ascii_whitespace(),
+        ),
+        Format::BinaryLittleEndian => Body::Binary {
+            bytes: data,
+            big_endian: false,
+        },
+        Format::BinaryBigEndian => Body::Binary {
+            bytes: data,
+            big_endian: true,
+        },
+    };
+
+    let mut mesh = Mesh::default();
+    let mut polygon = Vec::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                mesh.positions.reserve(reservation(element, data));
+                read_vertices(&mut body, element, &mut mesh)?
+            }
+            "face" => {
+                mesh.indices.reserve(reservation(element, data));
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match property {
+                            Property::List(name, count, item)
+                                if name == "vertex_indices" || name == "vertex_index" =>
+                            {
+                                let n = body.read_index(*count)?;
+                                polygon.clear();
+                                for _ in 0..n {
+                                    polygon.push(body.read_index(*item)?);
+                                }
+                                for i in 2..polygon.len() {
+                                    mesh.indices.push([polygon[0], polygon[i - 1], polygon[i]]);
+                                }
+                            }
+                            _ => skip(&mut body, property)?,
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        skip(&mut body, property)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let vertex_count = mesh.positions.len();
+    if let Some(index) = mesh.indices.iter().flatten().find(|&&i| i >= vertex_count) {
+        return Err(invalid(format!(
+            "PLY face refers to missing vertex {}",
+            index
+        )));
+    }
+    Ok(mesh)
+}
+
+/// 预先分配的元素个数。头部的个数不可信，每个元素至少占一个字节，所以不会超过数据的长度
+fn reservation(element: &Element, data: &[u8]) -> usize {
+    element.count.min(data.len())
+}
+
+fn skip(body: &mut Body, property: &Property) -> io::Result<()> {
+    match property {
+        Property::Scalar(_, scalar) => {
+            body.read(*scalar)?;
+        }
+        Property::List(_, count, item) => {
+            for _ in 0..body.read_index(*count)? {
+                body.read(*item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_vertices(body: &mut Body, element: &Element, mesh: &mut Mesh) -> io::Result<()> {
+    let slots: Vec<Slot> = element
+        .properties
+        .iter()
+        .map(|property| match property {
+            Property::Scalar(name, _) => vertex_slot(name),
+            Property::List(..) => Slot::Ignored,
+        })
+        .collect();
+    let has = |f: fn(&Slot) -> bool| slots.iter().any(f);
+    let has_normals = has(|s| matches!(s, Slot::Normal(_)));
+    let has_uvs = has(|s| matches!(s, Slot::Uv(_)));
+    let has_colors = has(|s| matches!(s, Slot::Color(_)));
+
+    for _ in 0..element.count {
+        let mut position = [0.0; 3];
+        let mut normal = [0.0; 3];
+        let mut uv = [0.0; 2];
+        let mut color = [1.0; 3];
+        for (property, slot) in element.properties.iter().zip(&slots) {
+            let scalar = match property {
+                Property::Scalar(_, scalar) => *scalar,
+                Property::List(..) => {
+                    skip(body, property)?;
+                    continue;
+                }
+            };
+            let value = body.read(scalar)?;
+            match *slot {
+                Slot::Position(i) => position[i] = value as Float,
+                Slot::Normal(i) => normal[i] = value as Float,
+                Slot::Uv(i) => uv[i] = value as Float,
+                Slot::Color(i) => color[i] = (value * scalar.color_scale()) as Float,
+                Slot::Ignored => {}
+            }
+        }
+        mesh.positions
+            .push(Vec3::new(position[0], position[1], position[2]));
+        if has_normals {
+            mesh.normals
+                .push(Vec3::new(normal[0], normal[1], normal[2]));
+        }
+        if has_uvs {
+            // PLY的v轴向上，纹理坐标的v轴向下
+            mesh.uvs.push([uv[0], 1.0 - uv[1]]);
+        }
+        if has_colors {
+            mesh.colors.push(Vec3::new(
+                srgb_to_linear(color[0]),
+                srgb_to_linear(color[1]),
+                srgb_to_linear(color[2]),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 读取PLY文件
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Mesh> {
+    parse(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ascii_quad_with_colors() {
+        let ply = b"ply
+format ascii 1.0
+comment a unit quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 0 0 255
+0 1 0 0 0 255
+4 0 1 2 3
+";
+        let mesh = parse(ply).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors[0], Vec3::new(1.0, 0.0, 0.0));
+        assert!(mesh.normals.is_empty() && mesh.uvs.is_empty());
+    }
+
+    #[test]
+    fn test_binary_matches_ascii() {
+        let mut ply = b"ply
+format binary_big_endian 1.0
+element vertex 3
+property double x
+property double y
+property double z
+property float confidence
+element face 1
+property uchar intensity
+property list uchar uint vertex_indices
+end_header
+"
+        .to_vec();
+        for p in &[[0.0f64, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 3.0, 1.0]] {
+            for x in p {
+                ply.extend_from_slice(&x.to_be_bytes());
+            }
+            ply.extend_from_slice(&0.5f32.to_be_bytes());
+        }
+        ply.push(7);
+        ply.push(3);
+        for i in &[0u32, 1, 2] {
+            ply.extend_from_slice(&i.to_be_bytes());
+        }
+        let mesh = parse(&ply).unwrap();
+        assert_eq!(mesh.positions[2], Vec3::new(0.0, 3.0, 1.0));
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+
+        // 少了一个字节
+        assert!(parse(&ply[..ply.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_indices_and_counts() {
+        let header = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+1 0 0
+0 1 0
+";
+        for face in &["3 0 1 2", "3 0 -1 2", "3 0 1.5 2", "3 0 1 3", "-3 0 1 2"] {
+            let result = parse(format!("{}{}\n", header, face).as_bytes());
+            assert_eq!(result.is_ok(), *face == "3 0 1 2", "{}", face);
+        }
+
+        // 头部声明了巨大的个数，但数据很短
+        let huge = header.replace("element face 1", "element face 1000000000000000000");
+        assert!(parse(format!("{}3 0 1 2\n", huge).as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+//! 检查场景用的预览积分器，几秒钟就能看出物体的位置、朝向和材质是否正确
+//!
+//! 每个函数追踪一条摄像机射线，和`render::ray_color`一样把第一次碰撞记进渲染通道
+
+use crate::aov::{material_key, AovSample};
+use crate::hittable::HitRecord;
+use crate::light;
+use crate::material::random_unit_vector;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::vec3::{Float, Vec3};
+use std::rc::Rc;
+
+/// 热度图满刻度对应的检测次数
+const MAX_COST: Float = 200.0;
+
+/// 线框的宽度，为到最近一条边的重心坐标
+const EDGE_WIDTH: Float = 0.03;
+
+/// 求第一次碰撞，把它记进`aov`，颜色通道记为`color`算出的结果
+fn first_hit<F: FnOnce(Option<&HitRecord>) -> Vec3>(
+    ray: &Ray,
+    scene: &Scene,
+    aov: &mut AovSample,
+    color: F,
+) -> Vec3 {
+    match scene.hit(ray, 0.001, Float::MAX) {
+        Some(hit_record) => {
+            let c = color(Some(&hit_record));
+            *aov = AovSample {
+                albedo: hit_record.material.albedo(&hit_record),
+                normal: hit_record.normal,
+                depth: hit_record.t * ray.direction().length(),
+                position: hit_record.point,
+                material_key: material_key(&hit_record.material),
+                object_id: hit_record.object_id,
+                direct: c,
+                indirect: Vec3::zero(),
+            };
+            c
+        }
+        None => {
+            let c = color(None);
+            *aov = AovSample::miss(c);
+            c
+        }
+    }
+}
+
+/// 着色法向映射到`[0, 1]`的颜色，没有击中物体时为黑色
+pub fn normal(ray: &Ray, scene: &Scene, aov: &mut AovSample) -> Vec3 {
+    first_hit(ray, scene, aov, |hit| {
+        hit.map_or(Vec3::zero(), |h| {
+            0.5 * (h.normal + Vec3::new(1.0, 1.0, 1.0))
+        })
+    })
+}
+
+/// 到摄像机的距离，近处白远处黑，场景包围球的远端为黑色
+pub fn depth(ray: &Ray, scene: &Scene, aov: &mut AovSample) -> Vec3 {
+    let (center, radius) = scene.bounding_sphere();
+    let far = (*ray.origin() - center).length() + radius;
+    first_hit(ray, scene, aov, |hit| {
+        hit.map_or(Vec3::zero(), |h| {
+            let d = h.t * ray.direction().length();
+            let gray = (1.0 - d / far).clamp(0.0, 1.0);
+            Vec3::new(gray, gray, gray)
+        })
+    })
+}
+
+/// 材质的反照率，没有击中物体时为背景
+pub fn albedo(ray: &Ray, scene: &Scene, aov: &mut AovSample) -> Vec3 {
+    first_hit(ray, scene, aov, |hit| {
+        hit.map_or_else(|| scene.background(ray), |h| h.material.albedo(h))
+    })
+}
+
+/// 求交时检测过的包围盒和物体个数的热度图，从蓝色经绿色到红色
+pub fn traversal_cost(ray: &Ray, scene: &Scene, aov: &mut AovSample) -> Vec3 {
+    let cost = scene.traversal_cost(ray);
+    first_hit(ray, scene, aov, |_| heat(cost as Float / MAX_COST))
+}
+
+/// 线框：三角形靠近边的地方画成白线，其余的面和不是三角形的物体按朝向摄像机的程度着灰色
+pub fn wireframe(ray: &Ray, scene: &Scene, aov: &mut AovSample) -> Vec3 {
+    first_hit(ray, scene, aov, |hit| {
+        hit.map_or(Vec3::zero(), |h| {
+            let edge = h
+                .barycentric
+                .map_or(Float::MAX, |b| b.x().min(b.y()).min(b.z()));
+            if edge < EDGE_WIDTH {
+                Vec3::new(1.0, 1.0, 1.0)
+            } else {
+                let facing = h.normal.dot(&ray.direction().unit_vector()).abs();
+                let gray = 0.1 + 0.3 * facing;
+                Vec3::new(gray, gray, gray)
+            }
+        })
+    })
+}
+
+/// `t`从0到1对应蓝、绿、红
+fn heat(t: Float) -> Vec3 {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        let s = 2.0 * t;
+        Vec3::new(0.0, s, 1.0 - s)
+    } else {
+        let s = 2.0 * t - 1.0;
+        Vec3::new(s, 1.0 - s, 0.0)
+    }
+}
+
+/// 环境光遮蔽：从第一次碰撞处按余弦分布射出一条射线，`radius`以内没有物体时为白色
+pub fn ambient_occlusion(ray: &Ray, scene: &Scene, radius: Float, aov: &mut AovSample) -> Vec3 {
+    first_hit(ray, scene, aov, |hit| match hit {
+        Some(h) => {
+            let mut direction = h.normal + random_unit_vector();
+            if direction.squared_length() < 1e-8 {
+                direction = h.normal;
+            }
+            let probe = Ray::with_time(h.point, direction.unit_vector(), ray.time());
+            if scene.hit(&probe, 0.001, radius).is_some() {
+                Vec3::zero()
+            } else {
+                Vec3::new(1.0, 1.0, 1.0)
+            }
+        }
+        None => Vec3::new(1.0, 1.0, 1.0),
+    })
+}
+
+/// 只有直接光照：路径穿过镜面，在第一个能用`Material::bsdf`求值的表面上对光源抽样一次；
+/// 只能用`scatter`抽样的表面再反射一次，取下一个交点的自发光或背景
+pub fn direct_lighting(ray: &Ray, scene: &Scene, depth: i32, aov: &mut AovSample) -> Vec3 {
+    first_hit(ray, scene, aov, |hit| {
+        hit.map_or_else(|| scene.background(ray), |_| Vec3::zero())
+    });
+    let mut ray = Ray::with_time(*ray.origin(), *ray.direction(), ray.time());
+    let mut beta = Vec3::new(1.0, 1.0, 1.0);
+    let mut radiance = Vec3::zero();
+    for _ in 0..=depth {
+        let hit_record = match scene.hit(&ray, 0.001, Float::MAX) {
+            Some(hit_record) => hit_record,
+            None => {
+                radiance += beta * scene.background(&ray);
+                break;
+            }
+        };
+        let material = Rc::clone(&hit_record.material);
+        radiance += beta * material.emitted(&ray, &hit_record);
+        let specular = material.is_specular(&hit_record);
+        let wo = -ray.direction().unit_vector();
+        if !specular && material.bsdf(&hit_record, &wo, &wo).is_some() {
+            radiance += beta * sample_light(scene, &hit_record, &wo, ray.time());
+            break;
+        }
+        let (attenuation, scattered) = match material.scatter(&ray, &hit_record) {
+            Some(scattered) => scattered,
+            None => break,
+        };
+        beta = beta * attenuation;
+        if !specular {
+            radiance += beta
+                * match scene.hit(&scattered, 0.001, Float::MAX) {
+                    Some(h) => h.material.emitted(&scattered, &h),
+                    None => scene.background(&scattered),
+                };
+            break;
+        }
+        ray = scattered;
+    }
+    aov.direct = radiance;
+    radiance
+}
+
+/// 对光源抽样一次估计`hit_record`处反射向`wo`的直接光照
+fn sample_light(scene: &Scene, hit_record: &HitRecord, wo: &Vec3, time: Float) -> Vec3 {
+    let sample = match light::sample_incident(scene, &hit_record.point) {
+        Some(sample) if sample.pdf > 0.0 => sample,
+        _ => return Vec3::zero(),
+    };
+    if !light::visible(scene, &hit_record.point, &sample, time) {
+        return Vec3::zero();
+    }
+    match hit_record.material.bsdf(hit_record, wo, &sample.direction) {
+        Some((f, _)) => {
+            let cos = hit_record.normal.dot(&sample.direction).abs();
+            f * sample.radiance * (cos / sample.pdf)
+        }
+        None => Vec3::zero(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::Sphere;
+    use crate::material::Lambertian;
+    use crate::mesh::{Mesh, TriangleMesh};
+    use crate::primitives::Plane;
+
+    fn gray() -> Rc<Lambertian> {
+        Rc::new(Lambertian::new(&Vec3::new(0.6, 0.6, 0.6)))
+    }
+
+    #[test]
+    fn test_open_floor() {
+        // 空旷的地面朝上，没有任何遮挡
+        let mut builder = Scene::builder();
+        builder.add(Box::new(Plane::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+            Rc::new(Lambertian::new(&Vec3::new(0.6, 0.6, 0.6))),
+        )));
+        let scene = builder.build();
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 1.0));
+        let mut aov = AovSample::miss(Vec3::zero());
+
+        let n = normal(&ray, &scene, &mut aov);
+        assert!((n - Vec3::new(0.5, 1.0, 0.5)).length() < 1e-4);
+        assert_eq!(aov.object_id, 1);
+        let a = albedo(&ray, &scene, &mut aov);
+        assert!((a - Vec3::new(0.6, 0.6, 0.6)).length() < 1e-4);
+        for _ in 0..100 {
+            assert_eq!(
+                ambient_occlusion(&ray, &scene, 10.0, &mut aov),
+                Vec3::new(1.0, 1.0, 1.0)
+            );
+        }
+        assert_eq!(scene.traversal_cost(&ray), 1);
+    }
+
+    #[test]
+    fn test_depth_and_occlusion() {
+        // 地面上方0.5处有一块朝下的天花板
+        let mut builder = Scene::builder();
+        builder.add(Box::new(Plane::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+            gray(),
+        )));
+        builder.add(Box::new(Plane::new(
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            gray(),
+        )));
+        builder.add(Box::new(Sphere::new(
+            Vec3::new(0.0, 0.2, -3.0),
+            0.2,
+            gray(),
+        )));
+        let scene = builder.build();
+        let ray = Ray::new(Vec3::new(0.0, 0.2, 0.0), Vec3::new(0.0, -0.2, -2.0));
+        let mut aov = AovSample::miss(Vec3::zero());
+
+        let d = depth(&ray, &scene, &mut aov);
+        let distance = ray.direction().length();
+        assert!((aov.depth - distance).abs() < 1e-4);
+        let (center, radius) = scene.bounding_sphere();
+        let far = (*ray.origin() - center).length() + radius;
+        assert!((d.x() - (1.0 - distance / far)).abs() < 1e-4);
+
+        // 向上的射线总会碰到天花板，但走过的距离不小于0.5
+        for _ in 0..100 {
+            assert_eq!(
+                ambient_occlusion(&ray, &scene, Float::MAX, &mut aov),
+                Vec3::zero()
+            );
+            assert_eq!(
+                ambient_occlusion(&ray, &scene, 0.4, &mut aov),
+                Vec3::new(1.0, 1.0, 1.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_traversal_cost_grows_with_objects() {
+        // 射线从一排球的包围盒的角上穿过，碰不到球，每个包围盒都要检测
+        let ray = Ray::new(Vec3::new(0.35, 0.35, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let costs: Vec<usize> = [1, 10, 100]
+            .iter()
+            .map(|&n| {
+                let mut builder = Scene::builder();
+                for i in 0..n {
+                    let center = Vec3::new(0.0, 0.0, -(i as Float));
+                    builder.add(Box::new(Sphere::new(center, 0.4, gray())));
+                }
+                let scene = builder.build();
+                let mut aov = AovSample::miss(Vec3::zero());
+                let cost = scene.traversal_cost(&ray);
+                let c = traversal_cost(&ray, &scene, &mut aov);
+                assert_eq!(c, heat(cost as Float / MAX_COST));
+                cost
+            })
+            .collect();
+        assert!(costs[0] < costs[1] && costs[1] < costs[2], "{:?}", costs);
+    }
+
+    #[test]
+    fn test_wireframe_edges() {
+        let mesh = Mesh {
+            positions: vec![
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            indices: vec![[0, 1, 2]],
+            ..Mesh::default()
+        };
+        let mut builder = Scene::builder();
+        builder.add(Box::new(TriangleMesh::new(mesh, gray())));
+        builder.add(Box::new(Sphere::new(Vec3::new(3.0, 0.0, 0.0), 0.5, gray())));
+        let scene = builder.build();
+        let mut aov = AovSample::miss(Vec3::zero());
+        let shoot = |x: Float, y: Float, aov: &mut AovSample| {
+            let ray = Ray::new(Vec3::new(x, y, 2.0), Vec3::new(0.0, 0.0, -1.0));
+            wireframe(&ray, &scene, aov)
+        };
+
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        // 底边附近
+        assert_eq!(shoot(0.0, -0.99, &mut aov), white);
+        // 三角形中心
+        let center = shoot(0.0, -1.0 / 3.0, &mut aov);
+        assert!((center - Vec3::new(0.4, 0.4, 0.4)).length() < 1e-4);
+        // 球没有边
+        let sphere = shoot(3.0, 0.0, &mut aov);
+        assert!((sphere - Vec3::new(0.4, 0.4, 0.4)).length() < 1e-4);
+        assert_eq!(shoot(0.0, 2.0, &mut aov), Vec3::zero());
+    }
+}
@@ -0,0 +1,727 @@
+//! 球以外的解析几何体：平面、圆盘、圆柱、圆锥和圆环
+//!
+//! 每种形状都在自己的局部坐标系里求交，局部坐标系以`y`轴为对称轴，
+//! 再用刚体变换摆到世界坐标中。纹理坐标的`u`都是绕对称轴的角度
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec3::{Float, Vec3};
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 把局部坐标系的`y`轴对准`axis`、原点移到`origin`的刚体变换
+fn frame(origin: Vec3, axis: Vec3) -> Transform {
+    let w = axis.unit_vector();
+    let helper = if w.x().abs() > 0.9 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t = w.cross(&helper).unit_vector();
+    let b = t.cross(&w);
+    let m = [
+        [t.x(), w.x(), b.x()],
+        [t.y(), w.y(), b.y()],
+        [t.z(), w.z(), b.z()],
+    ];
+    Transform::from_matrix(m, origin).expect("axis must not be zero")
+}
+
+/// 绕`y`轴的角度，范围`[0, 2π)`
+fn azimuth(p: &Vec3) -> Float {
+    let phi = p.z().atan2(p.x());
+    if phi < 0.0 {
+        phi + 2.0 * PI
+    } else {
+        phi
+    }
+}
+
+/// 绕`y`轴旋转一周时点的切向
+fn azimuth_tangent(p: &Vec3) -> Vec3 {
+    2.0 * PI * Vec3::new(-p.z(), 0.0, p.x())
+}
+
+/// 局部坐标系里的求交结果，由`Primitive`变换回世界坐标
+struct LocalHit {
+    t: Float,
+    point: Vec3,
+    normal: Vec3,
+    u: Float,
+    v: Float,
+    dpdu: Vec3,
+    dpdv: Vec3,
+}
+
+impl LocalHit {
+    fn into_record(self, material: &Rc<dyn Material>, ray: &Ray) -> HitRecord {
+        HitRecord {
+            u: self.u,
+            v: self.v,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
+            ..HitRecord::new(self.t, self.point, self.normal, Rc::clone(material), ray)
+        }
+    }
+}
+
+/// 在`y = height`的平面上求交，返回`t`和交点
+fn hit_y_plane(ray: &Ray, height: Float, t_min: Float, t_max: Float) -> Option<(Float, Vec3)> {
+    let dy = ray.direction().y();
+    if dy.abs() < 1e-12 {
+        return None;
+    }
+    let t = (height - ray.origin().y()) / dy;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    Some((t, ray.point_at_parameter(&t)))
+}
+
+/// 在`y = height`、半径为`[inner, outer]`的圆环上求交，法向为`normal_y`方向
+fn hit_disk(
+    ray: &Ray,
+    height: Float,
+    inner: Float,
+    outer: Float,
+    normal_y: Float,
+    t_min: Float,
+    t_max: Float,
+) -> Option<LocalHit> {
+    let (t, point) = hit_y_plane(ray, height, t_min, t_max)?;
+    let r = point.x().hypot(point.z());
+    if r > outer || r < inner {
+        return None;
+    }
+    let radial = if r > 0.0 {
+        Vec3::new(point.x(), 0.0, point.z()) / r
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    Some(LocalHit {
+        t,
+        point,
+        normal: Vec3::new(0.0, normal_y, 0.0),
+        u: azimuth(&point) / (2.0 * PI),
+        v: (outer - r) / (outer - inner),
+        dpdu: azimuth_tangent(&point),
+        dpdv: -(outer - inner) * radial,
+    })
+}
+
+/// 二次方程`a t² + 2b t + c = 0`在区间内最小的根
+fn nearest_root(
+    a: Float,
+    b: Float,
+    c: Float,
+    t_min: Float,
+    t_max: Float,
+    accept: impl Fn(Float) -> bool,
+) -> Option<Float> {
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 || a.abs() < 1e-12 {
+        return None;
+    }
+    let sqrt = discriminant.sqrt();
+    let (t0, t1) = ((-b - sqrt) / a, (-b + sqrt) / a);
+    let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+    [t0, t1]
+        .iter()
+        .copied()
+        .find(|&t| t > t_min && t < t_max && accept(t))
+}
+
+/// 局部坐标系中的形状
+trait Shape {
+    fn hit_local(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<LocalHit>;
+
+    /// 局部坐标系中的包围盒，无限大时返回`None`
+    fn local_bounds(&self) -> Option<Aabb>;
+
+    /// 按面积均匀抽样表面上的一点，返回射线从法向一侧击中该点的结果
+    fn sample_local(&self, _u: Float, _v: Float) -> Option<(LocalHit, Ray)> {
+        None
+    }
+
+    fn local_area(&self) -> Float {
+        0.0
+    }
+}
+
+/// 摆放在世界中的形状
+struct Primitive<S> {
+    shape: S,
+    transform: Transform,
+    material: Rc<dyn Material>,
+}
+
+impl<S: Shape> Primitive<S> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let local = self.transform.inverse_ray(ray);
+        self.shape.hit_local(&local, t_min, t_max).map(|h| {
+            self.transform
+                .hit_record(h.into_record(&self.material, &local))
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.shape
+            .local_bounds()
+            .map(|bbox| self.transform.bounding_box(&bbox))
+    }
+
+    /// 变换都是刚体变换，面积不变
+    fn sample_surface(&self, u: Float, v: Float) -> Option<HitRecord> {
+        let (h, ray) = self.shape.sample_local(u, v)?;
+        Some(
+            self.transform
+                .hit_record(h.into_record(&self.material, &ray)),
+        )
+    }
+}
+
+/// 为包装了`Primitive`的形状实现`Hittable`
+macro_rules! impl_hittable {
+    ($name:ident) => {
+        impl Hittable for $name {
+            fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+                self.0.hit(ray, t_min, t_max)
+            }
+
+            fn bounding_box(&self) -> Option<Aabb> {
+                self.0.bounding_box()
+            }
+
+            fn sample_surface(&self, u: Float, v: Float) -> Option<HitRecord> {
+                self.0.sample_surface(u, v)
+            }
+
+            fn area(&self) -> Float {
+                self.0.shape.local_area()
+            }
+        }
+    };
+}
+
+struct PlaneShape;
+
+impl Shape for PlaneShape {
+    /// 纹理坐标就是平面上的局部坐标，纹理会无限重复
+    fn hit_local(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<LocalHit> {
+        let (t, point) = hit_y_plane(ray, 0.0, t_min, t_max)?;
+        Some(LocalHit {
+            t,
+            point,
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            u: point.x(),
+            v: point.z(),
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 1.0),
+        })
+    }
+
+    fn local_bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// 无限大的平面
+pub struct Plane(Primitive<PlaneShape>);
+
+impl Plane {
+    /// 经过`point`、法向为`normal`的平面
+    pub fn new(point: Vec3, normal: Vec3, material: Rc<dyn Material>) -> Self {
+        Plane(Primitive {
+            shape: PlaneShape,
+            transform: frame(point, normal),
+            material,
+        })
+    }
+}
+
+impl_hittable!(Plane);
+
+struct DiskShape {
+    radius: Float,
+    inner_radius: Float,
+}
+
+impl Shape for DiskShape {
+    fn hit_local(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<LocalHit> {
+        hit_disk(ray, 0.0, self.inner_radius, self.radius, 1.0, t_min, t_max)
+    }
+
+    fn local_bounds(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::new(Vec3::new(-r, -1e-4, -r), Vec3::new(r, 1e-4, r)))
+    }
+
+    fn sample_local(&self, u: Float, v: Float) -> Option<(LocalHit, Ray)> {
+        let (inner2, outer2) = (self.inner_radius.powi(2), self.radius.powi(2));
+        let r = (inner2 + u * (outer2 - inner2)).sqrt();
+        let phi = 2.0 * PI * v;
+        let ray = Ray::new(
+            Vec3::new(r * phi.cos(), 1.0, r * phi.sin()),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        let h = hit_disk(&ray, 0.0, self.inner_radius, self.radius, 1.0, 0.0, 2.0)?;
+        Some((h, ray))
+    }
+
+    fn local_area(&self) -> Float {
+        PI * (self.radius.powi(2) - self.inner_radius.powi(2))
+    }
+}
+
+/// 圆盘，`v`从外圈的0变到内圈的1
+pub struct Disk(Primitive<DiskShape>);
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: Float, material: Rc<dyn Material>) -> Self {
+        Disk(Primitive {
+            shape: DiskShape {
+                radius,
+                inner_radius: 0.0,
+            },
+            transform: frame(center, normal),
+            material,
+        })
+    }
+
+    /// 挖去中间半径为`inner_radius`的部分，变成圆环，`inner_radius`必须小于外半径
+    pub fn with_inner_radius(mut self, inner_radius: Float) -> Self {
+        assert!(
+            inner_radius >= 0.0 && inner_radius < self.0.shape.radius,
+            "inner radius {} must be in [0, {})",
+            inner_radius,
+            self.0.shape.radius
+        );
+        self.0.shape.inner_radius = inner_radius;
+        self
+    }
+}
+
+impl_hittable!(Disk);
+
+struct CylinderShape {
+    radius: Float,
+    height: Float,
+    capped: bool,
+}
+
+impl Shape for CylinderShape {
+    fn hit_local(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<LocalHit> {
+        let (o, d) = (ray.origin(), ray.direction());
+        let a = d.x() * d.x() + d.z() * d.z();
+        let b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+        let side = nearest_root(a, b, c, t_min, t_max, |t| {
+            let y = o.y() + t * d.y();
+            y >= 0.0 && y <= self.height
+        })
+        .map(|t| {
+            let point = ray.point_at_parameter(&t);
+            LocalHit {
+                t,
+                point,
+                normal: Vec3::new(point.x(), 0.0, point.z()) / self.radius,
+                u: azimuth(&point) / (2.0 * PI),
+                v: point.y() / self.height,
+                dpdu: azimuth_tangent(&point),
+                dpdv: Vec3::new(0.0, self.height, 0.0),
+            }
+        });
+        if !self.capped {
+            return side;
+        }
+        let t_max = side.as_ref().map_or(t_max, |h| h.t);
+        let bottom = hit_disk(ray, 0.0, 0.0, self.radius, -1.0, t_min, t_max);
+        let t_max = bottom.as_ref().map_or(t_max, |h| h.t);
+        let top = hit_disk(ray, self.height, 0.0, self.radius, 1.0, t_min, t_max);
+        top.or(bottom).or(side)
+    }
+
+    fn local_bounds(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::new(
+            Vec3::new(-r, 0.0, -r),
+            Vec3::new(r, self.height, r),
+        ))
+    }
+}
+
+/// 圆柱，默认两端开口，`v`沿轴从底面的0变到顶面的1
+pub struct Cylinder(Primitive<CylinderShape>);
+
+impl Cylinder {
+    /// 底面圆心为`base`、顶面圆心为`top`的圆柱
+    pub fn new(base: Vec3, top: Vec3, radius: Float, material: Rc<dyn Material>) -> Self {
+        Cylinder(Primitive {
+            shape: CylinderShape {
+                radius,
+                height: (top - base).length(),
+                capped: false,
+            },
+            transform: frame(base, top - base),
+            material,
+        })
+    }
+
+    /// 用圆盘封住两端
+    pub fn capped(mut self) -> Self {
+        self.0.shape.capped = true;
+        self
+    }
+}
+
+impl_hittable!(Cylinder);
+
+struct ConeShape {
+    radius: Float,
+    height: Float,
+    capped: bool,
+}
+
+impl Shape for ConeShape {
+    /// 底面在`y = 0`，顶点在`y = height`：`x² + z² = (k(h - y))²`，`k = r / h`
+    fn hit_local(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<LocalHit> {
+        let (o, d) = (ray.origin(), ray.direction());
+        let (r, h) = (self.radius, self.height);
+        let k2 = (r / h) * (r / h);
+        let hy = h - o.y();
+        let a = d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y();
+        let b = o.x() * d.x() + o.z() * d.z() + k2 * hy * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k2 * hy * hy;
+        let side = nearest_root(a, b, c, t_min, t_max, |t| {
+            let y = o.y() + t * d.y();
+            y >= 0.0 && y <= h
+        })
+        .map(|t| {
+            let point = ray.point_at_parameter(&t);
+            let phi = azimuth(&point);
+            let v = point.y() / h;
+            LocalHit {
+                t,
+                point,
+                normal: Vec3::new(point.x(), k2 * (h - point.y()), point.z()).unit_vector(),
+                u: phi / (2.0 * PI),
+                v,
+                dpdu: azimuth_tangent(&point),
+                dpdv: Vec3::new(-r * phi.cos(), h, -r * phi.sin()),
+            }
+        });
+        if !self.capped {
+            return side;
+        }
+        let t_max = side.as_ref().map_or(t_max, |h| h.t);
+        hit_disk(ray, 0.0, 0.0, r, -1.0, t_min, t_max).or(side)
+    }
+
+    fn local_bounds(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::new(
+            Vec3::new(-r, 0.0, -r),
+            Vec3::new(r, self.height, r),
+        ))
+    }
+}
+
+/// 圆锥，默认底面开口，`v`从底面的0变到顶点的1
+pub struct Cone(Primitive<ConeShape>);
+
+impl Cone {
+    /// 底面圆心为`base`、半径为`radius`，顶点为`apex`的圆锥
+    pub fn new(base: Vec3, apex: Vec3, radius: Float, material: Rc<dyn Material>) -> Self {
+        Cone(Primitive {
+            shape: ConeShape {
+                radius,
+                height: (apex - base).length(),
+                capped: false,
+            },
+            transform: frame(base, apex - base),
+            material,
+        })
+    }
+
+    /// 用圆盘封住底面
+    pub fn capped(mut self) -> Self {
+        self.0.shape.capped = true;
+        self
+    }
+}
+
+impl_hittable!(Cone);
+
+struct TorusShape {
+    major_radius: Float,
+    minor_radius: Float,
+}
+
+impl Shape for TorusShape {
+    /// `(|p|² + R² - r²)² = 4R²(x² + z²)`，代入射线得到关于`t`的四次方程
+    ///
+    /// 为了减小数值误差，先把方向归一化，并把原点沿射线移到离圆环中心最近的地方
+    fn hit_local(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<LocalHit> {
+        let (big_r, small_r) = (self.major_radius as f64, self.minor_radius as f64);
+        let length = ray.direction().length() as f64;
+        let d = ray.direction().unit_vector();
+        let (dx, dy, dz) = (d.x() as f64, d.y() as f64, d.z() as f64);
+        let shift = -(ray.origin().dot(&d) as f64);
+        let o = ray.origin() + (shift as Float) * d;
+        let (ox, oy, oz) = (o.x() as f64, o.y() as f64, o.z() as f64);
+
+        let od = ox * dx + oy * dy + oz * dz;
+        let e = ox * ox + oy * oy + oz * oz - big_r * big_r - small_r * small_r;
+        let coefficients = [
+            e * e - 4.0 * big_r * big_r * (small_r * small_r - oy * oy),
+            4.0 * od * e + 8.0 * big_r * big_r * oy * dy,
+            2.0 * e + 4.0 * od * od + 4.0 * big_r * big_r * dy * dy,
+            4.0 * od,
+            1.0,
+        ];
+        let t = solve_quartic(&coefficients)
+            .into_iter()
+            .map(|s| ((shift + s) / length) as Float)
+            .filter(|&t| t > t_min && t < t_max)
+            .fold(None, |best: Option<Float>, t| {
+                Some(best.map_or(t, |b| b.min(t)))
+            })?;
+
+        let point = ray.point_at_parameter(&t);
+        let rho = point.x().hypot(point.z());
+        let radial = if rho > 0.0 {
+            Vec3::new(point.x(), 0.0, point.z()) / rho
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let tube_center = self.major_radius * radial;
+        let normal = (point - tube_center).unit_vector();
+        let theta = point.y().atan2(rho - self.major_radius);
+        let theta = if theta < 0.0 { theta + 2.0 * PI } else { theta };
+        Some(LocalHit {
+            t,
+            point,
+            normal,
+            u: azimuth(&point) / (2.0 * PI),
+            v: theta / (2.0 * PI),
+            dpdu: azimuth_tangent(&point),
+            dpdv: 2.0
+                * PI
+                * self.minor_radius
+                * (-theta.sin() * radial + Vec3::new(0.0, theta.cos(), 0.0)),
+        })
+    }
+
+    fn local_bounds(&self) -> Option<Aabb> {
+        let (big_r, small_r) = (self.major_radius, self.minor_radius);
+        let extent = big_r + small_r;
+        Some(Aabb::new(
+            Vec3::new(-extent, -small_r, -extent),
+            Vec3::new(extent, small_r, extent),
+        ))
+    }
+}
+
+/// 圆环，`u`绕对称轴，`v`绕管子的截面从外侧开始
+pub struct Torus(Primitive<TorusShape>);
+
+impl Torus {
+    /// `major_radius`为管子中心线的半径，`minor_radius`为管子的半径
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: Float,
+        minor_radius: Float,
+        material: Rc<dyn Material>,
+    ) -> Self {
+        Torus(Primitive {
+            shape: TorusShape {
+                major_radius,
+                minor_radius,
+            },
+            transform: frame(center, axis),
+            material,
+        })
+    }
+}
+
+impl_hittable!(Torus);
+
+/// `x³ + a x² + b x + c = 0`最大的实根
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    // 代换 x = z - a/3 得到 z³ + p z + q = 0
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    let z = if discriminant > 0.0 {
+        let sqrt = discriminant.sqrt();
+        (-q / 2.0 + sqrt).cbrt() + (-q / 2.0 - sqrt).cbrt()
+    } else if p < 0.0 {
+        let m = 2.0 * (-p / 3.0).sqrt();
+        let cos = (3.0 * q / (p * m)).clamp(-1.0, 1.0);
+        m * (cos.acos() / 3.0).cos()
+    } else {
+        0.0
+    };
+    let mut x = z - a / 3.0;
+    for _ in 0..2 {
+        let f = ((x + a) * x + b) * x + c;
+        let df = (3.0 * x + 2.0 * a) * x + b;
+        if df.abs() > 1e-12 {
+            x -= f / df;
+        }
+    }
+    x
+}
+
+/// 用Ferrari方法求四次方程的实根，`coefficients`从常数项开始排列，最后再用牛顿法修正
+pub fn solve_quartic(coefficients: &[f64; 5]) -> Vec<f64> {
+    let [c0, c1, c2, c3, c4] = *coefficients;
+    let (a, b, c, d) = (c3 / c4, c2 / c4, c1 / c4, c0 / c4);
+    // 代换 x = y - a/4 得到 y⁴ + p y² + q y + r = 0
+    let p = b - 3.0 * a * a / 8.0;
+    let q = c - a * b / 2.0 + a * a * a / 8.0;
+    let r = d - a * c / 4.0 + a * a * b / 16.0 - 3.0 * a * a * a * a / 256.0;
+
+    let mut roots = Vec::with_capacity(4);
+    let mut push_quadratic = |b: f64, c: f64| {
+        let discriminant = b * b - 4.0 * c;
+        if discriminant >= 0.0 {
+            let sqrt = discriminant.sqrt();
+            roots.push((-b - sqrt) / 2.0);
+            roots.push((-b + sqrt) / 2.0);
+        }
+    };
+    if q.abs() < 1e-12 {
+        // 双二次方程
+        let discriminant = p * p - 4.0 * r;
+        if discriminant >= 0.0 {
+            let sqrt = discriminant.sqrt();
+            for z in [(-p - sqrt) / 2.0, (-p + sqrt) / 2.0].iter() {
+                if *z >= 0.0 {
+                    push_quadratic(0.0, -z);
+                }
+            }
+        }
+    } else {
+        // (y² + m + p/2)² = (s y - q/(2s))²，s = √(2m)，m是预解三次方程的正根
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m > 0.0 {
+            let s = (2.0 * m).sqrt();
+            push_quadratic(-s, m + p / 2.0 + q / (2.0 * s));
+            push_quadratic(s, m + p / 2.0 - q / (2.0 * s));
+        }
+    }
+
+    roots
+        .into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..2 {
+                let f = (((c4 * x + c3) * x + c2) * x + c1) * x + c0;
+                let df = ((4.0 * c4 * x + 3.0 * c3) * x + 2.0 * c2) * x + c1;
+                if df.abs() > 1e-12 {
+                    x -= f / df;
+                }
+            }
+            x
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Rc<dyn Material> {
+        Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn test_solve_quartic() {
+        // (x-1)(x-2)(x-3)(x-4)
+        let mut roots = solve_quartic(&[24.0, -50.0, 35.0, -10.0, 1.0]);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip(&[1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        // (x²+1)(x-2)(x+3) = x⁴ + x³ - 5x² + x - 6，只有两个实根
+        let mut roots = solve_quartic(&[-6.0, 1.0, -5.0, 1.0, 1.0]);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(roots.len(), 2);
+        assert!((roots[0] + 3.0).abs() < 1e-9 && (roots[1] - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_torus_hits_and_normals() {
+        // 轴沿z的圆环，从正面看过去先打到管子，中间的洞打不到
+        let torus = Torus::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 2.0, 0.5, material());
+        let ray = Ray::new(Vec3::new(2.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = torus.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 9.5).abs() < 1e-3);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-3);
+        let hole = Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(torus.hit(&hole, 0.001, Float::MAX).is_none());
+        // 从侧面穿过，先打到外侧
+        let side = Ray::new(Vec3::new(10.0, 0.0, 0.0), Vec3::new(-2.0, 0.0, 0.0));
+        let hit = torus.hit(&side, 0.001, Float::MAX).unwrap();
+        assert!((hit.point.x() - 2.5).abs() < 1e-3);
+        assert!((hit.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn test_cylinder_cone_and_disk() {
+        let cylinder = Cylinder::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            1.0,
+            material(),
+        );
+        let ray = Ray::new(Vec3::new(5.0, 2.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = cylinder.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4 && (hit.v - 0.5).abs() < 1e-4);
+        // 开口的圆柱斜着从上面看进去打到内壁，竖直向下的射线和侧面平行，从开口穿过去；
+        // 封口的打到顶面
+        let slanted = Ray::new(Vec3::new(0.0, 4.0, 0.0), Vec3::new(1.0, -2.0, 0.0));
+        let hit = cylinder.hit(&slanted, 0.001, Float::MAX).unwrap();
+        assert!((hit.point - Vec3::new(1.0, 2.0, 0.0)).length() < 1e-4);
+        assert!(!hit.front_face && (hit.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-4);
+        let down = Ray::new(Vec3::new(0.5, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(cylinder.hit(&down, 0.001, Float::MAX).is_none());
+        let capped = Cylinder::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            1.0,
+            material(),
+        )
+        .capped();
+        let hit = capped.hit(&down, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 7.0).abs() < 1e-4 && hit.normal.y() > 0.99);
+
+        let cone = Cone::new(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), 1.0, material());
+        let hit = cone.hit(&down, 0.001, Float::MAX).unwrap();
+        // 半径0.5处的高度为1
+        assert!((hit.point.y() - 1.0).abs() < 1e-4);
+        assert!(hit.normal.x() > 0.0 && hit.normal.y() > 0.0);
+
+        let bbox = cone.bounding_box().unwrap();
+        assert!(bbox.max().y() >= 2.0 - 1e-4 && bbox.min().x() <= -1.0 + 1e-4);
+
+        let disk = Disk::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, material())
+            .with_inner_radius(0.25);
+        assert!(disk.hit(&down, 0.001, Float::MAX).is_some());
+        let center = Ray::new(Vec3::new(0.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk.hit(&center, 0.001, Float::MAX).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "inner radius")]
+    fn test_disk_rejects_inner_radius_equal_to_radius() {
+        Disk::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, material()).with_inner_radius(1.0);
+    }
+}
@@ -4,12 +4,18 @@ use crate::vec3::{Float, Vec3};
 pub struct Ray {
     a: Vec3,
     b: Vec3,
+    /// 射线发出的时刻，用于运动模糊
+    time: Float,
 }
 
 #[allow(dead_code)]
 impl Ray {
     pub fn new(a: Vec3, b: Vec3) -> Self {
-        Ray { a: a, b: b }
+        Ray { a, b, time: 0.0 }
+    }
+
+    pub fn with_time(a: Vec3, b: Vec3, time: Float) -> Self {
+        Ray { a, b, time }
     }
 
     pub fn origin(&self) -> &Vec3 {
@@ -20,6 +26,10 @@ impl Ray {
         &self.b
     }
 
+    pub fn time(&self) -> Float {
+        self.time
+    }
+
     pub fn point_at_parameter(&self, t: &Float) -> Vec3 {
         self.a + *t * self.b
     }
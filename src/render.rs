@@ -0,0 +1,436 @@
+use crate::aov::{material_key, AovBuffers, AovSample};
+use crate::bdpt::Bidirectional;
+use crate::camera::Camera;
+use crate::denoise::Denoiser;
+use crate::mlt::Metropolis;
+use crate::preview;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::sppm::PhotonMapping;
+use crate::vec3::{Float, Vec3};
+use image::{ImageBuffer, ImageResult, RgbImage};
+use rand::Rng;
+use std::path::Path;
+
+/// 渲染参数
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    /// 射线最多反射的次数
+    pub max_depth: i32,
+    /// 渲染完成后是否用辅助缓冲引导的滤波降噪
+    pub denoise: bool,
+    /// 快门打开和关闭的时刻，每条摄像机射线的时间在其间均匀分布，形成运动模糊
+    pub shutter: (Float, Float),
+    /// 用主波长采样的光谱模式渲染，可以表现色散和用光谱定义的材质与光源，只用于路径追踪
+    pub spectral: bool,
+    pub integrator: Integrator,
+}
+
+/// 估计每条摄像机射线带回的光的方法
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Integrator {
+    /// 从摄像机出发的路径追踪
+    PathTracing,
+    /// 双向路径追踪，焦散和只从小开口照进来的光收敛得更快
+    Bidirectional,
+    /// 随机渐进光子映射，焦散干净，每个像素的采样数作为迭代的轮数，轮数少时偏模糊
+    PhotonMapping,
+    /// 主样本空间的Metropolis光传输，适合光只能从很窄的缝隙照进来的场景，
+    /// `large_step_probability`为每次变异重新生成整条路径的概率
+    Metropolis { large_step_probability: Float },
+    /// 着色法向，这一项和以下几项是检查场景用的预览，见`preview`模块
+    Normals,
+    /// 到摄像机的距离
+    Depth,
+    /// 材质的反照率
+    Albedo,
+    /// 求交时检测包围盒和物体次数的热度图
+    TraversalCost,
+    /// 三角形网格的线框
+    Wireframe,
+    /// `radius`以内的环境光遮蔽
+    AmbientOcclusion { radius: Float },
+    /// 只有直接光照
+    DirectLighting,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 1200,
+            height: 800,
+            samples_per_pixel: 500,
+            max_depth: 50,
+            denoise: false,
+            shutter: (0.0, 0.0),
+            spectral: false,
+            integrator: Integrator::PathTracing,
+        }
+    }
+}
+
+/// 渲染结果：线性空间的颜色以及各个渲染通道
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+    aov: AovBuffers,
+}
+
+impl Image {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// 线性空间的颜色，`(0, 0)`为左上角
+    pub fn pixel(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    pub fn aov(&self) -> &AovBuffers {
+        &self.aov
+    }
+
+    /// 进行gamma=2.0的校正并转换为8位图像
+    pub fn to_rgb8(&self) -> RgbImage {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| to_rgb(self.pixel(x, y)))
+    }
+
+    /// 保存为8位图像，格式由扩展名决定
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        self.to_rgb8().save(path)
+    }
+
+    /// 保存为包含所有渲染通道的多层EXR文件
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P) -> exr::error::UnitResult {
+        self.aov.save_exr(&self.pixels, path)
+    }
+}
+
+/// 对线性颜色进行gamma=2.0的校正并转换为8位像素
+fn to_rgb(pixel_color: Vec3) -> image::Rgb<u8> {
+    let r = pixel_color.x().sqrt();
+    let g = pixel_color.y().sqrt();
+    let b = pixel_color.z().sqrt();
+
+    let r = (255.0 * r) as u8;
+    let g = (255.0 * g) as u8;
+    let b = (255.0 * b) as u8;
+
+    image::Rgb([r, g, b])
+}
+
+/// 渲染器，按设置选择积分器
+pub struct Renderer {
+    settings: RenderSettings,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Self {
+        Renderer { settings }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// 用一个摄像机渲染整幅图像
+    pub fn render(&self, scene: &Scene, camera: &dyn Camera) -> Image {
+        let RenderSettings {
+            width,
+            height,
+            max_depth,
+            integrator,
+            ..
+        } = self.settings;
+        let (mut pixels, aov_buffers) = match integrator {
+            Integrator::PhotonMapping => {
+                PhotonMapping::new(scene, max_depth).render(camera, &self.settings)
+            }
+            Integrator::Metropolis {
+                large_step_probability,
+            } => Metropolis::new(scene, max_depth)
+                .with_large_step_probability(large_step_probability)
+                .render(camera, &self.settings),
+            _ => self.render_samples(scene, camera),
+        };
+
+        if self.settings.denoise {
+            pixels = Denoiser::default().denoise(&pixels, &aov_buffers);
+        }
+
+        Image {
+            width,
+            height,
+            pixels,
+            aov: aov_buffers,
+        }
+    }
+
+    /// 每个像素独立地追踪若干条摄像机射线取平均
+    fn render_samples(&self, scene: &Scene, camera: &dyn Camera) -> (Vec<Vec3>, AovBuffers) {
+        let RenderSettings {
+            width,
+            height,
+            samples_per_pixel,
+            max_depth,
+            spectral,
+            integrator,
+            ..
+        } = self.settings;
+        let bidirectional = Bidirectional::new(scene, max_depth);
+        let mut rng = sampler::rng();
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        let mut aov_buffers = AovBuffers::new(width as usize, height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut pixel_color = Vec3::zero();
+                for _ in 0..samples_per_pixel {
+                    let mut aov = AovSample::miss(Vec3::zero());
+                    if let Some(ray) = camera_ray(camera, &self.settings, x, y) {
+                        pixel_color += match integrator {
+                            Integrator::PathTracing if spectral => {
+                                let mut wavelengths =
+                                    SampledWavelengths::sample_uniform(rng.gen_range(0.0, 1.0));
+                                spectral_ray_color(
+                                    &ray,
+                                    scene,
+                                    max_depth,
+                                    &mut wavelengths,
+                                    &mut aov,
+                                )
+                            }
+                            Integrator::Bidirectional => bidirectional.ray_color(&ray, &mut aov),
+                            Integrator::Normals => preview::normal(&ray, scene, &mut aov),
+                            Integrator::Depth => preview::depth(&ray, scene, &mut aov),
+                            Integrator::Albedo => preview::albedo(&ray, scene, &mut aov),
+                            Integrator::TraversalCost => {
+                                preview::traversal_cost(&ray, scene, &mut aov)
+                            }
+                            Integrator::Wireframe => preview::wireframe(&ray, scene, &mut aov),
+                            Integrator::AmbientOcclusion { radius } => {
+                                preview::ambient_occlusion(&ray, scene, radius, &mut aov)
+                            }
+                            Integrator::DirectLighting => {
+                                preview::direct_lighting(&ray, scene, max_depth, &mut aov)
+                            }
+                            _ => ray_color(&ray, scene, max_depth, Some(&mut aov)),
+                        };
+                    }
+                    aov_buffers.add(x as usize, y as usize, &aov);
+                }
+                pixels.push(pixel_color / samples_per_pixel as Float);
+            }
+        }
+        (pixels, aov_buffers)
+    }
+}
+
+/// 穿过像素`(x, y)`内随机一点的摄像机射线，时间在快门打开的时段内随机选取
+pub(crate) fn camera_ray(
+    camera: &dyn Camera,
+    settings: &RenderSettings,
+    x: u32,
+    y: u32,
+) -> Option<Ray> {
+    let mut rng = sampler::rng();
+    let (open, close) = settings.shutter;
+    let u = (x as Float + rng.gen_range(0.0, 1.0)) / (settings.width - 1) as Float;
+    let v = 1.0 - (y as Float + rng.gen_range(0.0, 1.0)) / (settings.height - 1) as Float;
+    let time = open + (close - open) * rng.gen_range(0.0, 1.0);
+    camera.get_ray_at(u, v, time)
+}
+
+/// 追踪一条射线，`aov`不为空时记录第一次碰撞的各个通道
+pub fn ray_color(ray: &Ray, scene: &Scene, depth: i32, aov: Option<&mut AovSample>) -> Vec3 {
+    let (emitted, reflected) = trace(ray, scene, depth, aov);
+    emitted + reflected
+}
+
+/// 返回射线击中处的自发光（或背景）和经该处反射的光，
+/// 分开返回以便区分直接光照和间接光照
+fn trace(ray: &Ray, scene: &Scene, depth: i32, aov: Option<&mut AovSample>) -> (Vec3, Vec3) {
+    if depth < 0 {
+        return (Vec3::zero(), Vec3::zero());
+    }
+
+    match scene.hit(ray, 0.001, Float::MAX) {
+        Some(hit_record) => {
+            let emitted = hit_record.material.emitted(ray, &hit_record);
+            let (direct, indirect) = match hit_record.material.scatter(ray, &hit_record) {
+                Some((attenuation, scattered)) => {
+                    let (emitted, reflected) = trace(&scattered, scene, depth - 1, None);
+                    (attenuation * emitted, attenuation * reflected)
+                }
+                None => (Vec3::zero(), Vec3::zero()),
+            };
+            if let Some(aov) = aov {
+                *aov = AovSample {
+                    albedo: hit_record.material.albedo(&hit_record),
+                    normal: hit_record.normal,
+                    depth: hit_record.t * ray.direction().length(),
+                    position: hit_record.point,
+                    material_key: material_key(&hit_record.material),
+                    object_id: hit_record.object_id,
+                    direct: emitted + direct,
+                    indirect,
+                };
+            }
+            (emitted, direct + indirect)
+        }
+        None => {
+            let background = scene.background(ray);
+            if let Some(aov) = aov {
+                *aov = AovSample::miss(background);
+            }
+            (background, Vec3::zero())
+        }
+    }
+}
+
+/// 光谱模式下追踪一条射线，返回转换成线性sRGB的颜色，并记录第一次碰撞的各个通道
+pub fn spectral_ray_color(
+    ray: &Ray,
+    scene: &Scene,
+    depth: i32,
+    wavelengths: &mut SampledWavelengths,
+    aov: &mut AovSample,
+) -> Vec3 {
+    let (emitted, reflected) = trace_spectral(ray, scene, depth, wavelengths, Some(aov));
+    wavelengths.to_rgb(&(emitted + reflected))
+}
+
+/// 和`trace`相同，但辐射度是几个波长上的光谱值
+///
+/// 所有波长共用同一条路径，色散会在路径中途丢弃次要波长，
+/// 所以通道的颜色要等整条路径追踪完再用最终的概率密度转换
+fn trace_spectral(
+    ray: &Ray,
+    scene: &Scene,
+    depth: i32,
+    wavelengths: &mut SampledWavelengths,
+    aov: Option<&mut AovSample>,
+) -> (SampledSpectrum, SampledSpectrum) {
+    if depth < 0 {
+        return (SampledSpectrum::zero(), SampledSpectrum::zero());
+    }
+
+    match scene.hit(ray, 0.001, Float::MAX) {
+        Some(hit_record) => {
+            let emitted = hit_record
+                .material
+                .emitted_spectral(ray, &hit_record, wavelengths);
+            let (direct, indirect) =
+                match hit_record
+                    .material
+                    .scatter_spectral(ray, &hit_record, wavelengths)
+                {
+                    Some((attenuation, scattered)) => {
+                        let (emitted, reflected) =
+                            trace_spectral(&scattered, scene, depth - 1, wavelengths, None);
+                        (attenuation * emitted, attenuation * reflected)
+                    }
+                    None => (SampledSpectrum::zero(), SampledSpectrum::zero()),
+                };
+            if let Some(aov) = aov {
+                *aov = AovSample {
+                    albedo: hit_record.material.albedo(&hit_record),
+                    normal: hit_record.normal,
+                    depth: hit_record.t * ray.direction().length(),
+                    position: hit_record.point,
+                    material_key: material_key(&hit_record.material),
+                    object_id: hit_record.object_id,
+                    direct: wavelengths.to_rgb(&(emitted + direct)),
+                    indirect: wavelengths.to_rgb(&indirect),
+                };
+            }
+            (emitted, direct + indirect)
+        }
+        None => {
+            let background = scene.background(ray);
+            if let Some(aov) = aov {
+                *aov = AovSample::miss(background);
+            }
+            (
+                SampledSpectrum::from_rgb(background, wavelengths),
+                SampledSpectrum::zero(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::PerspectiveCamera;
+    use crate::hittable::Sphere;
+    use crate::material::DiffuseLight;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_render_tiny_scene() {
+        // 摄像机正对一个很大的发光球，画面上的每个像素都是它的发光
+        let mut builder = Scene::builder();
+        builder.add(Box::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -100.0),
+            90.0,
+            Rc::new(DiffuseLight::new(Vec3::new(0.25, 0.5, 1.0))),
+        )));
+        let scene = builder.build();
+        let camera = PerspectiveCamera::new(
+            &Vec3::zero(),
+            &Vec3::new(0.0, 0.0, -1.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+            20.0,
+            1.5,
+            0.0,
+            1.0,
+        );
+        let settings = RenderSettings {
+            width: 6,
+            height: 4,
+            samples_per_pixel: 3,
+            ..RenderSettings::default()
+        };
+
+        let image = Renderer::new(settings.clone()).render(&scene, &camera);
+        assert_eq!((image.width(), image.height()), (6, 4));
+        assert_eq!(image.pixels().len(), 24);
+        for &pixel in image.pixels() {
+            assert!((pixel - Vec3::new(0.25, 0.5, 1.0)).length() < 1e-5);
+        }
+        assert_eq!(
+            image.to_rgb8().get_pixel(5, 3),
+            &image::Rgb([127, 180, 255])
+        );
+        let aov = image.aov();
+        assert_eq!(aov.sample_count(0, 0), 3);
+        assert_eq!(aov.object_id(2, 1), 1);
+        // 球面离摄像机最近处的距离为10，像素内的样本偏离画面中心，会稍远一些
+        let depth = aov.depth(3, 2);
+        assert!(depth > 10.0 && depth < 10.5);
+
+        // 深度预览里越近越亮，中心最近
+        let image = Renderer::new(RenderSettings {
+            integrator: Integrator::Depth,
+            ..settings
+        })
+        .render(&scene, &camera);
+        assert!(image.pixel(3, 2).x() > image.pixel(0, 0).x());
+        let depth = image.aov().depth(3, 2);
+        assert!(depth > 10.0 && depth < 10.5);
+    }
+}
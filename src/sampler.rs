@@ -0,0 +1,198 @@
+//! 渲染用的随机数来源
+//!
+//! 平时直接使用`thread_rng`。Metropolis光传输在追踪一条路径期间把一个主样本空间里的点
+//! 装进当前线程，材质、摄像机和光源抽样用到的随机数就依次从这个点的各个分量里取，
+//! 变异这个点就相当于变异整条路径
+
+use crate::vec3::Float;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static PRIMARY_SAMPLE: RefCell<Option<PrimarySample>> = const { RefCell::new(None) };
+}
+
+/// 渲染代码应当用它代替`rand::thread_rng()`
+pub fn rng() -> SampleRng {
+    SampleRng
+}
+
+/// 在`f`执行期间用`sample`提供随机数，返回变异过的`sample`和`f`的结果
+pub fn with_primary_sample<R, F: FnOnce() -> R>(sample: PrimarySample, f: F) -> (PrimarySample, R) {
+    PRIMARY_SAMPLE.with(|cell| *cell.borrow_mut() = Some(sample));
+    let result = f();
+    let sample = PRIMARY_SAMPLE
+        .with(|cell| cell.borrow_mut().take())
+        .unwrap();
+    (sample, result)
+}
+
+/// 当前线程的随机数发生器，装了主样本时从主样本里取数
+#[derive(Debug, Copy, Clone)]
+pub struct SampleRng;
+
+impl SampleRng {
+    /// `[0, 1)`内均匀分布的数
+    fn uniform(&mut self) -> Option<f64> {
+        PRIMARY_SAMPLE.with(|cell| cell.borrow_mut().as_mut().map(|s| s.next() as f64))
+    }
+}
+
+impl RngCore for SampleRng {
+    fn next_u32(&mut self) -> u32 {
+        match self.uniform() {
+            Some(u) => (u * 4_294_967_296.0) as u32,
+            None => rand::thread_rng().next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self.uniform() {
+            Some(u) => (u * 18_446_744_073_709_551_616.0) as u64,
+            None => rand::thread_rng().next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand::rngs::mock::StepRng::new(self.next_u64(), 1).fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// 主样本空间里的一个点，按Kelemen等人（2002）的方法变异
+///
+/// 分量在第一次用到时才生成。大步变异把所有分量换成新的均匀随机数，
+/// 小步变异给每个分量加上正态分布的扰动。为了不在每次变异时更新所有分量，
+/// 每个分量记下上次更新的迭代序号，用到时再补上错过的变异
+pub struct PrimarySample {
+    rng: StdRng,
+    values: Vec<Float>,
+    /// 每个分量上次更新时的迭代序号
+    modified: Vec<u64>,
+    /// 本次变异之前的分量，拒绝时恢复
+    backup: Vec<(usize, Float, u64)>,
+    index: usize,
+    iteration: u64,
+    last_large_step: u64,
+    large_step: bool,
+    large_step_probability: Float,
+    /// 小步变异的标准差
+    sigma: Float,
+}
+
+impl PrimarySample {
+    /// 同样的`seed`生成同样的初始点，第一次求值相当于一次大步变异
+    pub fn new(seed: u64, large_step_probability: Float, sigma: Float) -> Self {
+        PrimarySample {
+            rng: StdRng::seed_from_u64(seed),
+            values: Vec::new(),
+            modified: Vec::new(),
+            backup: Vec::new(),
+            index: 0,
+            iteration: 0,
+            last_large_step: 0,
+            large_step: true,
+            large_step_probability,
+            sigma,
+        }
+    }
+
+    /// 开始一次变异，各分量在下次求值用到时才真正变异
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen_range(0.0, 1.0) < self.large_step_probability;
+        self.index = 0;
+        self.backup.clear();
+    }
+
+    pub fn is_large_step(&self) -> bool {
+        self.large_step
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+        self.backup.clear();
+    }
+
+    pub fn reject(&mut self) {
+        for &(i, value, modified) in &self.backup {
+            self.values[i] = value;
+            self.modified[i] = modified;
+        }
+        self.backup.clear();
+        self.iteration -= 1;
+    }
+
+    /// 下一个分量
+    fn next(&mut self) -> Float {
+        let i = self.index;
+        self.index += 1;
+        if i >= self.values.len() {
+            // 新的分量相当于在初始点的那次大步变异里生成
+            let value = self.rng.gen_range(0.0, 1.0);
+            self.values.push(value);
+            self.modified.push(0);
+        }
+        // 补上最近一次被接受的大步变异
+        if self.modified[i] < self.last_large_step {
+            self.values[i] = self.rng.gen_range(0.0, 1.0);
+            self.modified[i] = self.last_large_step;
+        }
+        self.backup.push((i, self.values[i], self.modified[i]));
+        if self.large_step {
+            self.values[i] = self.rng.gen_range(0.0, 1.0);
+        } else {
+            // 错过的几次小步变异合在一起，方差相加
+            let n = (self.iteration - self.modified[i]) as Float;
+            let value = self.values[i] + self.normal() * self.sigma * n.sqrt();
+            self.values[i] = value - value.floor();
+        }
+        self.modified[i] = self.iteration;
+        self.values[i]
+    }
+
+    /// 标准正态分布的随机数
+    fn normal(&mut self) -> Float {
+        let u1: Float = self.rng.gen_range(Float::EPSILON, 1.0);
+        let u2: Float = self.rng.gen_range(0.0, 1.0);
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reject_restores_sample() {
+        let draw = |sample: PrimarySample| {
+            with_primary_sample(sample, || {
+                let mut rng = rng();
+                (0..8)
+                    .map(|_| rng.gen_range(0.0, 1.0))
+                    .collect::<Vec<Float>>()
+            })
+        };
+        // 同样的种子得到同样的初始点
+        let (_, first) = draw(PrimarySample::new(7, 0.3, 0.01));
+        let (mut sample, again) = draw(PrimarySample::new(7, 0.3, 0.01));
+        assert_eq!(first, again);
+
+        sample.start_iteration();
+        let (mut sample, mutated) = draw(sample);
+        assert_ne!(first, mutated);
+        sample.reject();
+        sample.start_iteration();
+        sample.large_step = false;
+        sample.sigma = 0.0;
+        let (_, restored) = draw(sample);
+        assert_eq!(first, restored);
+    }
+}
@@ -0,0 +1,954 @@
+use crate::aabb::Aabb;
+use crate::animation::{Animated, Interpolation, Track, TransformAnimation};
+use crate::bvh::Bvh;
+use crate::csg::Csg;
+use crate::curve::{Curve, CurveType};
+use crate::heightfield::Heightfield;
+use crate::hittable::*;
+use crate::layered::{Clearcoat, Flakes, Sheen, ThinFilm};
+use crate::material::*;
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::microfacet::AnisotropicMetal;
+use crate::normal_map::{BumpMap, NormalMap};
+use crate::primitives::{Cone, Cylinder, Disk, Plane, Torus};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::sdf::*;
+use crate::spectrum::Spectrum;
+use crate::texture::{ImageTexture, Texture};
+use crate::transform::Transform;
+use crate::vec3::{Float, Vec3};
+use crate::{ply, stl, subdivision};
+use rand::Rng;
+use std::error::Error;
+use std::rc::Rc;
+
+/// 渲染用的场景，物体在构建时被组织成BVH，之后可以被多个摄像机和多次渲染共用
+pub struct Scene {
+    world: Bvh,
+    distant_lights: Vec<DistantLight>,
+    /// 面光源和它们的物体编号
+    lights: Vec<(usize, Rc<dyn Hittable>)>,
+}
+
+/// 无限远处的光源，例如太阳，在天空中是一个张角很小的圆盘
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DistantLight {
+    /// 指向光源的单位向量
+    direction: Vec3,
+    radiance: Vec3,
+    cos_radius: Float,
+}
+
+impl DistantLight {
+    /// `direction`为光照射的方向，`illuminance`为垂直照射时的照度，`angular_diameter`为圆盘的张角（度）
+    pub fn new(direction: Vec3, illuminance: Vec3, angular_diameter: Float) -> Self {
+        let cos_radius = (angular_diameter.to_radians() / 2.0).cos();
+        let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - cos_radius);
+        DistantLight {
+            direction: -direction.unit_vector(),
+            radiance: illuminance / solid_angle,
+            cos_radius,
+        }
+    }
+}
+
+impl Scene {
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    /// 场景中所有物体组成的加速结构
+    pub fn world(&self) -> &dyn Hittable {
+        &self.world
+    }
+
+    /// 射线没有击中任何物体时看到的天空，从地平线的白色渐变到天顶的浅蓝色，再加上远处的光源
+    pub fn background(&self, ray: &Ray) -> Vec3 {
+        let direction = ray.direction().unit_vector();
+        let t = 0.5 * (direction.y() + 1.0);
+        let mut color = (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0);
+        for light in &self.distant_lights {
+            if direction.dot(&light.direction) >= light.cos_radius {
+                color += light.radiance;
+            }
+        }
+        color
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        self.world.hit(ray, t_min, t_max)
+    }
+
+    /// 求交时检测过的包围盒和物体的个数，见`Bvh::hit_with_cost`
+    pub fn traversal_cost(&self, ray: &Ray) -> usize {
+        self.world.hit_with_cost(ray, 0.001, Float::MAX).1
+    }
+
+    /// 用`SceneBuilder::add_light`添加的面光源的个数
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// 在第`index`个面光源上按面积均匀抽样一点，碰撞记录带有物体编号
+    pub fn sample_light(&self, index: usize, u: Float, v: Float) -> Option<HitRecord> {
+        let (id, light) = &self.lights[index];
+        light.sample_surface(u, v).map(|mut hit_record| {
+            hit_record.object_id = *id;
+            hit_record
+        })
+    }
+
+    pub fn light_area(&self, index: usize) -> Float {
+        self.lights[index].1.area()
+    }
+
+    /// 编号为`object_id`的物体在面光源中的序号，不是面光源时返回`None`
+    pub fn light_index(&self, object_id: usize) -> Option<usize> {
+        self.lights.iter().position(|(id, _)| *id == object_id)
+    }
+
+    /// 包住所有有限大物体的球，返回球心和半径
+    pub fn bounding_sphere(&self) -> (Vec3, Float) {
+        match self.world.finite_bounding_box() {
+            Some(bbox) => {
+                let center = bbox.centroid();
+                (center, (bbox.max() - center).length().max(1e-3))
+            }
+            None => (Vec3::zero(), 1.0),
+        }
+    }
+
+    /// 抽样一个看向天空的方向，天空和每个远处的光源各占相同的概率：
+    /// 天空在整个球面上均匀抽样，远处的光源在它的圆盘内均匀抽样
+    pub fn sample_background(&self) -> Vec3 {
+        let mut rng = sampler::rng();
+        let pi = std::f32::consts::PI;
+        let choice = rng.gen_range(0, self.distant_lights.len() + 1);
+        let (axis, cos_max) = match self.distant_lights.get(choice) {
+            Some(light) => (light.direction, light.cos_radius),
+            None => (Vec3::new(0.0, 1.0, 0.0), -1.0),
+        };
+        let cos = 1.0 - rng.gen_range(0.0, 1.0) * (1.0 - cos_max);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = rng.gen_range(0.0, 2.0 * pi);
+        let helper = if axis.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t = axis.cross(&helper).unit_vector();
+        let b = axis.cross(&t);
+        sin * phi.cos() * t + sin * phi.sin() * b + cos * axis
+    }
+
+    /// `sample_background`抽样到单位向量`direction`的概率密度（立体角）
+    pub fn background_pdf(&self, direction: &Vec3) -> Float {
+        let pi = std::f32::consts::PI;
+        let mut pdf = 1.0 / (4.0 * pi);
+        for light in &self.distant_lights {
+            if direction.dot(&light.direction) >= light.cos_radius {
+                pdf += 1.0 / (2.0 * pi * (1.0 - light.cos_radius));
+            }
+        }
+        pdf / (self.distant_lights.len() + 1) as Float
+    }
+}
+
+/// 逐个添加物体，最后调用`build`构建加速结构
+#[derive(Default)]
+pub struct SceneBuilder {
+    objects: Vec<Box<dyn Hittable>>,
+    distant_lights: Vec<DistantLight>,
+    lights: Vec<(usize, Rc<dyn Hittable>)>,
+}
+
+impl SceneBuilder {
+    /// 添加一个物体，物体编号按添加的顺序从1开始
+    pub fn add(&mut self, object: Box<dyn Hittable>) -> &mut Self {
+        self.objects.push(object);
+        self
+    }
+
+    pub fn distant_light(&mut self, light: DistantLight) -> &mut Self {
+        self.distant_lights.push(light);
+        self
+    }
+
+    /// 添加一个发光的物体，双向路径追踪会在它的表面上抽样光源，物体需要支持`sample_surface`
+    pub fn add_light(&mut self, light: Rc<dyn Hittable>) -> &mut Self {
+        assert!(light.area() > 0.0, "light must support sample_surface");
+        self.lights
+            .push((self.objects.len() + 1, Rc::clone(&light)));
+        self.add(Box::new(light))
+    }
+
+    pub fn build(&mut self) -> Scene {
+        Scene {
+            world: Bvh::new(std::mem::take(&mut self.objects)),
+            distant_lights: std::mem::take(&mut self.distant_lights),
+            lights: std::mem::take(&mut self.lights),
+        }
+    }
+}
+
+/// 地面和随机分布的小球
+fn add_ground_and_small_spheres(world: &mut SceneBuilder) {
+    let mut rng = rand::thread_rng();
+    let material_ground = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+    world.add(Box::new(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        material_ground,
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.gen_range(0.0, 1.0);
+            let center = Vec3::new(
+                a as Float + 0.9 * rng.gen_range(0.0, 1.0),
+                0.2,
+                b as Float + 0.9 * rng.gen_range(0.0, 1.0),
+            );
+
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Vec3::random() * Vec3::random();
+                    let sphere_material = Rc::new(Lambertian::new(&albedo));
+
+                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Vec3::new(
+                        rng.gen_range(0.5, 1.0),
+                        rng.gen_range(0.5, 1.0),
+                        rng.gen_range(0.5, 1.0),
+                    );
+                    let fuzz = rng.gen_range(0.0, 0.5);
+                    let sphere_material = Rc::new(Metal::new(&albedo, fuzz));
+
+                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                } else {
+                    // glass
+                    let sphere_material = Rc::new(Dielectric::new(1.5));
+                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                }
+            }
+        }
+    }
+}
+
+/// `load_mesh`读入网格之后的处理
+#[derive(Debug, Clone)]
+pub struct MeshOptions {
+    /// Loop细分的次数
+    pub subdivide: u32,
+    /// 位移贴图
+    pub displacement: Option<String>,
+    /// 位移的最大距离
+    pub displacement_scale: Float,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        MeshOptions {
+            subdivide: 0,
+            displacement: None,
+            displacement_scale: 0.05,
+        }
+    }
+}
+
+/// 读取PLY或STL网格，按需要细分和做位移，缩放到2个单位高，立在随机小球场景里玻璃球前方的地面上
+///
+/// 没有给出材质时用漫反射
+pub fn load_mesh(
+    path: &str,
+    options: &MeshOptions,
+    material: Option<Rc<dyn Material>>,
+) -> Result<TriangleMesh, Box<dyn Error>> {
+    let mut mesh = if path.to_lowercase().ends_with(".stl") {
+        stl::load(path)
+    } else {
+        ply::load(path)
+    }
+    .map_err(|e| format!("Failed to load {}: {}", path, e))?;
+    let bbox = mesh
+        .bounding_box()
+        .ok_or_else(|| format!("{} has no vertices", path))?;
+    let scale = 2.0 / (bbox.max().y() - bbox.min().y()).max(1e-6);
+    let center = bbox.centroid();
+    let fit = Transform::translate(-Vec3::new(center.x(), bbox.min().y(), center.z()))
+        .then(&Transform::scale(Vec3::new(scale, scale, scale)))
+        .then(&Transform::translate(Vec3::new(0.0, 0.0, 2.5)));
+    mesh.transform(&fit);
+    if options.subdivide > 0 {
+        mesh = subdivision::loop_subdivide(&mesh, options.subdivide);
+    }
+    if let Some(path) = &options.displacement {
+        let texture = ImageTexture::open(path, false)
+            .map_err(|e| format!("Failed to load {}: {}", path, e))?;
+        mesh.displace(&texture, options.displacement_scale);
+    }
+    let material = material.unwrap_or_else(|| {
+        // 有顶点颜色时用白色，让顶点颜色直接作为反照率
+        let albedo = if mesh.colors.is_empty() {
+            Vec3::new(0.7, 0.7, 0.7)
+        } else {
+            Vec3::new(1.0, 1.0, 1.0)
+        };
+        Rc::new(Lambertian::new(&albedo))
+    });
+    Ok(TriangleMesh::new(mesh, material))
+}
+
+/// Ray Tracing in One Weekend最后一章的随机小球场景
+pub fn random_scene() -> Scene {
+    random_scene_builder().build()
+}
+
+/// 还没有构建的随机小球场景，可以继续往里面添加物体
+pub fn random_scene_builder() -> SceneBuilder {
+    let mut world = Scene::builder();
+    add_ground_and_small_spheres(&mut world);
+    let material1 = Rc::new(Dielectric::new(1.5));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        material1,
+    )));
+    let material2 = Rc::new(Lambertian::new(&Vec3::new(0.4, 0.2, 0.1)));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(-4.0, 1.0, 0.0),
+        1.0,
+        material2,
+    )));
+    let material3 = Rc::new(Metal::new(&Vec3::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(4.0, 1.0, 0.0),
+        1.0,
+        material3,
+    )));
+
+    world
+}
+
+/// 随机小球场景的动画版本：玻璃球和金属球之间多了一个小金属球，在0到4秒之间每秒弹跳一次，
+/// 用于测试运动模糊。它最低时也在小球上方，不会和它们相交
+pub fn animated_scene() -> Scene {
+    let mut world = random_scene_builder();
+    let mut bounce = Track::new();
+    for i in 0..=8 {
+        let height = if i % 2 == 0 { 0.0 } else { 1.5 };
+        bounce = bounce.key(
+            i as Float * 0.5,
+            Vec3::new(0.0, height, 0.0),
+            Interpolation::Bezier,
+        );
+    }
+    let material = Rc::new(Metal::new(&Vec3::new(0.8, 0.8, 0.8), 0.0));
+    world.add(Box::new(Animated::new(
+        Box::new(Sphere::new(Vec3::new(2.0, 0.9, 1.0), 0.5, material)),
+        TransformAnimation {
+            translation: bounce,
+            ..TransformAnimation::default()
+        },
+    )));
+
+    world.build()
+}
+
+/// 沿v方向的正弦起伏，圆盘上是五圈同心圆的凹槽
+struct Grooves;
+
+impl Texture for Grooves {
+    fn value(&self, _u: Float, v: Float, _point: &Vec3) -> Vec3 {
+        let h = 0.5 + 0.5 * (10.0 * std::f32::consts::PI * v).sin();
+        Vec3::new(h, h, h)
+    }
+}
+
+/// 切线空间的法线沿u方向来回倾斜，柱面上是十六道竖直的凹槽
+struct Flutes;
+
+impl Texture for Flutes {
+    fn value(&self, u: Float, _v: Float, _point: &Vec3) -> Vec3 {
+        let x = 0.5 + 0.35 * (32.0 * std::f32::consts::PI * u).sin();
+        Vec3::new(x, 0.5, 1.0)
+    }
+}
+
+/// 展示各种解析几何体的场景：平面上摆着带凹凸贴图的圆盘、带法线贴图的圆柱、拉丝金属的圆锥、圆环和球，以及用CSG做的碗和透镜
+pub fn shapes_scene() -> Scene {
+    let mut world = Scene::builder();
+    world.add(Box::new(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5))),
+    )));
+    // 刻着同心圆纹路的圆环和带竖直凹槽的柱子
+    world.add(Box::new(BumpMap::new(
+        Box::new(
+            Disk::new(
+                Vec3::new(0.0, 0.001, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                5.5,
+                Rc::new(Lambertian::new(&Vec3::new(0.2, 0.3, 0.5))),
+            )
+            .with_inner_radius(4.5),
+        ),
+        Rc::new(Grooves),
+        0.04,
+    )));
+    world.add(Box::new(NormalMap::new(
+        Box::new(
+            Cylinder::new(
+                Vec3::new(-4.0, 0.0, 0.0),
+                Vec3::new(-4.0, 2.0, 0.0),
+                0.8,
+                Rc::new(Lambertian::new(&Vec3::new(0.4, 0.2, 0.1))),
+            )
+            .capped(),
+        ),
+        Rc::new(Flutes),
+    )));
+    world.add(Box::new(
+        Cone::new(
+            Vec3::new(-1.5, 0.0, 1.5),
+            Vec3::new(-1.5, 2.0, 1.5),
+            0.8,
+            // 沿圆周方向拉丝，高光沿母线被拉长
+            Rc::new(AnisotropicMetal::new(&Vec3::new(0.8, 0.6, 0.2), 0.1, 0.5)),
+        )
+        .capped(),
+    ));
+    world.add(Box::new(Torus::new(
+        Vec3::new(1.0, 1.1, -2.2),
+        Vec3::new(1.0, 0.2, -0.4),
+        0.8,
+        0.25,
+        Rc::new(Metal::new(&Vec3::new(0.7, 0.6, 0.5), 0.0)),
+    )));
+    // 色散很强的火石玻璃，只在光谱模式下可见
+    world.add(Box::new(Sphere::new(
+        Vec3::new(4.0, 1.0, 0.0),
+        1.0,
+        Rc::new(Dielectric::new(1.6).with_abbe_number(20.0)),
+    )));
+    // 像蜡一样的半透明球，红光在内部走得更远
+    world.add(Box::new(Sphere::new(
+        Vec3::new(4.5, 0.6, 3.2),
+        0.6,
+        Rc::new(Subsurface::new(
+            Vec3::new(0.9, 0.7, 0.5),
+            Vec3::new(0.3, 0.2, 0.15),
+            1.4,
+        )),
+    )));
+
+    // 空心球切掉上半部分就是碗
+    let bowl_material: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.8, 0.8, 0.7)));
+    let bowl_center = Vec3::new(2.0, 0.8, 2.5);
+    let shell = Csg::difference(
+        Box::new(Sphere::new(bowl_center, 0.8, Rc::clone(&bowl_material))),
+        Box::new(Sphere::new(bowl_center, 0.72, Rc::clone(&bowl_material))),
+    );
+    world.add(Box::new(Csg::difference(
+        Box::new(shell),
+        Box::new(
+            Cylinder::new(
+                bowl_center,
+                bowl_center + Vec3::new(0.0, 1.0, 0.0),
+                1.0,
+                bowl_material,
+            )
+            .capped(),
+        ),
+    )));
+    // 两个球相交得到双凸透镜
+    let glass: Rc<dyn Material> = Rc::new(Dielectric::new(1.5));
+    world.add(Box::new(Csg::intersection(
+        Box::new(Sphere::new(
+            Vec3::new(6.0, 1.0, 1.0),
+            1.2,
+            Rc::clone(&glass),
+        )),
+        Box::new(Sphere::new(Vec3::new(7.6, 1.0, 2.2), 1.2, glass)),
+    )));
+    world.build()
+}
+
+/// 用有向距离场表示的物体：Mandelbulb分形、平滑融合的团块、挖空的圆角方块和重复排列的小球
+pub fn sdf_scene() -> Scene {
+    let mut world = Scene::builder();
+    world.add(Box::new(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5))),
+    )));
+    world.add(Box::new(
+        SdfShape::new(
+            Box::new(Mandelbulb::new(Vec3::new(-2.5, 1.4, 0.0), 1.3, 8.0).with_iterations(8)),
+            Rc::new(Lambertian::new(&Vec3::new(0.8, 0.5, 0.3))),
+        )
+        .with_epsilon(5e-4),
+    ));
+    let blob = SmoothUnion::new(
+        Box::new(SdfSphere::new(Vec3::new(1.0, 0.6, 2.4), 0.6)),
+        Box::new(SdfTorus::new(Vec3::new(1.0, 0.3, 2.4), 0.8, 0.2)),
+        0.4,
+    );
+    world.add(Box::new(SdfShape::new(
+        Box::new(SmoothUnion::new(
+            Box::new(blob),
+            Box::new(SdfCapsule::new(
+                Vec3::new(1.0, 0.6, 2.4),
+                Vec3::new(1.0, 1.6, 2.9),
+                0.2,
+            )),
+            0.3,
+        )),
+        Rc::new(Metal::new(&Vec3::new(0.7, 0.7, 0.8), 0.1)),
+    )));
+    world.add(Box::new(SdfShape::new(
+        Box::new(SmoothSubtraction::new(
+            Box::new(
+                SdfBox::new(Vec3::new(1.0, 0.7, -2.4), Vec3::new(0.7, 0.7, 0.7)).with_rounding(0.1),
+            ),
+            Box::new(SdfSphere::new(Vec3::new(1.0, 1.2, -2.4), 0.8)),
+            0.1,
+        )),
+        Rc::new(Lambertian::new(&Vec3::new(0.2, 0.4, 0.6))),
+    )));
+    world.add(Box::new(SdfShape::new(
+        Box::new(Repeat::new(
+            Box::new(SdfSphere::new(Vec3::new(0.0, 0.2, 0.0), 0.2)),
+            Vec3::new(0.7, 0.0, 0.7),
+            [2, 0, 2],
+        )),
+        Rc::new(Metal::new(&Vec3::new(0.8, 0.3, 0.3), 0.3)),
+    )));
+    world.build()
+}
+
+/// 地形场景：高度场做成的山谷，谷底是一片水面，山坡上立着一个用隐式曲面做的镂空石球
+///
+/// 没有给出高度时用几个正弦波叠加生成地形
+pub fn terrain_scene(heights: Option<(usize, usize, Vec<Float>)>) -> Scene {
+    let (width, depth, heights) = heights.unwrap_or_else(|| {
+        let n = 257;
+        let heights = (0..n * n)
+            .map(|k| {
+                let x = (k % n) as Float / (n - 1) as Float;
+                let z = (k / n) as Float / (n - 1) as Float;
+                // 中间低、四周高的山谷，加上几层起伏
+                let valley = 4.0 * ((x - 0.5).powi(2) + (z - 0.5).powi(2));
+                let hills = 0.15 * (9.0 * x).sin() * (7.0 * z).cos()
+                    + 0.06 * (23.0 * x + 5.0 * z).sin() * (19.0 * z).sin()
+                    + 0.02 * (61.0 * x).sin() * (53.0 * z + 2.0).cos();
+                (0.5 * valley + hills + 0.2).clamp(0.0, 1.0)
+            })
+            .collect();
+        (n, n, heights)
+    });
+    let mut world = Scene::builder();
+    world.add(Box::new(Heightfield::new(
+        width,
+        depth,
+        heights,
+        Vec3::new(-12.0, -1.0, -12.0),
+        Vec3::new(24.0, 4.0, 24.0),
+        Rc::new(Lambertian::new(&Vec3::new(0.35, 0.45, 0.25))),
+    )));
+    world.add(Box::new(Plane::new(
+        Vec3::new(0.0, 0.1, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Rc::new(Metal::new(&Vec3::new(0.3, 0.4, 0.5), 0.05)),
+    )));
+    // 球壳和gyroid曲面相交，留下镂空的网格
+    let center = Vec3::new(0.0, 1.3, 0.0);
+    world.add(Box::new(ImplicitSurface::new(
+        move |p| {
+            let q = 3.0 * (p - center);
+            let gyroid =
+                q.x().sin() * q.y().cos() + q.y().sin() * q.z().cos() + q.z().sin() * q.x().cos();
+            let shell = ((p - center).length() - 1.0).abs() - 0.1;
+            shell.max(gyroid.abs() - 0.4)
+        },
+        Aabb::new(
+            center - Vec3::new(1.2, 1.2, 1.2),
+            center + Vec3::new(1.2, 1.2, 1.2),
+        ),
+        Rc::new(Lambertian::new(&Vec3::new(0.8, 0.75, 0.7))),
+    )));
+    world.build()
+}
+
+/// 薄膜厚度的起伏，几组不同方向的正弦波叠加，范围约为0.4到1.6
+struct Swirl;
+
+impl Texture for Swirl {
+    fn value(&self, _u: Float, _v: Float, point: &Vec3) -> Vec3 {
+        let (x, y, z) = (point.x(), point.y(), point.z());
+        let w = 1.0
+            + 0.3 * (4.0 * x + 2.0 * (3.0 * z).sin()).sin()
+            + 0.2 * (5.0 * y - 3.0 * z).sin()
+            + 0.1 * (9.0 * (x + y + z)).sin();
+        Vec3::new(w, w, w)
+    }
+}
+
+/// 菱形的铁丝网，有铁丝的地方为1，网眼为0
+struct Lattice;
+
+impl Texture for Lattice {
+    fn value(&self, _u: Float, _v: Float, point: &Vec3) -> Vec3 {
+        const SPACING: Float = 0.25;
+        const WIRE: Float = 0.06;
+        let along = point.x() * 0.225 - point.z() * 0.974;
+        let on_wire = |t: Float| (t / SPACING).rem_euclid(1.0) < WIRE;
+        if on_wire(point.y() + along) || on_wire(point.y() - along) {
+            Vec3::new(1.0, 1.0, 1.0)
+        } else {
+            Vec3::zero()
+        }
+    }
+}
+
+/// 一块块的锈斑，值越大锈得越厉害
+struct Rust;
+
+impl Texture for Rust {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> Vec3 {
+        let w = Swirl.value(u, v, &(3.0 * *point)).x();
+        let rust = ((w - 0.9) * 3.0).clamp(0.0, 1.0);
+        Vec3::new(rust, rust, rust)
+    }
+}
+
+/// 分层材质：车漆、天鹅绒、阳极氧化的金属和肥皂泡，前面的地上有一滩油膜，
+/// 后面立着一面生锈的铁丝网，用混合材质和透明度遮罩做成
+pub fn layered_scene() -> Scene {
+    let mut world = Scene::builder();
+    world.add(Box::new(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5))),
+    )));
+    let swirl: Rc<dyn Texture> = Rc::new(Swirl);
+    let asphalt: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.03, 0.03, 0.03)));
+    world.add(Box::new(Disk::new(
+        Vec3::new(3.0, 0.001, 0.3),
+        Vec3::new(0.0, 1.0, 0.0),
+        1.2,
+        Rc::new(
+            ThinFilm::new(500.0, 1.5)
+                .with_thickness_texture(Rc::clone(&swirl))
+                .over(asphalt, 1.33),
+        ),
+    )));
+
+    // 清漆包着银色的铝片，铝片下面是深红色的底漆
+    let primer: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.02, 0.03)));
+    let flakes: Rc<dyn Material> = Rc::new(
+        Flakes::new(primer, Vec3::new(0.7, 0.7, 0.7), 0.008)
+            .with_density(0.15)
+            .with_spread(0.2),
+    );
+    let car_paint = Rc::new(Clearcoat::new(flakes, 0.05));
+    let velvet = Rc::new(Sheen::new(
+        Rc::new(Lambertian::new(&Vec3::new(0.05, 0.05, 0.3))),
+        Vec3::new(0.6, 0.6, 0.9),
+        0.4,
+    ));
+    let anodized = Rc::new(
+        ThinFilm::new(300.0, 2.2)
+            .with_thickness_texture(Rc::clone(&swirl))
+            .over(Rc::new(Metal::new(&Vec3::new(0.3, 0.3, 0.3), 0.05)), 3.0),
+    );
+    let bubble = Rc::new(ThinFilm::new(600.0, 1.33).with_thickness_texture(swirl));
+    let steel: Rc<dyn Material> = Rc::new(Metal::new(&Vec3::new(0.6, 0.6, 0.6), 0.3));
+    let rust: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.35, 0.12, 0.04)));
+    let rusty_steel = Rc::new(MixMaterial::new(steel, rust, 1.0).with_texture(Rc::new(Rust)));
+    world.add(Box::new(Disk::new(
+        Vec3::new(-2.5, 1.2, -0.58),
+        Vec3::new(0.974, 0.0, 0.225),
+        2.2,
+        Rc::new(AlphaMask::new(rusty_steel, Rc::new(Lattice)).with_cutoff(0.5)),
+    )));
+    let materials: [Rc<dyn Material>; 4] = [car_paint, velvet, anodized, bubble];
+    // 沿垂直于视线的方向排成一排
+    let across = Vec3::new(0.225, 0.0, -0.974);
+    for (i, material) in materials.iter().enumerate() {
+        let center = Vec3::new(0.0, 0.7, 0.0) + (i as Float - 1.5) * 1.6 * across;
+        world.add(Box::new(Sphere::new(center, 0.7, Rc::clone(material))));
+    }
+    world.build()
+}
+
+/// 曲线场景：一个长满毛的球放在草地上，毛发是带Kajiya-Kay材质的圆管，草叶是扁平的带子
+pub fn hair_scene() -> Scene {
+    let mut rng = rand::thread_rng();
+    let mut world = Scene::builder();
+    world.add(Box::new(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Rc::new(Lambertian::new(&Vec3::new(0.3, 0.25, 0.2))),
+    )));
+
+    let center = Vec3::new(0.0, 1.0, 0.0);
+    let radius = 0.8;
+    world.add(Box::new(Sphere::new(
+        center,
+        radius,
+        Rc::new(Lambertian::new(&Vec3::new(0.3, 0.15, 0.05))),
+    )));
+    let fur: Rc<dyn Material> = Rc::new(Hair::new(
+        Vec3::new(0.45, 0.25, 0.1),
+        Vec3::new(0.3, 0.3, 0.3),
+        40.0,
+    ));
+    for _ in 0..8000 {
+        let normal = random_in_uint_sphere().unit_vector();
+        let root = center + radius * normal;
+        // 毛发沿法向长出，越往外越被重力拉弯
+        let length = rng.gen_range(0.2, 0.35);
+        let points: Vec<Vec3> = (0..4)
+            .map(|k| {
+                let s = k as Float / 3.0;
+                root + length * s * normal - 0.3 * length * s * s * Vec3::new(0.0, 1.0, 0.0)
+            })
+            .collect();
+        for curve in Curve::bspline_strand(&points, 0.012, 0.002, CurveType::Tube, Rc::clone(&fur))
+        {
+            world.add(Box::new(curve));
+        }
+    }
+
+    let grass: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.2, 0.45, 0.1)));
+    for _ in 0..6000 {
+        let root = Vec3::new(rng.gen_range(-3.0, 6.0), 0.0, rng.gen_range(-3.5, 3.5));
+        if (root - Vec3::new(0.0, 0.0, 0.0)).length() < 0.5 {
+            continue;
+        }
+        let height = rng.gen_range(0.15, 0.4);
+        let lean = Vec3::new(rng.gen_range(-1.0, 1.0), 0.0, rng.gen_range(-1.0, 1.0));
+        world.add(Box::new(Curve::new(
+            [
+                root,
+                root + Vec3::new(0.0, 0.5 * height, 0.0),
+                root + Vec3::new(0.0, height, 0.0) + 0.2 * height * lean,
+                root + Vec3::new(0.0, 1.1 * height, 0.0) + 0.5 * height * lean,
+            ],
+            0.03,
+            0.002,
+            Rc::clone(&grass),
+        )));
+    }
+    world.build()
+}
+
+/// 封闭的房间，只有天花板下的一盏小灯，地上的玻璃球把灯光汇聚成焦散，适合用双向路径追踪渲染
+pub fn room_scene() -> Scene {
+    let mut world = Scene::builder();
+    let white: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.75, 0.75, 0.75)));
+    let red: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.65, 0.1, 0.08)));
+    let green: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.15, 0.5, 0.12)));
+    let walls = [
+        (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), &white),
+        (Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), &white),
+        (Vec3::new(-3.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), &white),
+        (Vec3::new(16.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), &white),
+        (Vec3::new(0.0, 0.0, -6.0), Vec3::new(0.0, 0.0, 1.0), &red),
+        (Vec3::new(0.0, 0.0, 6.0), Vec3::new(0.0, 0.0, -1.0), &green),
+    ];
+    for (point, normal, material) in walls.iter() {
+        world.add(Box::new(Plane::new(*point, *normal, Rc::clone(material))));
+    }
+
+    world.add_light(Rc::new(Sphere::new(
+        Vec3::new(-1.0, 3.8, -1.5),
+        0.2,
+        Rc::new(DiffuseLight::new(Vec3::new(200.0, 180.0, 150.0))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        Rc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(1.2, 0.5, 1.8),
+        0.5,
+        Rc::new(Metal::new(&Vec3::new(0.8, 0.8, 0.8), 0.0)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(-1.0, 0.7, -2.6),
+        0.7,
+        Rc::new(Lambertian::new(&Vec3::new(0.2, 0.3, 0.7))),
+    )));
+    world.build()
+}
+
+/// 适合光谱模式的场景：阳光穿过色散很强的三棱镜，在地面上投出彩虹，
+/// 旁边是用黑体辐射定义的两盏不同色温的灯和用反射光谱定义的漫反射球
+pub fn prism_scene() -> Scene {
+    let mut world = Scene::builder();
+    world.add(Box::new(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Rc::new(Lambertian::from_spectrum(Spectrum::Constant(0.8))),
+    )));
+    world.distant_light(DistantLight::new(
+        Vec3::new(1.0, -0.4, 0.0),
+        Vec3::new(8.0, 8.0, 8.0),
+        5.0,
+    ));
+
+    // 截面是等边三角形、沿z轴伸长的三棱镜
+    let mut prism = Mesh::default();
+    for &z in &[-1.5, 1.5] {
+        prism.positions.push(Vec3::new(-0.8, 0.0, z));
+        prism.positions.push(Vec3::new(0.8, 0.0, z));
+        prism.positions.push(Vec3::new(0.0, 1.4, z));
+    }
+    prism.indices = vec![
+        [0, 2, 1],
+        [3, 4, 5],
+        [0, 1, 4],
+        [0, 4, 3],
+        [1, 2, 5],
+        [1, 5, 4],
+        [2, 0, 3],
+        [2, 3, 5],
+    ];
+    world.add(Box::new(TriangleMesh::new(
+        prism,
+        Rc::new(Dielectric::new(1.6).with_abbe_number(20.0)),
+    )));
+
+    for &(center, kelvin) in &[
+        (Vec3::new(-2.5, 0.4, 2.5), 2700.0),
+        (Vec3::new(-2.5, 0.4, -2.5), 9000.0),
+    ] {
+        world.add_light(Rc::new(Sphere::new(
+            center,
+            0.4,
+            Rc::new(DiffuseLight::spectral(Spectrum::Blackbody(kelvin), 4.0)),
+        )));
+    }
+    // 窄带的反射光谱：纯正的黄色和青色
+    let bands = [
+        (Vec3::new(2.0, 0.5, -2.2), 560.0, 600.0),
+        (Vec3::new(2.5, 0.5, 2.0), 470.0, 510.0),
+    ];
+    for &(center, low, high) in bands.iter() {
+        let spectrum = Spectrum::PiecewiseLinear(vec![
+            (low - 10.0, 0.05),
+            (low, 0.9),
+            (high, 0.9),
+            (high + 10.0, 0.05),
+        ]);
+        world.add(Box::new(Sphere::new(
+            center,
+            0.5,
+            Rc::new(Lambertian::from_spectrum(spectrum)),
+        )));
+    }
+    world.build()
+}
+
+/// 积分器测试共用的小场景
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// 地面上方的球形面光源照着地面上的玻璃球，还可以继续往里面添加物体
+    pub fn lit_glass_sphere() -> SceneBuilder {
+        let mut builder = Scene::builder();
+        builder.add(Box::new(Plane::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+            Rc::new(Lambertian::new(&Vec3::new(0.6, 0.6, 0.6))),
+        )));
+        builder.add_light(Rc::new(Sphere::new(
+            Vec3::new(0.0, 2.5, 0.0),
+            0.5,
+            Rc::new(DiffuseLight::new(Vec3::new(40.0, 40.0, 40.0))),
+        )));
+        builder.add(Box::new(Sphere::new(
+            Vec3::new(0.0, 0.5, 0.0),
+            0.5,
+            Rc::new(Dielectric::new(1.5)),
+        )));
+        builder
+    }
+
+    /// 六面墙围成4×4×4的房间，里面只有一盏灯和一个玻璃球，天空完全照不进来
+    ///
+    /// 比`room_scene`小，灯也大得多，少量的采样就能收敛
+    pub fn closed_room() -> Scene {
+        let mut builder = Scene::builder();
+        let white: Rc<dyn Material> = Rc::new(Lambertian::new(&Vec3::new(0.7, 0.7, 0.7)));
+        for (point, normal) in &[
+            (Vec3::zero(), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.0, 4.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(-2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(2.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+            (Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0)),
+        ] {
+            builder.add(Box::new(Plane::new(*point, *normal, Rc::clone(&white))));
+        }
+        builder.add_light(Rc::new(Sphere::new(
+            Vec3::new(0.0, 3.0, 0.0),
+            0.5,
+            Rc::new(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0))),
+        )));
+        builder.add(Box::new(Sphere::new(
+            Vec3::new(0.5, 0.5, 0.0),
+            0.5,
+            Rc::new(Dielectric::new(1.5)),
+        )));
+        builder.build()
+    }
+
+    /// 两个估计的差不超过参考值长度的`tolerance`倍
+    pub fn assert_close(expected: Vec3, actual: Vec3, tolerance: Float) {
+        assert!(
+            (actual - expected).length() < tolerance * expected.length(),
+            "{:?} {:?}",
+            expected,
+            actual
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load_mesh_fits_in_front_of_glass_sphere() {
+        let stl = "solid tri
+  facet normal 0 0 1
+    outer loop
+      vertex 10 5 0
+      vertex 14 5 0
+      vertex 10 9 0
+    endloop
+  endfacet
+endsolid tri
+";
+        let path = std::env::temp_dir().join(format!("load-mesh-{}.stl", std::process::id()));
+        std::fs::write(&path, stl).unwrap();
+        let mesh = load_mesh(path.to_str().unwrap(), &MeshOptions::default(), None);
+        std::fs::remove_file(&path).unwrap();
+        // 高2个单位，底面在地上，水平方向的中心在(0, 2.5)
+        let bbox = mesh.unwrap().bounding_box().unwrap();
+        assert!((bbox.min() - Vec3::new(-1.0, 0.0, 2.5)).length() < 1e-3);
+        assert!((bbox.max() - Vec3::new(1.0, 2.0, 2.5)).length() < 1e-3);
+
+        assert!(load_mesh("missing.ply", &MeshOptions::default(), None).is_err());
+    }
+}
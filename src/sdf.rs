@@ -0,0 +1,544 @@
+//! 有向距离场（SDF）表示的物体，用球面追踪（sphere tracing）求交
+//!
+//! 距离函数在物体外为正、内部为负，绝对值不超过到表面的真实距离，
+//! 射线每次前进当前点的距离值就不会穿过表面。
+//! 函数值不是距离的隐式曲面用`ImplicitSurface`，按固定步长求交
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Float, Vec3};
+use std::rc::Rc;
+
+/// 有向距离函数
+pub trait Sdf {
+    fn distance(&self, p: &Vec3) -> Float;
+
+    /// 包含整个物体的包围盒，射线只在包围盒内步进
+    fn bounding_box(&self) -> Aabb;
+}
+
+fn map(v: &Vec3, f: impl Fn(Float) -> Float) -> Vec3 {
+    Vec3::new(f(v.x()), f(v.y()), f(v.z()))
+}
+
+fn expand(bbox: &Aabb, amount: Float) -> Aabb {
+    let pad = Vec3::new(amount, amount, amount);
+    Aabb::new(bbox.min() - pad, bbox.max() + pad)
+}
+
+/// 用四面体的四个顶点做中心差分估计梯度，返回梯度方向
+fn gradient_normal(f: impl Fn(&Vec3) -> Float, p: &Vec3, h: Float) -> Vec3 {
+    let offsets = [
+        Vec3::new(1.0, -1.0, -1.0),
+        Vec3::new(-1.0, -1.0, 1.0),
+        Vec3::new(-1.0, 1.0, -1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    ];
+    let mut gradient = Vec3::zero();
+    for k in offsets.iter() {
+        gradient += f(&(p + h * k)) * *k;
+    }
+    gradient.unit_vector()
+}
+
+fn mix(a: Float, b: Float, t: Float) -> Float {
+    a + (b - a) * t
+}
+
+pub struct SdfSphere {
+    center: Vec3,
+    radius: Float,
+}
+
+impl SdfSphere {
+    pub fn new(center: Vec3, radius: Float) -> Self {
+        SdfSphere { center, radius }
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: &Vec3) -> Float {
+        (p - self.center).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        expand(&Aabb::new(self.center, self.center), self.radius)
+    }
+}
+
+/// 轴对齐的长方体，`rounding`大于0时棱角变成圆角
+pub struct SdfBox {
+    center: Vec3,
+    half_size: Vec3,
+    rounding: Float,
+}
+
+impl SdfBox {
+    pub fn new(center: Vec3, half_size: Vec3) -> Self {
+        SdfBox {
+            center,
+            half_size,
+            rounding: 0.0,
+        }
+    }
+
+    /// 圆角半径，长方体的外轮廓保持不变
+    pub fn with_rounding(mut self, rounding: Float) -> Self {
+        self.rounding = rounding;
+        self
+    }
+}
+
+impl Sdf for SdfBox {
+    fn distance(&self, p: &Vec3) -> Float {
+        let r = self.rounding;
+        let q = map(&(p - self.center), Float::abs) - self.half_size + Vec3::new(r, r, r);
+        let outside = map(&q, |x| x.max(0.0)).length();
+        let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+        outside + inside - r
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(self.center - self.half_size, self.center + self.half_size)
+    }
+}
+
+/// 对称轴为`y`轴方向的圆环
+pub struct SdfTorus {
+    center: Vec3,
+    major_radius: Float,
+    minor_radius: Float,
+}
+
+impl SdfTorus {
+    pub fn new(center: Vec3, major_radius: Float, minor_radius: Float) -> Self {
+        SdfTorus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, p: &Vec3) -> Float {
+        let d = p - self.center;
+        let ring = (d.x() * d.x() + d.z() * d.z()).sqrt() - self.major_radius;
+        (ring * ring + d.y() * d.y()).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = self.major_radius + self.minor_radius;
+        let half = Vec3::new(r, self.minor_radius, r);
+        Aabb::new(self.center - half, self.center + half)
+    }
+}
+
+/// 两端是半球的圆柱
+pub struct SdfCapsule {
+    a: Vec3,
+    b: Vec3,
+    radius: Float,
+}
+
+impl SdfCapsule {
+    pub fn new(a: Vec3, b: Vec3, radius: Float) -> Self {
+        SdfCapsule { a, b, radius }
+    }
+}
+
+impl Sdf for SdfCapsule {
+    fn distance(&self, p: &Vec3) -> Float {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = (pa.dot(&ba) / ba.squared_length()).clamp(0.0, 1.0);
+        (pa - h * ba).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        expand(
+            &Aabb::new(self.a, self.a).surrounding(&Aabb::new(self.b, self.b)),
+            self.radius,
+        )
+    }
+}
+
+/// 平滑并集，两个物体在距离`k`以内融合成一体
+pub struct SmoothUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: Float,
+}
+
+impl SmoothUnion {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: Float) -> Self {
+        SmoothUnion { a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &Vec3) -> Float {
+        let (d1, d2) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        mix(d2, d1, h) - self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // 融合处最多向外鼓出k/4
+        expand(
+            &self.a.bounding_box().surrounding(&self.b.bounding_box()),
+            0.25 * self.k,
+        )
+    }
+}
+
+/// 平滑差集，从`a`中挖去`b`，边缘在距离`k`以内过渡
+pub struct SmoothSubtraction {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: Float,
+}
+
+impl SmoothSubtraction {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: Float) -> Self {
+        SmoothSubtraction { a, b, k }
+    }
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: &Vec3) -> Float {
+        let (d1, d2) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 - 0.5 * (d2 + d1) / self.k).clamp(0.0, 1.0);
+        mix(d1, -d2, h) + self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.a.bounding_box()
+    }
+}
+
+/// 域重复：把以原点为中心的物体按`spacing`的间隔复制，
+/// 每个方向上从`-count`到`count`，共`(2·count+1)³`份
+///
+/// 物体不应超出自己的格子，否则相邻格子之间的距离会估计错误
+pub struct Repeat {
+    sdf: Box<dyn Sdf>,
+    spacing: Vec3,
+    count: [Float; 3],
+}
+
+impl Repeat {
+    pub fn new(sdf: Box<dyn Sdf>, spacing: Vec3, count: [u32; 3]) -> Self {
+        Repeat {
+            sdf,
+            spacing,
+            count: [count[0] as Float, count[1] as Float, count[2] as Float],
+        }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: &Vec3) -> Float {
+        let cell = |x: Float, s: Float, n: Float| {
+            if s > 0.0 {
+                x - s * (x / s).round().clamp(-n, n)
+            } else {
+                x
+            }
+        };
+        let s = &self.spacing;
+        let q = Vec3::new(
+            cell(p.x(), s.x(), self.count[0]),
+            cell(p.y(), s.y(), self.count[1]),
+            cell(p.z(), s.z(), self.count[2]),
+        );
+        self.sdf.distance(&q)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.sdf.bounding_box();
+        let offset = Vec3::new(
+            self.spacing.x() * self.count[0],
+            self.spacing.y() * self.count[1],
+            self.spacing.z() * self.count[2],
+        );
+        Aabb::new(bbox.min() - offset, bbox.max() + offset)
+    }
+}
+
+/// Mandelbulb分形，用距离估计`0.5·ln(r)·r/dr`
+pub struct Mandelbulb {
+    center: Vec3,
+    scale: Float,
+    power: Float,
+    iterations: u32,
+}
+
+impl Mandelbulb {
+    /// `scale`是分形的大致半径，经典的形状`power`为8
+    pub fn new(center: Vec3, scale: Float, power: Float) -> Self {
+        Mandelbulb {
+            center,
+            scale,
+            power,
+            iterations: 12,
+        }
+    }
+
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, p: &Vec3) -> Float {
+        let c = (p - self.center) / self.scale;
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if !(1e-6..=2.0).contains(&r) {
+                break;
+            }
+            // 在球坐标下做z^power + c
+            let theta = (z.y() / r).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.z().atan2(z.x()) * self.power;
+            dr = self.power * r.powf(self.power - 1.0) * dr + 1.0;
+            let zr = r.powf(self.power);
+            z =
+                zr * Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ) + c;
+            r = z.length();
+        }
+        if r < 1e-6 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        expand(&Aabb::new(self.center, self.center), 1.2 * self.scale)
+    }
+}
+
+/// 用球面追踪渲染的有向距离场物体
+pub struct SdfShape {
+    sdf: Box<dyn Sdf>,
+    material: Rc<dyn Material>,
+    max_steps: u32,
+    epsilon: Float,
+}
+
+impl SdfShape {
+    pub fn new(sdf: Box<dyn Sdf>, material: Rc<dyn Material>) -> Self {
+        SdfShape {
+            sdf,
+            material,
+            max_steps: 256,
+            epsilon: 1e-4,
+        }
+    }
+
+    /// 一条射线最多步进的次数，超过后认为没有相交
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// 距离小于`epsilon`时认为到达表面，同时也是估计法向的差分步长
+    pub fn with_epsilon(mut self, epsilon: Float) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+}
+
+impl Hittable for SdfShape {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let (t_start, t_end) = self.bounding_box()?.hit_interval(ray, t_min, t_max)?;
+        let speed = ray.direction().length();
+        let mut t = t_start;
+        // 起点在物体内部时（例如折射进入玻璃）沿着负的距离步进，找离开的位置
+        let sign = self.sdf.distance(&ray.point_at_parameter(&t)).signum();
+        for _ in 0..self.max_steps {
+            let point = ray.point_at_parameter(&t);
+            let d = sign * self.sdf.distance(&point);
+            if d < self.epsilon {
+                if t <= t_min {
+                    // 射线从表面上出发，不算和自己相交
+                    t += self.epsilon / speed;
+                    continue;
+                }
+                let normal = gradient_normal(|q| self.sdf.distance(q), &point, self.epsilon);
+                return Some(HitRecord::new(
+                    t,
+                    point,
+                    normal,
+                    Rc::clone(&self.material),
+                    ray,
+                ));
+            }
+            t += d / speed;
+            if t >= t_end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(expand(&self.sdf.bounding_box(), 1e-3))
+    }
+}
+
+/// 隐式曲面`f(p) = 0`，`f`在物体内部为负，例如地形可以写成`p.y - height(p.x, p.z)`
+///
+/// `f`不要求是距离，所以只能按固定步长前进，发现符号变化后再二分求出交点
+pub struct ImplicitSurface {
+    function: Box<dyn Fn(&Vec3) -> Float>,
+    bounds: Aabb,
+    material: Rc<dyn Material>,
+    step: Float,
+}
+
+impl ImplicitSurface {
+    /// 射线只在`bounds`内寻找交点，步长默认是包围盒最长边的1/256
+    pub fn new(
+        function: impl Fn(&Vec3) -> Float + 'static,
+        bounds: Aabb,
+        material: Rc<dyn Material>,
+    ) -> Self {
+        let extent = bounds.max() - bounds.min();
+        let step = extent.x().max(extent.y()).max(extent.z()) / 256.0;
+        ImplicitSurface {
+            function: Box::new(function),
+            bounds,
+            material,
+            step,
+        }
+    }
+
+    /// 步进的距离，比步长更薄的部分可能被漏掉
+    pub fn with_step(mut self, step: Float) -> Self {
+        self.step = step;
+        self
+    }
+}
+
+impl Hittable for ImplicitSurface {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let (t_start, t_end) = self.bounds.hit_interval(ray, t_min, t_max)?;
+        let f = |t: Float| (self.function)(&ray.point_at_parameter(&t));
+        let dt = self.step / ray.direction().length();
+        let mut t0 = t_start;
+        let mut f0 = f(t0);
+        while t0 < t_end {
+            let t1 = (t0 + dt).min(t_end);
+            let f1 = f(t1);
+            if (f0 < 0.0) != (f1 < 0.0) {
+                let (mut a, mut b) = (t0, t1);
+                for _ in 0..24 {
+                    let mid = 0.5 * (a + b);
+                    if (f(mid) < 0.0) == (f0 < 0.0) {
+                        a = mid;
+                    } else {
+                        b = mid;
+                    }
+                }
+                let t = 0.5 * (a + b);
+                let point = ray.point_at_parameter(&t);
+                let normal = gradient_normal(&self.function, &point, 1e-2 * self.step);
+                return Some(HitRecord::new(
+                    t,
+                    point,
+                    normal,
+                    Rc::clone(&self.material),
+                    ray,
+                ));
+            }
+            t0 = t1;
+            f0 = f1;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn shape(sdf: Box<dyn Sdf>) -> SdfShape {
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        SdfShape::new(sdf, material)
+    }
+
+    #[test]
+    fn test_sphere_tracing_matches_sphere() {
+        let sphere = shape(Box::new(SdfSphere::new(Vec3::zero(), 1.0)));
+        let ray = Ray::new(Vec3::new(0.3, 0.2, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        let hit = sphere.hit(&ray, 0.001, Float::MAX).unwrap();
+        let expected = Vec3::new(0.3, 0.2, (1.0 - 0.13 as Float).sqrt());
+        assert!((hit.point - expected).length() < 1e-3);
+        assert!((hit.normal - expected).length() < 1e-3 && hit.front_face);
+
+        // 从内部射出
+        let inside = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        let hit = sphere.hit(&inside, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-3 && !hit.front_face);
+
+        let miss = Ray::new(Vec3::new(1.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&miss, 0.001, Float::MAX).is_none());
+    }
+
+    #[test]
+    fn test_operators() {
+        let blob = SmoothUnion::new(
+            Box::new(SdfSphere::new(Vec3::new(-0.6, 0.0, 0.0), 0.5)),
+            Box::new(SdfSphere::new(Vec3::new(0.6, 0.0, 0.0), 0.5)),
+            0.5,
+        );
+        // 两个球之间本来有空隙，平滑并集把它填上了
+        assert!(blob.distance(&Vec3::zero()) < 0.0);
+
+        let carved = SmoothSubtraction::new(
+            Box::new(SdfBox::new(Vec3::zero(), Vec3::new(1.0, 1.0, 1.0))),
+            Box::new(SdfSphere::new(Vec3::zero(), 0.5)),
+            0.1,
+        );
+        assert!(carved.distance(&Vec3::zero()) > 0.0);
+        assert!(carved.distance(&Vec3::new(0.8, 0.0, 0.0)) < 0.0);
+
+        let grid = Repeat::new(
+            Box::new(SdfSphere::new(Vec3::zero(), 0.2)),
+            Vec3::new(1.0, 0.0, 1.0),
+            [2, 0, 2],
+        );
+        assert!((grid.distance(&Vec3::new(2.0, 0.0, -2.0)) + 0.2).abs() < 1e-5);
+        // 只有5×5份，第三格之外没有复制
+        assert!((grid.distance(&Vec3::new(3.0, 0.0, 0.0)) - 0.8).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_implicit_surface() {
+        // |p|² - 1不是距离函数，但零点仍然是单位球
+        let material = Rc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)));
+        let bounds = Aabb::new(Vec3::new(-2.0, -2.0, -2.0), Vec3::new(2.0, 2.0, 2.0));
+        let surface = ImplicitSurface::new(|p| p.squared_length() - 1.0, bounds, material);
+        let ray = Ray::new(Vec3::new(0.6, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = surface.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.t - 4.2).abs() < 1e-4);
+        assert!((hit.normal - Vec3::new(0.6, 0.0, 0.8)).length() < 1e-3 && hit.front_face);
+        let miss = Ray::new(Vec3::new(1.2, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(surface.hit(&miss, 0.001, Float::MAX).is_none());
+    }
+}
@@ -0,0 +1,363 @@
+//! 光谱渲染：每条路径同时追踪几个波长，最后通过CIE XYZ颜色匹配函数转换成sRGB
+//!
+//! 采用主波长采样（hero wavelength）：主波长在可见光范围内均匀抽样，其余几个波长等间隔地错开，
+//! 色散这类只能沿一个波长继续的散射会丢弃其余的波长。用RGB定义的材质和光源按Smits的方法转换成光谱
+
+use crate::vec3::{Float, Vec3};
+use std::ops::{Add, AddAssign, Mul};
+use std::sync::OnceLock;
+
+/// 可见光的波长范围，单位为纳米
+pub const LAMBDA_MIN: Float = 380.0;
+pub const LAMBDA_MAX: Float = 780.0;
+
+/// 每条路径同时追踪的波长个数
+pub const SAMPLES: usize = 4;
+
+/// 一条路径上的几个波长和各自的概率密度
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledWavelengths {
+    lambda: [Float; SAMPLES],
+    pdf: [Float; SAMPLES],
+}
+
+impl SampledWavelengths {
+    /// 由`[0, 1)`上的随机数`u`抽样主波长，其余波长在范围内等间隔地循环错开
+    pub fn sample_uniform(u: Float) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let t = (u + i as Float / SAMPLES as Float).fract();
+            *l = LAMBDA_MIN + t * range;
+        }
+        SampledWavelengths {
+            lambda,
+            pdf: [1.0 / range; SAMPLES],
+        }
+    }
+
+    pub fn lambda(&self, i: usize) -> Float {
+        self.lambda[i]
+    }
+
+    /// 主波长
+    pub fn hero(&self) -> Float {
+        self.lambda[0]
+    }
+
+    /// 只保留主波长，用于色散这类出射方向随波长变化的散射，重复调用没有影响
+    pub fn terminate_secondary(&mut self) {
+        if self.is_terminated() {
+            return;
+        }
+        for pdf in self.pdf[1..].iter_mut() {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SAMPLES as Float;
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    /// 把各个波长上的辐射度转换成线性sRGB，等能光谱（常数1）对应白色`(1, 1, 1)`
+    pub fn to_rgb(&self, spectrum: &SampledSpectrum) -> Vec3 {
+        let mut xyz = Vec3::zero();
+        for i in 0..SAMPLES {
+            if self.pdf[i] > 0.0 {
+                xyz += spectrum.values[i] / self.pdf[i] * cie_xyz(self.lambda[i]);
+            }
+        }
+        xyz_to_rgb(xyz / SAMPLES as Float)
+    }
+}
+
+/// 几个波长上的光谱值，和`SampledWavelengths`一一对应
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledSpectrum {
+    values: [Float; SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(values: [Float; SAMPLES]) -> Self {
+        SampledSpectrum { values }
+    }
+
+    pub fn constant(value: Float) -> Self {
+        SampledSpectrum {
+            values: [value; SAMPLES],
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::constant(0.0)
+    }
+
+    /// 按Smits的方法把RGB颜色转换成光谱，在给定的波长上取值
+    pub fn from_rgb(rgb: Vec3, wavelengths: &SampledWavelengths) -> Self {
+        let mut values = [0.0; SAMPLES];
+        for (i, v) in values.iter_mut().enumerate() {
+            *v = rgb_to_spectrum(rgb, wavelengths.lambda[i]);
+        }
+        SampledSpectrum { values }
+    }
+
+    pub fn value(&self, i: usize) -> Float {
+        self.values[i]
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut values = self.values;
+        for (v, o) in values.iter_mut().zip(other.values.iter()) {
+            *v += o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut values = self.values;
+        for (v, o) in values.iter_mut().zip(other.values.iter()) {
+            *v *= o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul<SampledSpectrum> for Float {
+    type Output = SampledSpectrum;
+
+    fn mul(self, spectrum: SampledSpectrum) -> SampledSpectrum {
+        let mut values = spectrum.values;
+        for v in values.iter_mut() {
+            *v *= self;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+/// 用光谱定义的颜色，例如光源的发射光谱和材质的反射光谱
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spectrum {
+    Constant(Float),
+    /// 按波长从小到大排列的`(波长, 值)`，之间线性插值，范围之外取端点的值
+    PiecewiseLinear(Vec<(Float, Float)>),
+    /// 色温为给定开尔文的黑体辐射，按峰值归一化
+    Blackbody(Float),
+    /// 按Smits的方法由RGB转换而来
+    Rgb(Vec3),
+}
+
+impl Spectrum {
+    /// 波长`lambda`（纳米）处的值
+    pub fn value(&self, lambda: Float) -> Float {
+        match self {
+            Spectrum::Constant(c) => *c,
+            Spectrum::PiecewiseLinear(samples) => interpolate(samples, lambda),
+            Spectrum::Blackbody(kelvin) => {
+                // 维恩位移定律给出峰值所在的波长
+                let peak = 2.897_772e-3 / kelvin * 1e9;
+                planck(lambda, *kelvin) / planck(peak, *kelvin)
+            }
+            Spectrum::Rgb(rgb) => rgb_to_spectrum(*rgb, lambda),
+        }
+    }
+
+    pub fn sample(&self, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+        let mut values = [0.0; SAMPLES];
+        for (i, v) in values.iter_mut().enumerate() {
+            *v = self.value(wavelengths.lambda[i]);
+        }
+        SampledSpectrum { values }
+    }
+
+    /// 在可见光范围内积分得到线性sRGB，用于RGB渲染模式
+    pub fn to_rgb(&self) -> Vec3 {
+        let mut xyz = Vec3::zero();
+        let mut lambda = LAMBDA_MIN + 0.5;
+        while lambda < LAMBDA_MAX {
+            xyz += self.value(lambda) * cie_xyz(lambda);
+            lambda += 1.0;
+        }
+        xyz_to_rgb(xyz)
+    }
+}
+
+fn interpolate(samples: &[(Float, Float)], lambda: Float) -> Float {
+    match samples.iter().position(|&(l, _)| l > lambda) {
+        None => samples.last().map_or(0.0, |&(_, v)| v),
+        Some(0) => samples[0].1,
+        Some(i) => {
+            let (l0, v0) = samples[i - 1];
+            let (l1, v1) = samples[i];
+            v0 + (v1 - v0) * (lambda - l0) / (l1 - l0)
+        }
+    }
+}
+
+/// 普朗克黑体辐射公式，波长单位为纳米，省略了常数系数
+fn planck(lambda: Float, kelvin: Float) -> Float {
+    const C2: f64 = 1.438_776_877e-2;
+    let l = lambda as f64 * 1e-9;
+    (1.0 / (l.powi(5) * ((C2 / (l * kelvin as f64)).exp() - 1.0))) as Float
+}
+
+/// CIE 1931颜色匹配函数的多峰高斯拟合（Wyman、Sloan和Shirley 2013）
+pub fn cie_xyz(lambda: Float) -> Vec3 {
+    let g = |mu: Float, sigma1: Float, sigma2: Float| {
+        let sigma = if lambda < mu { sigma1 } else { sigma2 };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// 等能光谱的XYZ积分值转换成的sRGB，用来把白色归一化为`(1, 1, 1)`
+fn white_balance() -> Vec3 {
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let mut xyz = Vec3::zero();
+        let mut lambda = LAMBDA_MIN + 0.5;
+        while lambda < LAMBDA_MAX {
+            xyz += cie_xyz(lambda);
+            lambda += 1.0;
+        }
+        xyz_to_srgb(xyz)
+    })
+}
+
+fn xyz_to_srgb(xyz: Vec3) -> Vec3 {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Vec3::new(
+        3.240_454 * x - 1.537_138 * y - 0.498_531 * z,
+        -0.969_266 * x + 1.876_011 * y + 0.041_556 * z,
+        0.055_643 * x - 0.204_026 * y + 1.057_225 * z,
+    )
+}
+
+/// 对波长积分得到的XYZ转换成线性sRGB，按等能白归一化
+fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    let rgb = xyz_to_srgb(xyz);
+    let white = white_balance();
+    Vec3::new(
+        rgb.x() / white.x(),
+        rgb.y() / white.y(),
+        rgb.z() / white.z(),
+    )
+}
+
+/// Smits（1999）的基础光谱，在380nm到720nm之间等间隔的10个点上取值
+const SMITS_WHITE: [Float; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [Float; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [Float; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [Float; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [Float; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [Float; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [Float; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// RGB颜色对应的光谱在波长`lambda`处的值：先用白色表示三个分量的公共部分，
+/// 再用青、品红、黄表示两个分量的公共部分，最后用红、绿、蓝表示剩下的
+pub fn rgb_to_spectrum(rgb: Vec3, lambda: Float) -> Float {
+    let t = ((lambda - 380.0) / (720.0 - 380.0) * 9.0).clamp(0.0, 9.0);
+    let i = (t as usize).min(8);
+    let f = t - i as Float;
+    let at = |table: &[Float; 10]| table[i] + f * (table[i + 1] - table[i]);
+    let (r, g, b) = (rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0));
+    if r <= g && r <= b {
+        r * at(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * at(&SMITS_CYAN) + (b - g) * at(&SMITS_BLUE)
+            } else {
+                (b - r) * at(&SMITS_CYAN) + (g - b) * at(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * at(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * at(&SMITS_MAGENTA) + (b - r) * at(&SMITS_BLUE)
+            } else {
+                (b - g) * at(&SMITS_MAGENTA) + (r - b) * at(&SMITS_RED)
+            }
+    } else {
+        b * at(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * at(&SMITS_YELLOW) + (g - r) * at(&SMITS_GREEN)
+            } else {
+                (g - b) * at(&SMITS_YELLOW) + (r - g) * at(&SMITS_RED)
+            }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_rgb_round_trip_and_white() {
+        for &rgb in &[
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.8, 0.3, 0.2),
+            Vec3::new(0.2, 0.5, 0.7),
+            Vec3::new(0.4, 0.6, 0.3),
+        ] {
+            let back = Spectrum::Rgb(rgb).to_rgb();
+            assert!((back - rgb).length() < 0.06, "{:?} -> {:?}", rgb, back);
+        }
+
+        // 随机抽样的波长估计出的白色收敛到(1, 1, 1)
+        let mut rng = rand::thread_rng();
+        let n = 20_000;
+        let mut sum = Vec3::zero();
+        for _ in 0..n {
+            let mut wavelengths = SampledWavelengths::sample_uniform(rng.gen_range(0.0, 1.0));
+            if rng.gen_range(0.0, 1.0) < 0.5 {
+                wavelengths.terminate_secondary();
+                wavelengths.terminate_secondary();
+            }
+            sum += wavelengths.to_rgb(&SampledSpectrum::constant(1.0));
+        }
+        let mean = sum / n as Float;
+        assert!(
+            (mean - Vec3::new(1.0, 1.0, 1.0)).length() < 0.05,
+            "{:?}",
+            mean
+        );
+
+        // 低色温偏红，高色温偏蓝
+        let warm = Spectrum::Blackbody(2000.0).to_rgb();
+        let cool = Spectrum::Blackbody(12000.0).to_rgb();
+        assert!(warm.x() > warm.y() && warm.y() > warm.z());
+        assert!(cool.z() > cool.x());
+    }
+}
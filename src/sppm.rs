@@ -0,0 +1,396 @@
+//! 随机渐进光子映射（Hachisuka和Jensen 2009）
+//!
+//! 每一轮先从每个像素发出一条摄像机路径，穿过镜面和只能用`scatter`抽样的表面，
+//! 停在第一个能用`Material::bsdf`求值的表面上作为可见点。路上看到的自发光和背景，
+//! 以及在可见点对光源抽样得到的直接光照直接累加。
+//! 然后从光源发射光子，光子在第一次反射之后每碰到能求值的表面就记录一次，存进kd树，
+//! 每个可见点收集半径内的光子来估计间接光照。收集的半径一轮比一轮小，结果是一致的。
+//!
+//! 光源的抽样见`light`模块，没有加进光源列表的发光物体只在直接看到或被镜面反射时有贡献
+
+use crate::aabb::Aabb;
+use crate::aov::{material_key, AovBuffers, AovSample};
+use crate::camera::Camera;
+use crate::hittable::HitRecord;
+use crate::kdtree::KdTree;
+use crate::light;
+use crate::ray::Ray;
+use crate::render::{camera_ray, RenderSettings};
+use crate::sampler;
+use crate::scene::Scene;
+use crate::vec3::{Float, Vec3};
+use rand::Rng;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// 每一轮新收集到的光子保留的比例，决定半径缩小的速度
+const ALPHA: Float = 2.0 / 3.0;
+
+/// 记录在表面上的光子
+struct Photon {
+    /// 指向光子来的方向的单位向量
+    wi: Vec3,
+    /// 携带的功率，已经除以了抽样的概率密度
+    power: Vec3,
+}
+
+/// 摄像机路径停下的地方
+struct VisiblePoint {
+    hit_record: HitRecord,
+    wo: Vec3,
+    /// 摄像机到这一点路径的贡献
+    beta: Vec3,
+}
+
+/// 每个像素在各轮之间累积的统计量
+#[derive(Clone)]
+struct PixelStats {
+    /// 直接看到的光和直接光照之和
+    direct: Vec3,
+    radius: Float,
+    /// 收集到的光子数，每轮只保留新光子的`ALPHA`
+    photons: Float,
+    /// 半径内光子贡献之和，随半径缩小按面积比例缩放
+    flux: Vec3,
+}
+
+impl PixelStats {
+    /// `iterations`轮之后的估计，每轮发射`photons_per_iteration`个光子
+    fn estimate(&self, iterations: usize, photons_per_iteration: usize) -> (Vec3, Vec3) {
+        let direct = self.direct / iterations as Float;
+        let emitted = (iterations * photons_per_iteration) as Float;
+        let indirect = self.flux / (emitted * PI * self.radius * self.radius);
+        (direct, indirect)
+    }
+}
+
+/// 随机渐进光子映射，`max_depth`为摄像机路径和光子最多的反射次数
+pub struct PhotonMapping<'a> {
+    scene: &'a Scene,
+    max_depth: i32,
+    photons_per_iteration: Option<usize>,
+    initial_radius: Option<Float>,
+}
+
+impl<'a> PhotonMapping<'a> {
+    pub fn new(scene: &'a Scene, max_depth: i32) -> Self {
+        PhotonMapping {
+            scene,
+            max_depth,
+            photons_per_iteration: None,
+            initial_radius: None,
+        }
+    }
+
+    /// 每一轮发射的光子数，默认和像素数相同
+    pub fn with_photons(mut self, photons: usize) -> Self {
+        self.photons_per_iteration = Some(photons);
+        self
+    }
+
+    /// 第一轮收集光子的半径，默认为第一轮所有可见点的包围盒对角线长度的1%，
+    /// 这样墙壁之类无限大的物体也算在场景的尺度里
+    pub fn with_initial_radius(mut self, radius: Float) -> Self {
+        self.initial_radius = Some(radius);
+        self
+    }
+
+    /// 渲染整幅图像，每个像素的采样数作为迭代的轮数
+    pub fn render(
+        &self,
+        camera: &dyn Camera,
+        settings: &RenderSettings,
+    ) -> (Vec<Vec3>, AovBuffers) {
+        let (width, height) = (settings.width as usize, settings.height as usize);
+        let iterations = settings.samples_per_pixel.max(1) as usize;
+        let photons_per_iteration = self.photons_per_iteration.unwrap_or(width * height);
+        let (open, close) = settings.shutter;
+        let mut rng = sampler::rng();
+        let mut stats = vec![
+            PixelStats {
+                direct: Vec3::zero(),
+                radius: 0.0,
+                photons: 0.0,
+                flux: Vec3::zero(),
+            };
+            width * height
+        ];
+        let mut aov_buffers = AovBuffers::new(width, height);
+
+        for iteration in 1..=iterations {
+            let mut visible_points = Vec::with_capacity(width * height);
+            for (i, pixel) in stats.iter_mut().enumerate() {
+                let (x, y) = ((i % width) as u32, (i / width) as u32);
+                let mut aov = AovSample::miss(Vec3::zero());
+                let visible_point = camera_ray(camera, settings, x, y).and_then(|ray| {
+                    let (direct, visible_point) = self.camera_path(ray, &mut aov);
+                    pixel.direct += direct;
+                    visible_point
+                });
+                visible_points.push((visible_point, aov));
+            }
+            if iteration == 1 {
+                let radius = self
+                    .initial_radius
+                    .unwrap_or_else(|| self.default_radius(&visible_points));
+                for pixel in stats.iter_mut() {
+                    pixel.radius = radius;
+                }
+            }
+
+            let photons = (0..photons_per_iteration)
+                .flat_map(|_| {
+                    let time = open + (close - open) * rng.gen_range(0.0, 1.0);
+                    self.trace_photon(time)
+                })
+                .collect();
+            let photons = KdTree::new(photons);
+
+            for (i, (visible_point, mut aov)) in visible_points.into_iter().enumerate() {
+                let pixel = &mut stats[i];
+                if let Some(VisiblePoint {
+                    hit_record,
+                    wo,
+                    beta,
+                }) = visible_point
+                {
+                    gather(&photons, &hit_record, &wo, &beta, pixel);
+                }
+                aov.indirect = pixel.estimate(iteration, photons_per_iteration).1;
+                aov_buffers.add(i % width, i / width, &aov);
+            }
+        }
+
+        let pixels = stats
+            .iter()
+            .map(|pixel| {
+                let (direct, indirect) = pixel.estimate(iterations, photons_per_iteration);
+                direct + indirect
+            })
+            .collect();
+        (pixels, aov_buffers)
+    }
+
+    /// 可见点的包围盒对角线长度的1%，没有可见点时用有限大的物体的包围球
+    fn default_radius(&self, visible_points: &[(Option<VisiblePoint>, AovSample)]) -> Float {
+        visible_points
+            .iter()
+            .filter_map(|(visible_point, _)| visible_point.as_ref())
+            .map(|v| Aabb::new(v.hit_record.point, v.hit_record.point))
+            .reduce(|a, b| a.surrounding(&b))
+            .map(|bbox| (bbox.max() - bbox.min()).length())
+            .filter(|&diagonal| diagonal > 0.0)
+            .map_or_else(|| 0.02 * self.scene.bounding_sphere().1, |d| 0.01 * d)
+    }
+
+    /// 追踪摄像机路径直到可见点，返回路上看到的光和可见点的直接光照
+    fn camera_path(&self, mut ray: Ray, aov: &mut AovSample) -> (Vec3, Option<VisiblePoint>) {
+        let mut beta = Vec3::new(1.0, 1.0, 1.0);
+        let mut radiance = Vec3::zero();
+        for depth in 0..=self.max_depth {
+            let hit_record = match self.scene.hit(&ray, 0.001, Float::MAX) {
+                Some(hit_record) => hit_record,
+                None => {
+                    let background = self.scene.background(&ray);
+                    if depth == 0 {
+                        *aov = AovSample::miss(background);
+                    }
+                    radiance += beta * background;
+                    break;
+                }
+            };
+            let material = Rc::clone(&hit_record.material);
+            radiance += beta * material.emitted(&ray, &hit_record);
+            let wo = -ray.direction().unit_vector();
+            let visible = !material.is_specular(&hit_record)
+                && material.bsdf(&hit_record, &wo, &wo).is_some();
+            let direct = if visible {
+                self.direct_lighting(&hit_record, &wo, ray.time())
+            } else {
+                Vec3::zero()
+            };
+            if depth == 0 {
+                *aov = AovSample {
+                    albedo: material.albedo(&hit_record),
+                    normal: hit_record.normal,
+                    depth: hit_record.t * ray.direction().length(),
+                    position: hit_record.point,
+                    material_key: material_key(&hit_record.material),
+                    object_id: hit_record.object_id,
+                    direct: radiance + direct,
+                    indirect: Vec3::zero(),
+                };
+            }
+            if visible {
+                radiance += beta * direct;
+                let visible_point = VisiblePoint {
+                    hit_record,
+                    wo,
+                    beta,
+                };
+                return (radiance, Some(visible_point));
+            }
+            match material.scatter(&ray, &hit_record) {
+                Some((attenuation, scattered)) => {
+                    beta = beta * attenuation;
+                    ray = scattered;
+                }
+                None => break,
+            }
+        }
+        (radiance, None)
+    }
+
+    /// 在可见点对光源抽样一次估计直接光照
+    fn direct_lighting(&self, hit_record: &HitRecord, wo: &Vec3, time: Float) -> Vec3 {
+        let sample = match light::sample_incident(self.scene, &hit_record.point) {
+            Some(sample) if sample.pdf > 0.0 => sample,
+            _ => return Vec3::zero(),
+        };
+        if !light::visible(self.scene, &hit_record.point, &sample, time) {
+            return Vec3::zero();
+        }
+        match hit_record.material.bsdf(hit_record, wo, &sample.direction) {
+            Some((f, _)) => {
+                let cos = hit_record.normal.dot(&sample.direction).abs();
+                f * sample.radiance * (cos / sample.pdf)
+            }
+            None => Vec3::zero(),
+        }
+    }
+
+    /// 从光源发射一个光子，返回它在第一次反射之后留在各个表面上的记录
+    fn trace_photon(&self, time: Float) -> Vec<(Vec3, Photon)> {
+        let mut rng = sampler::rng();
+        let mut photons = Vec::new();
+        let emission = match light::sample_emission(self.scene, time) {
+            Some(emission) if emission.pdf_direction > 0.0 => emission,
+            _ => return photons,
+        };
+        let mut power = emission.power();
+        let mut ray = emission.ray;
+        for depth in 0..self.max_depth {
+            let hit_record = match self.scene.hit(&ray, 0.001, Float::MAX) {
+                Some(hit_record) => hit_record,
+                None => break,
+            };
+            let material = Rc::clone(&hit_record.material);
+            let wi = -ray.direction().unit_vector();
+            // 第一次击中的光子是直接光照，已经在可见点对光源抽样时算过
+            if depth > 0
+                && !material.is_specular(&hit_record)
+                && material.bsdf(&hit_record, &wi, &wi).is_some()
+            {
+                photons.push((hit_record.point, Photon { wi, power }));
+            }
+            let (attenuation, scattered) = match material.scatter(&ray, &hit_record) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+            power = power * attenuation;
+            // 俄罗斯轮盘，反射率低的光子更早被吸收
+            if depth > 3 {
+                let q = attenuation
+                    .x()
+                    .max(attenuation.y())
+                    .max(attenuation.z())
+                    .min(0.95);
+                if rng.gen_range(0.0, 1.0) >= q {
+                    break;
+                }
+                power /= q;
+            }
+            ray = scattered;
+        }
+        photons
+    }
+}
+
+/// 收集可见点附近的光子，按渐进光子映射的规则更新半径和累积的贡献
+fn gather(
+    photons: &KdTree<Photon>,
+    hit_record: &HitRecord,
+    wo: &Vec3,
+    beta: &Vec3,
+    pixel: &mut PixelStats,
+) {
+    let mut count = 0;
+    let mut flux = Vec3::zero();
+    photons.for_each_within(&hit_record.point, pixel.radius, |_, photon| {
+        if let Some((f, _)) = hit_record.material.bsdf(hit_record, wo, &photon.wi) {
+            flux += f * photon.power;
+        }
+        count += 1;
+    });
+    if count == 0 {
+        return;
+    }
+    let count = count as Float;
+    let photons = pixel.photons + ALPHA * count;
+    let radius = pixel.radius * (photons / (pixel.photons + count)).sqrt();
+    let shrink = (radius / pixel.radius).powi(2);
+    pixel.flux = (pixel.flux + *beta * flux) * shrink;
+    pixel.photons = photons;
+    pixel.radius = radius;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::PerspectiveCamera;
+    use crate::render::ray_color;
+    use crate::scene::fixtures::{assert_close, closed_room, lit_glass_sphere};
+
+    /// 在4×4的小图上比较光子映射和路径追踪的平均值，
+    /// 视角很窄，默认的半径会太小，所以指定初始半径
+    fn assert_matches_path_tracing(scene: &Scene, lookfrom: Vec3, lookat: Vec3) {
+        let camera = PerspectiveCamera::new(
+            &lookfrom,
+            &lookat,
+            &Vec3::new(0.0, 1.0, 0.0),
+            5.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let settings = RenderSettings {
+            width: 4,
+            height: 4,
+            samples_per_pixel: 64,
+            max_depth: 10,
+            ..RenderSettings::default()
+        };
+        let (pixels, _) = PhotonMapping::new(scene, 10)
+            .with_photons(4000)
+            .with_initial_radius(0.1)
+            .render(&camera, &settings);
+        let actual = pixels.iter().fold(Vec3::zero(), |a, &b| a + b) / pixels.len() as Float;
+
+        let n = 20_000;
+        let mut expected = Vec3::zero();
+        for i in 0..n {
+            let ray = camera_ray(&camera, &settings, i % 4, (i / 4) % 4).unwrap();
+            expected += ray_color(&ray, scene, 10, None);
+        }
+        assert_close(expected / n as Float, actual, 0.15);
+    }
+
+    #[test]
+    fn test_matches_path_tracing() {
+        // 看向玻璃球旁边有焦散和间接光照的一小块地面
+        assert_matches_path_tracing(
+            &lit_glass_sphere().build(),
+            Vec3::new(0.0, 1.0, 4.0),
+            Vec3::new(0.3, 0.0, 0.9),
+        );
+    }
+
+    #[test]
+    fn test_enclosed_scene_matches_path_tracing() {
+        assert_matches_path_tracing(
+            &closed_room(),
+            Vec3::new(0.0, 1.5, 1.8),
+            Vec3::new(-0.5, 0.0, 0.0),
+        );
+    }
+}
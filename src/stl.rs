@@ -0,0 +1,128 @@
+//! 读取STL网格（ASCII和二进制）
+//!
+//! STL的每个三角形单独保存三个顶点，没有共享顶点也没有顶点法向，渲染出来是平直着色
+
+use crate::mesh::Mesh;
+use crate::vec3::{Float, Vec3};
+use std::io;
+use std::path::Path;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// 二进制STL：80字节的头，三角形个数，每个三角形50字节
+fn parse_binary(bytes: &[u8]) -> Option<Mesh> {
+    let count = u32::from_le_bytes([
+        *bytes.get(80)?,
+        *bytes.get(81)?,
+        *bytes.get(82)?,
+        *bytes.get(83)?,
+    ]) as usize;
+    if bytes.len() != 84 + 50 * count {
+        return None;
+    }
+    let float = |b: &[u8], i: usize| {
+        f32::from_le_bytes([b[4 * i], b[4 * i + 1], b[4 * i + 2], b[4 * i + 3]]) as Float
+    };
+    let mut mesh = Mesh::default();
+    mesh.positions.reserve(3 * count);
+    mesh.indices.reserve(count);
+    for facet in bytes[84..].chunks_exact(50) {
+        // 跳过面法向，最后两个字节是属性
+        let n = mesh.positions.len();
+        for v in 1..4 {
+            mesh.positions.push(Vec3::new(
+                float(facet, 3 * v),
+                float(facet, 3 * v + 1),
+                float(facet, 3 * v + 2),
+            ));
+        }
+        mesh.indices.push([n, n + 1, n + 2]);
+    }
+    Some(mesh)
+}
+
+fn parse_ascii(bytes: &[u8]) -> io::Result<Mesh> {
+    let text =
+        std::str::from_utf8(bytes).map_err(|_| invalid("STL file is not ASCII".to_string()))?;
+    let mut tokens = text.split_ascii_whitespace();
+    let mut mesh = Mesh::default();
+    let mut facet = Vec::with_capacity(3);
+    while let Some(token) = tokens.next() {
+        match token {
+            "vertex" => {
+                let mut p = [0.0; 3];
+                for x in p.iter_mut() {
+                    let value = tokens
+                        .next()
+                        .ok_or_else(|| invalid("unexpected end of STL".to_string()))?;
+                    *x = value
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid STL coordinate {}", value)))?;
+                }
+                facet.push(mesh.positions.len());
+                mesh.positions.push(Vec3::new(p[0], p[1], p[2]));
+            }
+            "endfacet" => {
+                if facet.len() != 3 {
+                    return Err(invalid(format!("STL facet has {} vertices", facet.len())));
+                }
+                mesh.indices.push([facet[0], facet[1], facet[2]]);
+                facet.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+/// 从内存中解析STL文件
+///
+/// 有些二进制文件的头也以`solid`开头，所以先按二进制的长度校验，不符合再按ASCII解析
+pub fn parse(bytes: &[u8]) -> io::Result<Mesh> {
+    match parse_binary(bytes) {
+        Some(mesh) => Ok(mesh),
+        None if bytes.starts_with(b"solid") => parse_ascii(bytes),
+        None => Err(invalid("invalid binary STL size".to_string())),
+    }
+}
+
+/// 读取STL文件
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Mesh> {
+    parse(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ascii_and_binary() {
+        let ascii = b"solid tri
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid tri
+";
+        let mesh = parse(ascii).unwrap();
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[1], Vec3::new(1.0, 0.0, 0.0));
+
+        let mut binary = b"solid but actually binary".to_vec();
+        binary.resize(80, 0);
+        binary.extend_from_slice(&1u32.to_le_bytes());
+        for x in &[
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend_from_slice(&x.to_le_bytes());
+        }
+        binary.extend_from_slice(&[0, 0]);
+        let mesh = parse(&binary).unwrap();
+        assert_eq!(mesh.positions, parse(ascii).unwrap().positions);
+    }
+}
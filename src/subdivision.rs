@@ -0,0 +1,203 @@
+//! Loop细分曲面：每次把一个三角形分成四个，新顶点按周围顶点加权平均，多次细分后收敛到光滑曲面
+//!
+//! 网格在读取时已经全部三角化，所以用针对三角形的Loop细分而不是针对四边形的Catmull–Clark细分
+
+use crate::mesh::Mesh;
+use crate::vec3::{Float, Vec3};
+use std::collections::HashMap;
+
+/// 细分`levels`次，每次三角形个数变为4倍
+///
+/// 位置按Loop规则平滑，开放的边界按边界规则只和边界上的点平均；
+/// 纹理坐标和顶点颜色线性插值。细分后法向重新计算，切线被丢弃
+pub fn loop_subdivide(mesh: &Mesh, levels: u32) -> Mesh {
+    let mut mesh = mesh.clone();
+    for _ in 0..levels {
+        mesh = subdivide_once(&mesh);
+    }
+    if levels > 0 {
+        mesh.compute_normals();
+    }
+    mesh
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn subdivide_once(mesh: &Mesh) -> Mesh {
+    // 拓扑按焊接后的顶点计算，这样纹理接缝两侧的位置保持一致
+    let (ids, count) = mesh.weld();
+    let mut welded = vec![Vec3::zero(); count];
+    for (i, p) in mesh.positions.iter().enumerate() {
+        welded[ids[i]] = *p;
+    }
+    // 每条边对面的顶点
+    let mut opposite: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for &[a, b, c] in &mesh.indices {
+        let (a, b, c) = (ids[a], ids[b], ids[c]);
+        for &(x, y, z) in &[(a, b, c), (b, c, a), (c, a, b)] {
+            opposite.entry(edge_key(x, y)).or_default().push(z);
+        }
+    }
+    let mut neighbors = vec![Vec::new(); count];
+    let mut boundary_neighbors = vec![Vec::new(); count];
+    for (&(a, b), faces) in &opposite {
+        neighbors[a].push(b);
+        neighbors[b].push(a);
+        if faces.len() == 1 {
+            boundary_neighbors[a].push(b);
+            boundary_neighbors[b].push(a);
+        }
+    }
+
+    // 原有顶点的新位置
+    let even: Vec<Vec3> = (0..count)
+        .map(|v| {
+            let p = welded[v];
+            let boundary = &boundary_neighbors[v];
+            if !boundary.is_empty() {
+                return if boundary.len() == 2 {
+                    0.75 * p + 0.125 * (welded[boundary[0]] + welded[boundary[1]])
+                } else {
+                    // 非流形的角点保持不动
+                    p
+                };
+            }
+            let n = neighbors[v].len();
+            if n == 0 {
+                return p;
+            }
+            let beta = if n == 3 {
+                3.0 / 16.0
+            } else {
+                3.0 / (8.0 * n as Float)
+            };
+            let sum = neighbors[v]
+                .iter()
+                .fold(Vec3::zero(), |sum, &u| sum + welded[u]);
+            (1.0 - n as Float * beta) * p + beta * sum
+        })
+        .collect();
+
+    let mut result = Mesh {
+        positions: mesh.positions.iter().map(|_| Vec3::zero()).collect(),
+        uvs: mesh.uvs.clone(),
+        colors: mesh.colors.clone(),
+        ..Mesh::default()
+    };
+    for (i, p) in result.positions.iter_mut().enumerate() {
+        *p = even[ids[i]];
+    }
+
+    // 边上的新顶点按原始下标区分，接缝两侧各有一个，属性分别插值
+    let mut odd: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut edge_vertex = |a: usize, b: usize, result: &mut Mesh| -> usize {
+        *odd.entry(edge_key(a, b)).or_insert_with(|| {
+            let (wa, wb) = (ids[a], ids[b]);
+            let faces = &opposite[&edge_key(wa, wb)];
+            let p = if faces.len() == 2 {
+                0.375 * (welded[wa] + welded[wb]) + 0.125 * (welded[faces[0]] + welded[faces[1]])
+            } else {
+                0.5 * (welded[wa] + welded[wb])
+            };
+            result.positions.push(p);
+            if !mesh.uvs.is_empty() {
+                let (ua, ub) = (mesh.uvs[a], mesh.uvs[b]);
+                result
+                    .uvs
+                    .push([0.5 * (ua[0] + ub[0]), 0.5 * (ua[1] + ub[1])]);
+            }
+            if !mesh.colors.is_empty() {
+                result.colors.push(0.5 * (mesh.colors[a] + mesh.colors[b]));
+            }
+            result.positions.len() - 1
+        })
+    };
+    for &[a, b, c] in &mesh.indices {
+        let ab = edge_vertex(a, b, &mut result);
+        let bc = edge_vertex(b, c, &mut result);
+        let ca = edge_vertex(c, a, &mut result);
+        result.indices.push([a, ab, ca]);
+        result.indices.push([ab, b, bc]);
+        result.indices.push([ca, bc, c]);
+        result.indices.push([ab, bc, ca]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::texture::SolidColor;
+
+    /// 正八面体，顶点都在单位球上
+    fn octahedron() -> Mesh {
+        Mesh {
+            positions: vec![
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, -1.0),
+            ],
+            indices: vec![
+                [0, 2, 4],
+                [4, 2, 1],
+                [1, 2, 5],
+                [5, 2, 0],
+                [4, 3, 0],
+                [1, 3, 4],
+                [5, 3, 1],
+                [0, 3, 5],
+            ],
+            ..Mesh::default()
+        }
+    }
+
+    #[test]
+    fn test_loop_subdivision_smooths_octahedron() {
+        let mesh = loop_subdivide(&octahedron(), 3);
+        assert_eq!(mesh.indices.len(), 8 * 64);
+        // 封闭网格：V - E + F = 2，E = 3F / 2
+        assert_eq!(mesh.positions.len(), 2 + mesh.indices.len() / 2);
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+        // 细分后向内收缩，半径的变化范围比八面体本身（1/√3到1）小得多
+        let radii: Vec<Float> = mesh.positions.iter().map(|p| p.length()).collect();
+        let min = radii.iter().cloned().fold(Float::MAX, Float::min);
+        let max = radii.iter().cloned().fold(Float::MIN, Float::max);
+        assert!(max - min < 0.1, "radius varies from {} to {}", min, max);
+        // 法向朝外
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!(p.unit_vector().dot(n) > 0.9);
+        }
+    }
+
+    #[test]
+    fn test_boundary_and_displacement() {
+        // 一个开放的正方形，边界上的点只沿边界平均，仍然留在平面上
+        let mut mesh = Mesh {
+            positions: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            indices: vec![[0, 2, 1], [0, 3, 2]],
+            uvs: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            ..Mesh::default()
+        };
+        mesh = loop_subdivide(&mesh, 2);
+        assert_eq!(mesh.uvs.len(), mesh.positions.len());
+        assert!(mesh.positions.iter().all(|p| p.y() == 0.0));
+        assert!(mesh.normals.iter().all(|n| (n.y() - 1.0).abs() < 1e-6));
+
+        mesh.displace(&SolidColor::new(Vec3::new(0.5, 0.5, 0.5)), 0.2);
+        assert!(mesh.positions.iter().all(|p| (p.y() - 0.1).abs() < 1e-6));
+    }
+}
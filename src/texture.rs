@@ -0,0 +1,160 @@
+//! 纹理：根据表面的纹理坐标查询颜色
+//!
+//! 纹理坐标`(u, v)`的原点在图片的左上角，`v`向下增大，和glTF的约定相同
+
+use crate::vec3::{Float, Vec3};
+use image::ImageResult;
+use std::path::Path;
+
+pub trait Texture {
+    /// 纹理在`(u, v)`处的值，`point`为交点的世界坐标，供程序纹理使用
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> Vec3;
+}
+
+/// 纯色
+pub struct SolidColor {
+    color: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3) -> Self {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: Float, _v: Float, _point: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+/// 纹理坐标超出`[0, 1]`时的处理方式
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl Wrap {
+    /// 把像素下标映射回`[0, size)`
+    fn apply(self, i: i64, size: usize) -> usize {
+        let n = size as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::MirroredRepeat => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+            Wrap::ClampToEdge => i.max(0).min(n - 1),
+        };
+        i as usize
+    }
+}
+
+/// 图片纹理，像素保存为线性空间的颜色，采样时做双线性插值
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+    wrap_u: Wrap,
+    wrap_v: Wrap,
+}
+
+/// sRGB编码的分量转换到线性空间
+pub fn srgb_to_linear(c: Float) -> Float {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl ImageTexture {
+    /// 由按行排列的像素构造，默认在两个方向上重复
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> Self {
+        assert_eq!(pixels.len(), width * height, "pixel count mismatch");
+        ImageTexture {
+            width,
+            height,
+            pixels,
+            wrap_u: Wrap::Repeat,
+            wrap_v: Wrap::Repeat,
+        }
+    }
+
+    /// 读取图片文件，`srgb`为真时把颜色从sRGB转换到线性空间，法线、粗糙度等数据纹理应为假
+    pub fn open<P: AsRef<Path>>(path: P, srgb: bool) -> ImageResult<Self> {
+        let img = image::open(path)?.into_rgb();
+        let decode = |c: u8| {
+            let c = c as Float / 255.0;
+            if srgb {
+                srgb_to_linear(c)
+            } else {
+                c
+            }
+        };
+        let pixels = img
+            .pixels()
+            .map(|p| Vec3::new(decode(p[0]), decode(p[1]), decode(p[2])))
+            .collect();
+        Ok(Self::new(
+            img.width() as usize,
+            img.height() as usize,
+            pixels,
+        ))
+    }
+
+    pub fn with_wrap(mut self, wrap_u: Wrap, wrap_v: Wrap) -> Self {
+        self.wrap_u = wrap_u;
+        self.wrap_v = wrap_v;
+        self
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let x = self.wrap_u.apply(x, self.width);
+        let y = self.wrap_v.apply(y, self.height);
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Float, v: Float, _point: &Vec3) -> Vec3 {
+        // 像素中心在半整数坐标上
+        let x = u * self.width as Float - 0.5;
+        let y = v * self.height as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+        let bottom = (1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bilinear_and_wrap() {
+        let black = Vec3::zero();
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let texture = ImageTexture::new(2, 1, vec![black, white]);
+        let p = Vec3::zero();
+        // 像素中心取到原值，两个像素之间取平均
+        assert_eq!(texture.value(0.25, 0.5, &p).x(), 0.0);
+        assert_eq!(texture.value(0.75, 0.5, &p).x(), 1.0);
+        assert!((texture.value(0.5, 0.5, &p).x() - 0.5).abs() < 1e-6);
+        // 重复模式下左边缘和右边的像素混合，截断模式下不混合
+        assert!((texture.value(0.0, 0.5, &p).x() - 0.5).abs() < 1e-6);
+        let clamped = ImageTexture::new(2, 1, vec![black, white])
+            .with_wrap(Wrap::ClampToEdge, Wrap::ClampToEdge);
+        assert_eq!(clamped.value(0.0, 0.5, &p).x(), 0.0);
+        assert_eq!(clamped.value(1.5, 0.5, &p).x(), 1.0);
+    }
+}
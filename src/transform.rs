@@ -0,0 +1,280 @@
+//! 仿射变换，以及把变换套在物体外面的`Transformed`
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::vec3::{Float, Vec3};
+
+/// 仿射变换：先做线性变换`matrix`，再平移`translation`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    matrix: [[Float; 3]; 3],
+    inverse: [[Float; 3]; 3],
+    translation: Vec3,
+}
+
+const IDENTITY: [[Float; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn mul_matrix(a: &[[Float; 3]; 3], b: &[[Float; 3]; 3]) -> [[Float; 3]; 3] {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn mul_vector(m: &[[Float; 3]; 3], v: &Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+        m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+        m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+    )
+}
+
+fn transpose(m: &[[Float; 3]; 3]) -> [[Float; 3]; 3] {
+    let mut t = [[0.0; 3]; 3];
+    for (i, row) in m.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            t[j][i] = *value;
+        }
+    }
+    t
+}
+
+/// 伴随矩阵除以行列式，行列式为0时返回`None`
+fn invert(m: &[[Float; 3]; 3]) -> Option<[[Float; 3]; 3]> {
+    let c = |i: usize, j: usize| {
+        let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+        let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+        m[i1][j1] * m[i2][j2] - m[i1][j2] * m[i2][j1]
+    };
+    let det = m[0][0] * c(0, 0) + m[0][1] * c(0, 1) + m[0][2] * c(0, 2);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut inv = [[0.0; 3]; 3];
+    for (i, row) in inv.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = c(j, i) / det;
+        }
+    }
+    Some(inv)
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            matrix: IDENTITY,
+            inverse: IDENTITY,
+            translation: Vec3::zero(),
+        }
+    }
+
+    /// 由按行排列的3×3矩阵和平移构造，矩阵不可逆时返回`None`
+    pub fn from_matrix(matrix: [[Float; 3]; 3], translation: Vec3) -> Option<Self> {
+        Some(Transform {
+            matrix,
+            inverse: invert(&matrix)?,
+            translation,
+        })
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        Transform {
+            translation: offset,
+            ..Self::identity()
+        }
+    }
+
+    /// 各轴分别缩放，缩放系数不能为0
+    pub fn scale(factor: Vec3) -> Self {
+        let m = [
+            [factor.x(), 0.0, 0.0],
+            [0.0, factor.y(), 0.0],
+            [0.0, 0.0, factor.z()],
+        ];
+        Self::from_matrix(m, Vec3::zero()).expect("scale factor must not be zero")
+    }
+
+    /// 绕过原点的`axis`轴旋转`degrees`度（右手定则）
+    pub fn rotate(axis: Vec3, degrees: Float) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let m = [
+            [
+                cos + x * x * (1.0 - cos),
+                x * y * (1.0 - cos) - z * sin,
+                x * z * (1.0 - cos) + y * sin,
+            ],
+            [
+                y * x * (1.0 - cos) + z * sin,
+                cos + y * y * (1.0 - cos),
+                y * z * (1.0 - cos) - x * sin,
+            ],
+            [
+                z * x * (1.0 - cos) - y * sin,
+                z * y * (1.0 - cos) + x * sin,
+                cos + z * z * (1.0 - cos),
+            ],
+        ];
+        Transform {
+            matrix: m,
+            inverse: transpose(&m),
+            translation: Vec3::zero(),
+        }
+    }
+
+    /// 按x、y、z的顺序依次绕各轴旋转，单位为度
+    pub fn rotate_euler(degrees: Vec3) -> Self {
+        Self::rotate(Vec3::new(1.0, 0.0, 0.0), degrees.x())
+            .then(&Self::rotate(Vec3::new(0.0, 1.0, 0.0), degrees.y()))
+            .then(&Self::rotate(Vec3::new(0.0, 0.0, 1.0), degrees.z()))
+    }
+
+    /// 先缩放，再旋转，最后平移
+    pub fn from_trs(translation: Vec3, rotation: Vec3, scale: Vec3) -> Self {
+        Self::scale(scale)
+            .then(&Self::rotate_euler(rotation))
+            .then(&Self::translate(translation))
+    }
+
+    /// 先做`self`再做`next`的复合变换
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: mul_matrix(&next.matrix, &self.matrix),
+            inverse: mul_matrix(&self.inverse, &next.inverse),
+            translation: mul_vector(&next.matrix, &self.translation) + next.translation,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+            translation: -mul_vector(&self.inverse, &self.translation),
+        }
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        mul_vector(&self.matrix, p) + self.translation
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        mul_vector(&self.matrix, v)
+    }
+
+    /// 法向要用逆矩阵的转置来变换，才能在非均匀缩放后仍垂直于表面
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        mul_vector(&transpose(&self.inverse), n)
+    }
+
+    /// 把射线变换到物体空间
+    pub fn inverse_ray(&self, ray: &Ray) -> Ray {
+        Ray::with_time(
+            mul_vector(&self.inverse, &(ray.origin() - self.translation)),
+            mul_vector(&self.inverse, ray.direction()),
+            ray.time(),
+        )
+    }
+
+    /// 包围盒八个顶点变换后的包围盒
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        let (min, max) = (bbox.min(), bbox.max());
+        let mut result: Option<Aabb> = None;
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { min.x() } else { max.x() },
+                if i & 2 == 0 { min.y() } else { max.y() },
+                if i & 4 == 0 { min.z() } else { max.z() },
+            );
+            let p = self.point(&corner);
+            let b = Aabb::new(p, p);
+            result = Some(result.map_or(b, |r| r.surrounding(&b)));
+        }
+        result.unwrap()
+    }
+
+    /// 把物体空间的碰撞记录变换回世界空间，`t`不变
+    pub fn hit_record(&self, mut hit_record: HitRecord) -> HitRecord {
+        hit_record.point = self.point(&hit_record.point);
+        hit_record.normal = self.normal(&hit_record.normal).unit_vector();
+        hit_record.geometric_normal = self.normal(&hit_record.geometric_normal).unit_vector();
+        hit_record.dpdu = self.vector(&hit_record.dpdu);
+        hit_record.dpdv = self.vector(&hit_record.dpdv);
+        hit_record
+    }
+}
+
+/// 施加了变换的物体，可以用来摆放同一个模型的多个实例
+pub struct Transformed {
+    object: Box<dyn Hittable>,
+    transform: Transform,
+}
+
+impl Transformed {
+    pub fn new(object: Box<dyn Hittable>, transform: Transform) -> Self {
+        Transformed { object, transform }
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let local = self.transform.inverse_ray(ray);
+        self.object
+            .hit(&local, t_min, t_max)
+            .map(|h| self.transform.hit_record(h))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object
+            .bounding_box()
+            .map(|bbox| self.transform.bounding_box(&bbox))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn test_rotate() {
+        let t = Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 90.0);
+        let p = t.point(&Vec3::new(1.0, 0.0, 0.0));
+        assert!(close(&p, &Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn test_compose_and_inverse() {
+        let t = Transform::from_trs(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(30.0, 45.0, 60.0),
+            Vec3::new(2.0, 0.5, 3.0),
+        );
+        let p = Vec3::new(-0.3, 0.7, 1.1);
+        assert!(close(&t.inverse().point(&t.point(&p)), &p));
+        let s = Transform::translate(Vec3::new(1.0, 0.0, 0.0))
+            .then(&Transform::scale(Vec3::new(2.0, 2.0, 2.0)));
+        assert!(close(&s.point(&Vec3::zero()), &Vec3::new(2.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_normal_stays_perpendicular() {
+        let t = Transform::scale(Vec3::new(1.0, 4.0, 1.0));
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+        assert!(t.vector(&tangent).dot(&t.normal(&normal)).abs() < 1e-5);
+    }
+}